@@ -1,11 +1,35 @@
 use std::fmt;
 use std::fmt::Formatter;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum DitError {
     NotInitialized,
     IoError(std::io::Error),
     UnexpectedComportement(String),
+    BranchExists(String),
+    BranchNotFound(String),
+    InvalidRefName(String),
+    TagExists(String),
+    UnknownRevision(String),
+    AmbiguousRevision(String),
+    ObjectNotFound { hash: String },
+    CorruptObject { hash: String, reason: String },
+    CorruptPack { path: PathBuf, reason: String },
+    CorruptRepository(usize),
+    UnsupportedRepositoryFormat(String),
+    OutdatedRepository(u32),
+    Locked { path: PathBuf, stale: bool },
+    NothingToCommit,
+    NoCommit,
+    OutsideRepository(PathBuf),
+    Conflict { paths: Vec<PathBuf> },
+    DirtyWorkTree,
+    EditorFailed(String),
+    MissingKey,
+    WrongKey,
+    DecryptionFailed(PathBuf),
+    UnverifiedSignature { name: String, reason: String },
 }
 
 impl fmt::Display for DitError {
@@ -14,6 +38,42 @@ impl fmt::Display for DitError {
             DitError::NotInitialized => write!(f, "Repository dit not initialized"),
             DitError::IoError(e) => write!(f, "IO error: {}", e),
             DitError::UnexpectedComportement(message) => write!(f, "{}", message),
+            DitError::BranchExists(name) => write!(f, "Branch {} already exists", name),
+            DitError::BranchNotFound(name) => write!(f, "Branch {} does not exist", name),
+            DitError::InvalidRefName(name) => write!(f, "Invalid branch name {}, it must not contain '/' or '\\' nor end with .lock", name),
+            DitError::TagExists(name) => write!(f, "Tag {} already exists", name),
+            DitError::UnknownRevision(revision) => write!(f, "Unknown revision {}", revision),
+            DitError::AmbiguousRevision(revision) => write!(f, "Revision {} is ambiguous", revision),
+            DitError::ObjectNotFound { hash } => write!(f, "Object {} not found", hash),
+            DitError::CorruptObject { hash, reason } => write!(f, "Object {} is corrupt: {}", hash, reason),
+            DitError::CorruptPack { path, reason } => write!(f, "Pack {} is corrupt: {}", path.display(), reason),
+            DitError::CorruptRepository(count) => write!(f, "{} missing or corrupt objects", count),
+            DitError::UnsupportedRepositoryFormat(format) => write!(f, "The repository uses {}, which this version of dit does not support", format),
+            DitError::Locked { path, stale: false } => {
+                write!(f, "Unable to create {}: another dit process seems to be running in this repository", path.display())
+            }
+            DitError::Locked { path, stale: true } => {
+                write!(f, "{} was left by a dit process which did not finish, remove it if no other dit process is running", path.display())
+            }
+            DitError::OutdatedRepository(version) => write!(f, "The repository uses layout version {}, run dit upgrade to update it", version),
+            DitError::NothingToCommit => write!(f, "Nothing to commit, stage elements first"),
+            DitError::NoCommit => write!(f, "No commit on the current branch"),
+            DitError::OutsideRepository(path) => write!(f, "{} is outside of the repository", path.display()),
+            DitError::Conflict { paths } => {
+                write!(f, "Conflict in:")?;
+                for path in paths {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+            DitError::DirtyWorkTree => write!(f, "Staged changes would be lost, commit them first"),
+            DitError::EditorFailed(message) => write!(f, "Editor failed: {}", message),
+            DitError::MissingKey => write!(f, "The repository is encrypted, give its key with DIT_PASSPHRASE or DIT_KEY_FILE"),
+            DitError::WrongKey => write!(f, "Wrong key for the encrypted repository"),
+            DitError::DecryptionFailed(path) => {
+                write!(f, "{} can not be decrypted: it was altered or encrypted with another key", path.display())
+            }
+            DitError::UnverifiedSignature { name, reason } => write!(f, "The signature of {} can not be verified: {}", name, reason),
         }
     }
 }
@@ -25,4 +85,4 @@ impl std::error::Error for DitError {
             _ => None,
         }
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,7 @@
 pub mod add;
 pub mod init;
 pub mod rm;
+pub mod restore;
 pub mod commit;
 pub mod delete;
 pub mod message;
@@ -9,4 +10,21 @@ pub mod revert;
 pub mod create_branch;
 pub mod checkout;
 pub mod merge;
-pub mod display_message;
\ No newline at end of file
+pub mod display_message;
+pub mod config;
+pub mod diff;
+pub mod log;
+pub mod mv;
+pub mod status;
+pub mod reflog;
+pub mod revision;
+pub mod output;
+pub mod tag;
+pub mod verify;
+pub mod cat_file;
+pub mod key;
+pub mod lfs;
+pub mod ls_tree;
+pub mod fsck;
+pub mod gc;
+pub mod upgrade;
@@ -1,27 +1,13 @@
 use std::path::PathBuf;
-use std::process;
-
-use repository_tree_creator::features::create_repository_tree as crt;
-use repository_tree_creator::features::get_repository_tree_from_object_files::get_repository_tree_from_object_files;
-use repository_tree_creator::features::transcript_repository_tree_to_object_files::transcript_repository_to_object_files;
-use repository_tree_creator::models::node::Node;
-use repository_tree_creator::models::tree::Tree;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_objects, find_staged, get_staged_hash, is_init};
-use crate::utils::{clean_path, NULL_HASH, write_hash_file};
-
-pub fn add(elements: Vec<&String>) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
-    }
-
-    let object_path = find_objects();
-    let staged_path = find_staged();
-    let staged_hash = get_staged_hash()?;
+use crate::objects::index::Index;
+use crate::objects::mode::Modes;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{clean_path, hash_working_files};
 
+pub fn add(repo: &Repository, elements: Vec<&String>) -> Result<(), DitError> {
     let new_elements: Vec<String> = elements.iter().map(|s| s.to_string()).collect();
 
     let new_elements: Vec<PathBuf> = new_elements
@@ -29,40 +15,40 @@ pub fn add(elements: Vec<&String>) -> Result<(), DitError> {
         .map(|p| PathBuf::from(p))
         .collect();
 
-    let new_elements = clean_path(new_elements)?;
+    let new_elements = clean_path(repo, new_elements)?;
 
     if new_elements.is_empty() {
-        display_message("You need to specify files to add.", Color::DEFAULT);
-    } else if staged_hash == NULL_HASH {
-        let tree: Tree = Default::default();
-        add_elements(new_elements, &object_path, &staged_path, tree)?;
-    } else {
-        let mut tree: Tree = Default::default();
-        get_repository_tree_from_object_files(&mut tree, &staged_hash, &object_path).map_err(|e| {
-            display_message("Error getting previous staged files", Color::RED);
-            DitError::UnexpectedComportement(format!("Details: {}", e))
-        })?;
-        add_elements(new_elements, &object_path, &staged_path, tree)?;
+        repo.report(Event::Warning(String::from("You need to specify files to add.")));
+        return Ok(());
     }
-    Ok(())
+    add_paths(repo, new_elements)
 }
 
-/// Add files to dit repository
-fn add_elements(
-    elements: Vec<PathBuf>,
-    object_path: &PathBuf,
-    staged_path: &PathBuf,
-    root: Tree,
-) -> Result<(), DitError> {
-    let root: Node = crt::create_repository_tree(root, elements).map_err(|e| {
-        display_message("Error creating repository tree", Color::RED);
-        DitError::UnexpectedComportement(format!("Details: {}", e))
-    })?;
-    transcript_repository_to_object_files(&root, &object_path).map_err(|e| {
-        display_message("Error saving repository tree", Color::RED);
-        DitError::UnexpectedComportement(format!("Details: {}", e))
-    })?;
+/// Stage files whose paths are already relative to the repository root: their blobs are
+/// written and their entries of the index updated. Files the index knows to be unchanged since
+/// they were staged or committed are skipped without being read. The modes of the entries are
+/// staged with them, symlinks and empty directories are only recorded in the staged modes.
+pub fn add_paths(repo: &Repository, new_elements: Vec<PathBuf>) -> Result<(), DitError> {
+    let modes = Modes::from_working(repo, &new_elements)?;
+    let mut staged_modes = Modes::load_staged(repo)?;
+    let previous_modes = staged_modes.clone();
+    staged_modes.overlay(&modes);
+    if staged_modes != previous_modes {
+        staged_modes.save_staged(repo)?;
+    }
+
+    let (mut index, lock) = Index::lock(repo)?;
+    let new_elements: Vec<PathBuf> = new_elements
+        .into_iter()
+        .filter(|path| !modes.get(path).is_special())
+        .filter(|path| index.get_cached_hash(repo, path).is_none())
+        .collect();
+    if new_elements.is_empty() {
+        return Ok(());
+    }
 
-    write_hash_file(root.get_id(), staged_path.clone(), 0).map_err(DitError::IoError)?;
-    Ok(())
+    for (path, hash) in hash_working_files(repo, &new_elements, true)? {
+        index.update(repo, path, hash)?;
+    }
+    index.save_to(repo, lock)
 }
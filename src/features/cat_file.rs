@@ -0,0 +1,37 @@
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::ls_tree::get_tree_entries;
+use crate::features::revision::resolve_revision;
+use crate::objects::object::{Object, ObjectKind};
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// What `cat-file` displays about an object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CatFileMode {
+    Type,
+    Size,
+    Pretty,
+}
+
+/// Display the type, the size or the content of the object designated by `revision`
+pub fn cat_file(repo: &Repository, revision: &str, mode: CatFileMode) -> Result<(), DitError> {
+    let hash = resolve_revision(repo, revision)?;
+    let object = Object::read(repo, &hash)?;
+
+    match mode {
+        CatFileMode::Type => repo.report(Event::Output(object.get_kind().to_string(), Color::DEFAULT)),
+        CatFileMode::Size => repo.report(Event::Output(object.get_size().to_string(), Color::DEFAULT)),
+        CatFileMode::Pretty if object.get_kind() == ObjectKind::Tree => {
+            for (kind, hash, path) in get_tree_entries(repo, &hash, false)? {
+                repo.report(Event::Output(format!("{} {}\t{}", kind, hash, path.display()), Color::DEFAULT));
+            }
+        }
+        CatFileMode::Pretty => {
+            for line in String::from_utf8_lossy(object.get_content()).lines() {
+                repo.report(Event::Output(line.to_string(), Color::DEFAULT));
+            }
+        }
+    }
+    Ok(())
+}
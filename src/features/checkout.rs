@@ -1,35 +1,28 @@
-use std::process;
-
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_refs, get_head_hash, is_init};
+use crate::features::init::get_head_hash;
+use crate::features::status::has_staged_files;
 use crate::objects::branch::Branch;
 use crate::objects::commit::Commit;
-use crate::utils::NULL_HASH;
+use crate::objects::lock::Transaction;
+use crate::objects::reflog::record_head_movement;
+use crate::repository::Repository;
 
-pub fn checkout(name: &String) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+pub fn checkout(repo: &Repository, name: &String) -> Result<(), DitError> {
+    let mut transaction = Transaction::new(repo);
+    transaction.lock(repo.get_info_path())?;
+    let branch = Branch::get_branch(repo, name.clone())?;
+    if has_staged_files(repo)? {
+        return Err(DitError::DirtyWorkTree);
     }
 
-    let branch_path = find_refs().join(name.to_owned());
-
-    if branch_path.exists() {
-        Commit::get_commit_from_file(get_head_hash()?).map_err(DitError::IoError)?;
+    let old_head = get_head_hash(repo)?;
+    let current_branch = Branch::get_current_branch(repo)?;
+    let reason = format!("checkout: moving from {} to {}", current_branch.get_name(), name);
 
-        let branch_commits = Commit::get_commit_list(name.to_string()).map_err(DitError::IoError)?;
-
-        match branch_commits.last() {
-            Some(commit) => {
-                commit.recreate_files()?;
-                Branch::set_info_file(name.clone(), commit.get_hash().clone()).map_err(DitError::IoError)?;
-            }
-            None => {
-                Branch::set_info_file(name.clone(), NULL_HASH.to_string()).map_err(DitError::IoError)?;
-            }
-        }
+    if !repo.get_object_format().is_null_hash(branch.get_head()) {
+        Commit::get_commit_from_file(repo, branch.get_head().clone())?.recreate_files(repo)?;
     }
-
-    Ok(())
+    Branch::write_info_to(&mut transaction, name, branch.get_head())?;
+    record_head_movement(&mut transaction, &old_head, branch.get_head(), reason.as_str())?;
+    transaction.commit()
 }
\ No newline at end of file
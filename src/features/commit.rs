@@ -1,123 +1,197 @@
+use std::fs;
 use std::path::PathBuf;
-use std::process;
 use std::process::Command;
 
-use repository_tree_creator::features::get_repository_tree_from_object_files::get_repository_tree_from_object_files;
-use repository_tree_creator::features::merge_repository_trees::{merge_repository_trees, Mode};
-use repository_tree_creator::features::remove_element_from_repository_tree::remove_element_from_repository_tree;
-use repository_tree_creator::features::transcript_repository_tree_to_object_files::transcript_repository_to_object_files;
-use repository_tree_creator::models::node::Node;
-use repository_tree_creator::models::node::Node::TreeNode;
-use repository_tree_creator::models::tree::Tree;
-
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_dit, get_staged_hash, is_init};
+use crate::features::message::{message, read_message};
+use crate::features::status::{get_head_blobs, get_head_modes, get_next_commit_blobs, get_next_commit_modes};
 use crate::objects::commit::Commit;
-use crate::utils::{NULL_HASH, path_from_dit, read_content_from_non_encrypted_file};
-
-use super::delete::get_deleted_elements;
+use crate::objects::config::{Config, COMMIT_SIGN};
+use crate::objects::index::Index;
+use crate::objects::mode::Modes;
+use crate::objects::reflog::now;
+use crate::objects::store::save_tree;
+use crate::objects::tree::{create_tree, Tree};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{get_indexed_working_blobs, get_tree_blobs, ScratchDir};
+
+use super::add::add_paths;
+use super::delete::delete_paths;
 use super::init::get_head_hash;
 
-pub fn commit(desc_already_set: bool) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+pub fn commit(repo: &Repository, desc_already_set: bool) -> Result<(), DitError> {
+    let blobs = get_next_commit_blobs(repo)?;
+    let modes = get_next_commit_modes(repo)?;
+    let mut head_blobs = get_head_blobs(repo)?;
+    head_blobs.sort();
+
+    if blobs == head_blobs && modes == get_head_modes(repo)? {
+        return Err(DitError::NothingToCommit);
     }
+    if !desc_already_set {
+        edit_description(repo)?;
+    }
+    let description = read_message(repo)?;
 
-    let dit_path = find_dit().unwrap();
-    let desc_path = dit_path.join("commit");
-    let objects_path = dit_path.join("objects");
-    let staged_hash = get_staged_hash()?;
+    let last_commit_hash = get_head_hash(repo)?;
+    let reason = if repo.get_object_format().is_null_hash(&last_commit_hash) { "commit (initial)" } else { "commit" };
+    let tree_hash = save_commit_tree(repo, &blobs)?;
+    create_commit(repo, description, last_commit_hash, String::new(), tree_hash, &modes, reason)
+}
 
-    if staged_hash == NULL_HASH {
-        display_message("You need to stage elements before commiting", Color::BLUE);
-        return Ok(());
-    } else if is_first_commit()? {
-        if !desc_already_set {
-            Command::new("vim")
-                .arg(desc_path.clone())
-                .spawn()
-                .expect("Failed to open nano")
-                .wait()
-                .expect("Error with running nano");
-        }
+/// Replace the HEAD commit with a new one built on the same parent.
+///
+/// The new tree is built from the files of the index. With `no_edit` the previous description
+/// is kept, otherwise the editor is opened on it.
+pub fn amend(repo: &Repository, desc_already_set: bool, no_edit: bool) -> Result<(), DitError> {
+    let last_commit_hash = get_head_hash(repo)?;
 
-        let description = read_content_from_non_encrypted_file(&desc_path.as_path()).unwrap_or_default();
+    if repo.get_object_format().is_null_hash(&last_commit_hash) {
+        return Err(DitError::NoCommit);
+    }
 
-        create_commit(description, String::from(NULL_HASH), staged_hash)?;
+    let last_commit = Commit::get_commit_from_file(repo, last_commit_hash.clone())?;
+
+    let description = if no_edit {
+        last_commit.get_description().clone()
     } else {
         if !desc_already_set {
-            Command::new("vim")
-                .arg(desc_path.clone())
-                .spawn()
-                .expect("Failed to open vim")
-                .wait()
-                .expect("Error with running vim");
+            message(repo, last_commit.get_description().clone())?;
+            edit_description(repo)?;
         }
+        read_message(repo)?
+    };
 
-        let description = read_content_from_non_encrypted_file(&desc_path.as_path()).unwrap_or_default();
-        let last_commit_hash = get_head_hash()?;
-
-        let last_commit = Commit::get_commit_from_file(last_commit_hash.clone()).map_err(DitError::IoError)?;
-
-        let mut staged_tree = Tree::default();
-        get_repository_tree_from_object_files(&mut staged_tree, &staged_hash, &objects_path).map_err(|e| {
-            display_message("Error getting repository files from objects directory", Color::RED);
-            DitError::UnexpectedComportement(format!("Error details: {}", e))
-        })?;
-        staged_tree.set_id(staged_hash);
-        let mut staged_root = TreeNode(staged_tree);
-
-        let mut last_commit_tree = Tree::default();
-        get_repository_tree_from_object_files(&mut last_commit_tree, last_commit.get_tree(), &objects_path).map_err(|e| {
-            display_message("Error getting repository files from objects directory", Color::RED);
-            DitError::UnexpectedComportement(format!("Error details: {}", e))
-        })?;
-        last_commit_tree.set_id(last_commit.get_tree().to_string());
-        let mut last_commit_root: Node = TreeNode(last_commit_tree);
-
-        let option_deleted_elements = get_deleted_elements()?.unwrap_or(vec![]);
-        let deleted_elements: Vec<PathBuf> = option_deleted_elements
-            .into_iter()
-            .map(|p| PathBuf::from(p))
-            .collect();
-
-        for deleted_element in deleted_elements {
-            let real_path = path_from_dit(&deleted_element)?;
-            remove_element_from_repository_tree(&mut last_commit_root, &real_path).map_err(|e2| {
-                DitError::UnexpectedComportement(format!("{}", e2))
-            })?;
-            remove_element_from_repository_tree(&mut staged_root, &real_path).map_err(|e2| {
-                DitError::UnexpectedComportement(format!("{}", e2))
-            })?;
-        }
+    let modes = get_next_commit_modes(repo)?;
+    let tree_hash = save_commit_tree(repo, &get_next_commit_blobs(repo)?)?;
 
-        if let Some(result) = merge_repository_trees(last_commit_root, staged_root, &Mode::Partial) {
-            transcript_repository_to_object_files(&result, &dit_path.join("objects")).map_err(|e1| {
-                display_message("Error transcribing repository files from objects directory", Color::RED);
-                DitError::UnexpectedComportement(format!("Error details: {}", e1))
-            })?;
-            create_commit(description, last_commit_hash, result.get_id())?;
-        } else {
-            Err(DitError::UnexpectedComportement("Fail to create commit".to_string()))?
-        }
+    let commit = new_commit(repo, tree_hash, modes.save(repo)?, last_commit.get_parent().clone(), last_commit.get_merged().clone(), description, Some(&last_commit))?;
+    if *commit.get_hash() == last_commit_hash {
+        return Err(DitError::NothingToCommit);
+    }
+
+    commit.amend_commit_to_file(repo, &last_commit_hash)?;
+
+    Commit::reset_description_file(repo).map_err(DitError::IoError)?;
+    reset_index(repo)
+}
+
+/// Stage every file of the HEAD commit that was modified or deleted in the working directory,
+/// or whose mode changed
+pub fn stage_tracked_changes(repo: &Repository) -> Result<(), DitError> {
+    let head = get_head_hash(repo)?;
+    if repo.get_object_format().is_null_hash(&head) {
+        return Ok(());
+    }
+
+    let project_path = repo.get_work_dir();
+    let last_commit = Commit::get_commit_from_file(repo, head)?;
+    let tracked = get_tree_blobs(repo, last_commit.get_tree())?;
+
+    let (existing, mut deleted): (Vec<_>, Vec<_>) = tracked
+        .iter()
+        .map(|(path, _)| path.clone())
+        .partition(|path| project_path.join(path).is_file());
+
+    let head_modes = Modes::load(repo, last_commit.get_modes())?;
+    let (existing_special, deleted_special): (Vec<_>, Vec<_>) = head_modes
+        .get_special_paths()
+        .into_iter()
+        .partition(|path| fs::symlink_metadata(project_path.join(path)).is_ok());
+    deleted.extend(deleted_special);
+
+    let mut index = Index::load(repo)?;
+    let working_blobs = get_indexed_working_blobs(repo, &mut index, existing.clone())?;
+    index.try_save(repo)?;
+    let working_modes = Modes::from_working(repo, &[existing, existing_special.clone()].concat())?;
+    let mut modified: Vec<PathBuf> = working_blobs
+        .into_iter()
+        .filter(|(path, hash)| {
+            tracked.iter().any(|(p, h)| p == path && h != hash) || working_modes.get(path) != head_modes.get(path)
+        })
+        .map(|(path, _)| path)
+        .collect();
+    modified.extend(existing_special.into_iter().filter(|path| working_modes.get(path) != head_modes.get(path)));
+
+    if !modified.is_empty() {
+        add_paths(repo, modified)?;
+    }
+
+    if !deleted.is_empty() {
+        delete_paths(repo, deleted)?;
     }
     Ok(())
 }
 
-pub fn create_commit(description: String, last_commit_hash: String, commit_tree_hash: String) -> Result<(), DitError> {
+/// Build the tree of the next commit from `blobs`, the files of the index, and save it. Return
+/// the hash of the tree.
+fn save_commit_tree(repo: &Repository, blobs: &[(PathBuf, String)]) -> Result<String, DitError> {
+    let mut root = create_tree(Tree::default(), blobs);
+    let tree_hash = save_tree(repo, &mut root)?;
+    repo.report(Event::ObjectWritten(tree_hash.clone()));
+    Ok(tree_hash)
+}
+
+/// Open the editor on the description of the next commit, copied to a scratch directory as
+/// `.dit/commit` may be encrypted, and save it
+fn edit_description(repo: &Repository) -> Result<(), DitError> {
+    let scratch = ScratchDir::create(repo)?;
+    let edit_path = scratch.get_path().join("COMMIT_EDITMSG");
+    fs::write(&edit_path, read_message(repo)?).map_err(DitError::IoError)?;
+
+    let status = Command::new("vim")
+        .arg(&edit_path)
+        .spawn()
+        .map_err(DitError::IoError)?
+        .wait()
+        .map_err(DitError::IoError)?;
+    if !status.success() {
+        return Err(DitError::EditorFailed(format!("vim exited with {}", status)));
+    }
+
+    let description = fs::read(&edit_path).map_err(DitError::IoError)?;
+    message(repo, String::from_utf8_lossy(&description).to_string())
+}
+
+/// Create the commit of `commit_tree_hash` on top of `last_commit_hash` and make it the tip of
+/// the current branch. `merged_commit_hash` is the tip of the merged branch for a merge, empty
+/// otherwise.
+pub fn create_commit(repo: &Repository, description: String, last_commit_hash: String, merged_commit_hash: String, commit_tree_hash: String, modes: &Modes, reason: &str) -> Result<(), DitError> {
     let parent = last_commit_hash;
     let tree = commit_tree_hash;
-    let commit: Commit = Commit::new(tree, String::from(parent), description);
+    let commit: Commit = new_commit(repo, tree, modes.save(repo)?, parent, merged_commit_hash, description, None)?;
 
-    commit.transcript_commit_to_file()?;
+    commit.transcript_commit_to_file(repo, reason)?;
 
-    Commit::reset_description_file().map_err(DitError::IoError)?;
-    Ok(())
+    Commit::reset_description_file(repo).map_err(DitError::IoError)?;
+    reset_index(repo)
+}
+
+/// Create a commit authored now by the identity of the config, or by the author of `amended`
+/// when it has one, signed with the signing key of the repository when `commit.sign` is true.
+/// `merged` is the merged parent of a merge, empty otherwise.
+fn new_commit(repo: &Repository, tree: String, modes: String, parent: String, merged: String, description: String, amended: Option<&Commit>) -> Result<Commit, DitError> {
+    let config = Config::load(repo)?;
+    let mut commit = Commit::new(repo, tree, modes, parent, description);
+    if !merged.is_empty() {
+        commit.set_merged(merged);
+    }
+    match amended.filter(|amended| !amended.get_author().is_empty()) {
+        Some(amended) => commit.set_author(amended.get_author().clone(), amended.get_timestamp()),
+        None => commit.set_author(config.get_identity(), now()),
+    }
+    if config.get_bool(COMMIT_SIGN)? {
+        commit.sign(repo)?;
+    }
+    Ok(commit)
 }
 
-fn is_first_commit() -> Result<bool, DitError> {
-    Ok(get_head_hash()? == NULL_HASH)
+/// Make the index hold the files of the HEAD commit once it is written, keeping the stat data
+/// of the files which did not change
+fn reset_index(repo: &Repository) -> Result<(), DitError> {
+    let (mut index, lock) = Index::lock(repo)?;
+    index.reset(&get_head_blobs(repo)?);
+    index.save_to(repo, lock)
 }
 
@@ -0,0 +1,24 @@
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::objects::config::{Config, OBJECT_FORMAT, REPOSITORY_FORMAT_VERSION};
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Display the value of `key`, or set it when `value` is given
+pub fn config(repo: &Repository, key: &String, value: Option<&String>) -> Result<(), DitError> {
+    let mut config = Config::load(repo)?;
+    match value {
+        Some(_) if key == REPOSITORY_FORMAT_VERSION || key == OBJECT_FORMAT => {
+            return Err(DitError::UnexpectedComportement(format!("{} is set by dit init and can not be changed", key)));
+        }
+        Some(value) => {
+            config.set(key.to_owned(), value.to_owned());
+            config.save(repo)?;
+        }
+        None => match config.get(key) {
+            Some(value) => repo.report(Event::Output(value.clone(), Color::DEFAULT)),
+            None => repo.report(Event::Message(format!("{} is not set", key), Color::BLUE)),
+        },
+    }
+    Ok(())
+}
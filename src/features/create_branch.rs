@@ -1,18 +1,58 @@
-use std::process;
+use serde_json::json;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{get_head_hash, is_init};
+use crate::features::display_message::Color;
+use crate::features::init::get_head_hash;
+use crate::features::output::{OutputFormat, report_json, report_porcelain};
 use crate::objects::branch::Branch;
+use crate::reporter::Event;
+use crate::repository::Repository;
 
-pub fn new_branch(name: &String) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
-    }
+pub fn new_branch(repo: &Repository, name: &String) -> Result<(), DitError> {
+    let head = get_head_hash(repo)?;
+    Branch::new_branch(repo, name.to_owned(), head)?;
+    repo.report(Event::Message(format!("branch {} created.", name), Color::GREEN));
+    Ok(())
+}
 
-    let head = get_head_hash()?;
-    Branch::new_branch(name.to_owned(), head)?;
-    display_message(format!("branch {} created.", name).as_str(), Color::GREEN);
+/// Display every branch, marking the current one
+pub fn list_branches(repo: &Repository, format: OutputFormat) -> Result<(), DitError> {
+    let current = Branch::get_current_branch(repo)?;
+    let branches = Branch::get_branches(repo)?;
+
+    match format {
+        OutputFormat::Json => {
+            let values: Vec<_> = branches
+                .iter()
+                .map(|branch| json!({
+                    "name": branch.get_name(),
+                    "head": branch.get_head(),
+                    "current": branch.get_name() == current.get_name(),
+                }))
+                .collect();
+            report_json(repo, json!({ "branches": values }));
+        }
+        OutputFormat::Porcelain => {
+            let records = branches
+                .iter()
+                .map(|branch| vec![
+                    String::from("branch"),
+                    branch.get_name().clone(),
+                    branch.get_head().clone(),
+                    if branch.get_name() == current.get_name() { "*" } else { "-" }.to_string(),
+                ])
+                .collect();
+            report_porcelain(repo, records);
+        }
+        OutputFormat::Human => {
+            for branch in branches.iter() {
+                if branch.get_name() == current.get_name() {
+                    repo.report(Event::Output(format!("* {}", branch.get_name()), Color::GREEN));
+                } else {
+                    repo.report(Event::Output(format!("  {}", branch.get_name()), Color::DEFAULT));
+                }
+            }
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,58 +1,73 @@
-use std::io::{BufRead, BufReader};
-use std::process;
-
-use dit_file_encryptor::CompressedFile;
+use std::path::PathBuf;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_dit, get_staged_hash, is_init};
-use crate::utils::NULL_HASH;
-
-pub fn delete(elements: Vec<&String>) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+use crate::features::init::get_head_hash;
+use crate::objects::index::Index;
+use crate::objects::lock::LockFile;
+use crate::repository::Repository;
+use crate::utils::{path_from_dit, read_dit_file};
+
+pub fn delete(repo: &Repository, elements: Vec<&String>) -> Result<(), DitError> {
+    let elements: Result<Vec<PathBuf>, DitError> = elements
+        .into_iter()
+        .map(|element| path_from_dit(repo, &PathBuf::from(element)))
+        .collect();
+
+    delete_paths(repo, elements?)
+}
+
+/// Remove files whose paths are relative to the repository root from the index, and record
+/// them as deleted for the next commit so that their modes are dropped too
+pub fn delete_paths(repo: &Repository, elements: Vec<PathBuf>) -> Result<(), DitError> {
+    let deleted_path = repo.get_deleted_path();
+    let head_hash = get_head_hash(repo)?;
+
+    if repo.get_object_format().is_null_hash(&head_hash) {
+        return Err(DitError::NoCommit);
     }
 
-    let dit_path = find_dit().unwrap();
-    let deleted_path = dit_path.join("deleted");
-    let staged_hash = get_staged_hash()?;
-
-    if staged_hash == NULL_HASH {
-        display_message("Elements need to be commited first", Color::BLUE);
-    } else {
-        for element in elements {
-            CompressedFile::new(deleted_path.clone())
-                .append_to_file(format!("{}", element).as_bytes())
-                .map_err(|e| DitError::IoError(e))?;
+    let (mut index, lock) = Index::lock(repo)?;
+    for element in elements.iter() {
+        index.remove(element);
+    }
+    index.save_to(repo, lock)?;
+
+    let lock = LockFile::acquire(&deleted_path)?;
+    let mut deleted = get_deleted_elements(repo)?.unwrap_or_default();
+    for element in elements {
+        let element = element.to_string_lossy().to_string();
+        if !deleted.contains(&element) {
+            deleted.push(element);
         }
     }
-    Ok(())
+    let content: String = deleted.iter().map(|element| format!("{}\n", element)).collect();
+    lock.write(repo, content.as_bytes())?;
+    lock.commit()
 }
 
-pub fn get_deleted_elements() -> Result<Option<Vec<String>>, DitError> {
-    let dit_path = find_dit().unwrap();
-    let deleted_path = dit_path.join("deleted");
+pub fn get_deleted_elements(repo: &Repository) -> Result<Option<Vec<String>>, DitError> {
+    let deleted_path = repo.get_deleted_path();
 
     let mut elements: Vec<String> = Vec::new();
 
     if deleted_path.metadata()
         .map_err(|e| DitError::IoError(e))?
         .len() != 0 {
-        let reader = CompressedFile::new(deleted_path)
-            .open_for_read()
-            .map_err(|e| DitError::IoError(e))?;
-
-        let buf_reader = BufReader::new(reader);
-
-        for line in buf_reader.lines() {
-            let content = match line {
-                Ok(content) => content,
-                Err(e) => return Err(DitError::UnexpectedComportement(format!("deleted element not found, e: {}", e))),
-            };
-            elements.push(content);
+        let content = read_dit_file(repo, &deleted_path)?;
+
+        for line in String::from_utf8_lossy(&content).lines() {
+            if !line.is_empty() {
+                elements.push(line.to_string());
+            }
         }
     }
 
     Ok(Some(elements))
 }
+
+/// Empty the list of deleted elements once they are part of a commit
+pub fn reset_deleted_file(repo: &Repository) -> Result<(), DitError> {
+    let lock = LockFile::acquire(&repo.get_deleted_path())?;
+    lock.write(repo, &[])?;
+    lock.commit()
+}
@@ -0,0 +1,439 @@
+use std::fs;
+
+use serde_json::json;
+use similar::{ChangeTag, TextDiff};
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::log::CombinedChange;
+use crate::features::output::{change_to_json, change_to_porcelain, OutputFormat, report_json, report_porcelain};
+use crate::features::status::{get_rename_threshold, get_staged_changes, get_unstaged_changes};
+use crate::objects::change::Change;
+use crate::objects::config::Config;
+use crate::objects::lfs::{clean, Pointer};
+use crate::objects::mode::FileMode;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::read_blob;
+
+/// Display the changes of the working directory, or with `staged` the changes of the next commit.
+///
+/// With `stat`, only the number of inserted and deleted lines of each file is displayed. The
+/// porcelain and JSON formats always display these numbers. Binary files, detected by
+/// `Config::is_binary`, are only displayed with their sizes.
+pub fn diff(repo: &Repository, staged: bool, threshold: Option<u8>, stat: bool, format: OutputFormat) -> Result<(), DitError> {
+    let project_path = repo.get_work_dir();
+    let config = Config::load(repo)?;
+    let mut contents: Vec<(Change, Vec<u8>, Vec<u8>)> = Vec::new();
+
+    if staged {
+        let threshold = get_rename_threshold(repo, threshold)?;
+        for change in get_staged_changes(repo, threshold)? {
+            let (old, new) = read_change_contents(repo, &change)?;
+            contents.push((change, old, new));
+        }
+    } else {
+        for change in get_unstaged_changes(repo)? {
+            match &change {
+                Change::Modified { path, old_hash, .. } => {
+                    let old = read_blob(repo, old_hash)?;
+                    let new = match Pointer::parse(&old) {
+                        Some(_) => clean(repo, path, false)?.encode().into_bytes(),
+                        None => fs::read(project_path.join(path)).map_err(DitError::IoError)?,
+                    };
+                    contents.push((change, old, new));
+                }
+                Change::ModeChanged { old_mode, new_mode, .. } => {
+                    let (old, new) = (get_symlink_target(old_mode), get_symlink_target(new_mode));
+                    contents.push((change, old, new));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let contents = get_file_diffs(&config, contents);
+    if stat || format != OutputFormat::Human {
+        display_stat(repo, staged, &contents, format);
+    } else {
+        for file_diff in contents.iter() {
+            display_change(repo, file_diff);
+        }
+    }
+    Ok(())
+}
+
+/// Display the unified diff of the changes between two versions stored in the objects, such as
+/// two commits, or with `stat` only the number of inserted and deleted lines of each file
+pub fn display_changes(repo: &Repository, changes: Vec<Change>, stat: bool) -> Result<(), DitError> {
+    let config = Config::load(repo)?;
+    let mut contents: Vec<(Change, Vec<u8>, Vec<u8>)> = Vec::new();
+    for change in changes {
+        let (old, new) = read_change_contents(repo, &change)?;
+        contents.push((change, old, new));
+    }
+
+    let contents = get_file_diffs(&config, contents);
+    if stat {
+        display_stat(repo, false, &contents, OutputFormat::Human);
+    } else {
+        for file_diff in contents.iter() {
+            display_change(repo, file_diff);
+        }
+    }
+    Ok(())
+}
+
+/// Display the combined diff of the files of a merge which differ from both of its parents.
+/// Each line is prefixed by one column per parent, `+` for a line absent from that parent and
+/// `-` for a line of that parent removed by the merge.
+pub fn display_combined_changes(repo: &Repository, changes: &[CombinedChange]) -> Result<(), DitError> {
+    let config = Config::load(repo)?;
+    for (path, parents, hash) in changes {
+        repo.report(Event::Output(format!("diff --dit --cc {}", path.display()), Color::DEFAULT));
+        let new = read_optional_blob(repo, hash)?;
+        let olds = [read_optional_blob(repo, &parents[0])?, read_optional_blob(repo, &parents[1])?];
+        if config.is_binary(path, &new) || olds.iter().any(|old| config.is_binary(path, old)) {
+            repo.report(Event::Output(format!("Binary files differ ({} bytes)", get_content_size(&new)), Color::DEFAULT));
+            continue;
+        }
+
+        repo.report(Event::Output(format!("--- a/{}", path.display()), Color::DEFAULT));
+        match hash {
+            Some(_) => repo.report(Event::Output(format!("+++ b/{}", path.display()), Color::DEFAULT)),
+            None => repo.report(Event::Output(String::from("+++ /dev/null"), Color::DEFAULT)),
+        }
+        let new = String::from_utf8_lossy(&new);
+        let lines: Vec<&str> = new.lines().collect();
+        let parents_lines = olds.map(|old| get_parent_lines(&String::from_utf8_lossy(&old), &new, lines.len()));
+        display_combined_hunks(repo, &lines, &parents_lines);
+    }
+    Ok(())
+}
+
+/// Lines of a merge compared to the lines of one of its parents
+struct ParentLines {
+    /// Whether each line of the merge is absent from the parent
+    inserted: Vec<bool>,
+    /// Lines of the parent removed before each line of the merge, and after the last one
+    deleted: Vec<Vec<String>>,
+    /// Number of lines of the parent before the removed lines of each line of the merge
+    start: Vec<usize>,
+}
+
+fn get_parent_lines(old: &str, new: &str, count: usize) -> ParentLines {
+    let mut inserted = vec![false; count];
+    let mut deleted: Vec<Vec<String>> = vec![Vec::new(); count + 1];
+    let mut start: Vec<Option<usize>> = vec![None; count + 1];
+    let (mut old_line, mut new_line) = (0, 0);
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        start[new_line].get_or_insert(old_line);
+        match change.tag() {
+            ChangeTag::Delete => {
+                deleted[new_line].push(change.value().trim_end_matches(['\n', '\r']).to_string());
+                old_line += 1;
+            }
+            ChangeTag::Insert => {
+                inserted[new_line] = true;
+                new_line += 1;
+            }
+            ChangeTag::Equal => {
+                old_line += 1;
+                new_line += 1;
+            }
+        }
+    }
+    start[count].get_or_insert(old_line);
+    ParentLines { inserted, deleted, start: start.into_iter().map(Option::unwrap_or_default).collect() }
+}
+
+/// Display the lines of the merge changed relative to a parent, with three lines of context
+fn display_combined_hunks(repo: &Repository, lines: &[&str], parents_lines: &[ParentLines; 2]) {
+    let count = lines.len();
+    let is_changed = |line: usize| {
+        parents_lines
+            .iter()
+            .any(|parent| !parent.deleted[line].is_empty() || parent.inserted.get(line).copied().unwrap_or(false))
+    };
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for line in (0..=count).filter(|line| is_changed(*line)) {
+        let (first, last) = (line.saturating_sub(3), (line + 3).min(count));
+        match hunks.last_mut() {
+            Some(hunk) if first <= hunk.1 + 1 => hunk.1 = last,
+            _ => hunks.push((first, last)),
+        }
+    }
+
+    for (first, last) in hunks {
+        let new_len = (last + 1).min(count).saturating_sub(first);
+        let mut header = String::from("@@@");
+        for parent in parents_lines.iter() {
+            let kept = usize::from(last < count && !parent.inserted[last]);
+            let old_len = parent.start[last] + parent.deleted[last].len() + kept - parent.start[first];
+            header.push_str(&format!(" -{}", format_range(parent.start[first], old_len)));
+        }
+        header.push_str(&format!(" +{} @@@", format_range(first, new_len)));
+        repo.report(Event::Output(header, Color::BLUE));
+
+        for line in first..=last {
+            for (i, parent) in parents_lines.iter().enumerate() {
+                for deleted in parent.deleted[line].iter() {
+                    let columns = if i == 0 { "- " } else { " -" };
+                    repo.report(Event::Output(format!("{}{}", columns, deleted), Color::RED));
+                }
+            }
+            if let Some(text) = lines.get(line) {
+                let columns: String = parents_lines.iter().map(|parent| if parent.inserted[line] { '+' } else { ' ' }).collect();
+                let color = if columns.contains('+') { Color::GREEN } else { Color::DEFAULT };
+                repo.report(Event::Output(format!("{}{}", columns, text), color));
+            }
+        }
+    }
+}
+
+/// Return the range of a hunk header, `<first line>,<number of lines>` counted from 1, or the
+/// line before the hunk when it is empty
+fn format_range(start: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, len)
+    }
+}
+
+fn read_optional_blob(repo: &Repository, hash: &Option<String>) -> Result<Vec<u8>, DitError> {
+    match hash {
+        Some(hash) => read_blob(repo, hash),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Return the contents before and after `change` of files stored in the objects, the targets of
+/// the symlinks for a mode change
+fn read_change_contents(repo: &Repository, change: &Change) -> Result<(Vec<u8>, Vec<u8>), DitError> {
+    Ok(match change {
+        Change::Added { hash, .. } => (Vec::new(), read_blob(repo, hash)?),
+        Change::Deleted { hash, .. } => (read_blob(repo, hash)?, Vec::new()),
+        Change::Modified { old_hash, new_hash, .. } | Change::Renamed { old_hash, new_hash, .. } => {
+            (read_blob(repo, old_hash)?, read_blob(repo, new_hash)?)
+        }
+        Change::ModeChanged { old_mode, new_mode, .. } => (get_symlink_target(old_mode), get_symlink_target(new_mode)),
+    })
+}
+
+fn get_file_diffs(config: &Config, contents: Vec<(Change, Vec<u8>, Vec<u8>)>) -> Vec<FileDiff> {
+    contents
+        .into_iter()
+        .map(|(change, old, new)| {
+            let binary = is_binary_change(config, &change, &old, &new);
+            FileDiff { change, old, new, binary }
+        })
+        .collect()
+}
+
+/// Contents of a file before and after a change
+struct FileDiff {
+    change: Change,
+    old: Vec<u8>,
+    new: Vec<u8>,
+    binary: bool,
+}
+
+/// Whether one of the sides of `change` is a binary file
+fn is_binary_change(config: &Config, change: &Change, old: &[u8], new: &[u8]) -> bool {
+    let old_path = match change {
+        Change::Renamed { from, .. } => from,
+        change => change.get_path(),
+    };
+    !matches!(change, Change::ModeChanged { .. }) && (config.is_binary(old_path, old) || config.is_binary(change.get_path(), new))
+}
+
+/// Return the size of `content`, or of the content of the large file when it is a pointer
+fn get_content_size(content: &[u8]) -> u64 {
+    match Pointer::parse(content) {
+        Some(pointer) => pointer.get_size(),
+        None => content.len() as u64,
+    }
+}
+
+/// Return the target of a symlink followed by a newline, its content as displayed by the diff,
+/// or nothing for the other modes
+fn get_symlink_target(mode: &Option<FileMode>) -> Vec<u8> {
+    match mode {
+        Some(FileMode::Symlink(target)) => format!("{}\n", target).into_bytes(),
+        _ => Vec::new(),
+    }
+}
+
+/// Return the number of inserted and deleted lines between `old` and `new`
+pub fn count_changed_lines(old: &str, new: &str) -> (usize, usize) {
+    let text_diff = TextDiff::from_lines(old, new);
+    let mut insertions = 0;
+    let mut deletions = 0;
+    for change in text_diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => insertions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => (),
+        }
+    }
+    (insertions, deletions)
+}
+
+fn display_stat(repo: &Repository, staged: bool, contents: &[FileDiff], format: OutputFormat) {
+    let stats: Vec<(&FileDiff, usize, usize)> = contents
+        .iter()
+        .map(|file_diff| {
+            let (insertions, deletions) = match file_diff.binary {
+                true => (0, 0),
+                false => count_changed_lines(&String::from_utf8_lossy(&file_diff.old), &String::from_utf8_lossy(&file_diff.new)),
+            };
+            (file_diff, insertions, deletions)
+        })
+        .collect();
+    let insertions: usize = stats.iter().map(|(_, insertions, _)| insertions).sum();
+    let deletions: usize = stats.iter().map(|(_, _, deletions)| deletions).sum();
+
+    match format {
+        OutputFormat::Json => {
+            let files: Vec<_> = stats
+                .iter()
+                .map(|(file_diff, insertions, deletions)| {
+                    let mut value = change_to_json(&file_diff.change);
+                    value["insertions"] = json!(insertions);
+                    value["deletions"] = json!(deletions);
+                    if file_diff.binary {
+                        value["binary"] = json!(true);
+                        value["old_size"] = json!(get_content_size(&file_diff.old));
+                        value["new_size"] = json!(get_content_size(&file_diff.new));
+                    }
+                    value
+                })
+                .collect();
+            report_json(repo, json!({
+                "staged": staged,
+                "files": files,
+                "insertions": insertions,
+                "deletions": deletions,
+            }));
+        }
+        OutputFormat::Porcelain => {
+            let records = stats
+                .iter()
+                .map(|(file_diff, insertions, deletions)| {
+                    let counts = match file_diff.binary {
+                        true => vec![String::from("-"), String::from("-")],
+                        false => vec![insertions.to_string(), deletions.to_string()],
+                    };
+                    [vec![String::from("stat")], counts, change_to_porcelain(&file_diff.change)].concat()
+                })
+                .collect();
+            report_porcelain(repo, records);
+        }
+        OutputFormat::Human => {
+            let names: Vec<String> = stats
+                .iter()
+                .map(|(file_diff, _, _)| match &file_diff.change {
+                    Change::Renamed { from, to, .. } => format!("{} => {}", from.display(), to.display()),
+                    change => change.get_path().display().to_string(),
+                })
+                .collect();
+            let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+            for (name, (file_diff, insertions, deletions)) in names.iter().zip(stats.iter()) {
+                let line = match file_diff.binary {
+                    true => format!(" {:<width$} | Bin {} -> {} bytes", name, get_content_size(&file_diff.old), get_content_size(&file_diff.new)),
+                    false => format!(" {:<width$} | {} {}{}", name, insertions + deletions, "+".repeat(*insertions), "-".repeat(*deletions)),
+                };
+                repo.report(Event::Output(line, Color::DEFAULT));
+            }
+            repo.report(Event::Output(
+                format!(" {} files changed, {} insertions(+), {} deletions(-)", stats.len(), insertions, deletions),
+                Color::DEFAULT,
+            ));
+        }
+    }
+}
+
+/// Display the header of a change followed by the unified diff of its contents, or by the
+/// sizes of the contents of a binary file
+fn display_change(repo: &Repository, file_diff: &FileDiff) {
+    let change = &file_diff.change;
+    let (old_path, new_path) = match change {
+        Change::Renamed { from, to, .. } => (from, to),
+        change => (change.get_path(), change.get_path()),
+    };
+    repo.report(Event::Output(format!("diff --dit a/{} b/{}", old_path.display(), new_path.display()), Color::DEFAULT));
+
+    match change {
+        Change::Added { .. } => repo.report(Event::Output(String::from("new file"), Color::DEFAULT)),
+        Change::Deleted { .. } => repo.report(Event::Output(String::from("deleted file"), Color::DEFAULT)),
+        Change::Renamed { from, to, similarity, .. } => {
+            repo.report(Event::Output(format!("similarity index {}%", similarity), Color::DEFAULT));
+            repo.report(Event::Output(format!("rename from {}", from.display()), Color::DEFAULT));
+            repo.report(Event::Output(format!("rename to {}", to.display()), Color::DEFAULT));
+        }
+        Change::Modified { .. } => (),
+        Change::ModeChanged { old_mode: None, new_mode: Some(mode), .. } => {
+            repo.report(Event::Output(format!("new file mode {}", mode.as_octal()), Color::DEFAULT));
+        }
+        Change::ModeChanged { old_mode: Some(mode), new_mode: None, .. } => {
+            repo.report(Event::Output(format!("deleted file mode {}", mode.as_octal()), Color::DEFAULT));
+        }
+        Change::ModeChanged { old_mode, new_mode, .. } => {
+            let old_mode = old_mode.as_ref().map(FileMode::as_octal).unwrap_or_default();
+            let new_mode = new_mode.as_ref().map(FileMode::as_octal).unwrap_or_default();
+            if old_mode != new_mode {
+                repo.report(Event::Output(format!("old mode {}", old_mode), Color::DEFAULT));
+                repo.report(Event::Output(format!("new mode {}", new_mode), Color::DEFAULT));
+            }
+        }
+    }
+
+    if file_diff.old == file_diff.new {
+        return;
+    }
+
+    let old_header = match change {
+        Change::Added { .. } | Change::ModeChanged { old_mode: None, .. } => String::from("/dev/null"),
+        _ => format!("a/{}", old_path.display()),
+    };
+    let new_header = match change {
+        Change::Deleted { .. } | Change::ModeChanged { new_mode: None, .. } => String::from("/dev/null"),
+        _ => format!("b/{}", new_path.display()),
+    };
+
+    if file_diff.binary {
+        repo.report(Event::Output(
+            format!(
+                "Binary files {} and {} differ ({} -> {} bytes)",
+                old_header,
+                new_header,
+                get_content_size(&file_diff.old),
+                get_content_size(&file_diff.new)
+            ),
+            Color::DEFAULT,
+        ));
+        return;
+    }
+
+    let (old, new) = (String::from_utf8_lossy(&file_diff.old), String::from_utf8_lossy(&file_diff.new));
+    let text_diff = TextDiff::from_lines(old.as_ref(), new.as_ref());
+    let unified = text_diff
+        .unified_diff()
+        .header(&old_header, &new_header)
+        .to_string();
+
+    for line in unified.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            repo.report(Event::Output(line.to_string(), Color::DEFAULT));
+        } else if line.starts_with('+') {
+            repo.report(Event::Output(line.to_string(), Color::GREEN));
+        } else if line.starts_with('-') {
+            repo.report(Event::Output(line.to_string(), Color::RED));
+        } else if line.starts_with("@@") {
+            repo.report(Event::Output(line.to_string(), Color::BLUE));
+        } else {
+            repo.report(Event::Output(line.to_string(), Color::DEFAULT));
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use colored::Colorize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Color {
      BLUE,
      RED,
@@ -0,0 +1,414 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::output::{OutputFormat, report_json, report_porcelain};
+use crate::objects::commit::Commit;
+use crate::objects::index::Index;
+use crate::objects::lfs::Pointer;
+use crate::objects::mode::Modes;
+use crate::objects::object::{get_referenced_commits, Object, ObjectKind};
+use crate::objects::store::load_tree;
+use crate::objects::tag::Tag;
+use crate::objects::tree::{decode_tree, Node};
+use crate::objects::tree::Node::TreeNode;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Problem found by `fsck` on one object
+#[derive(Clone, Debug, PartialEq)]
+pub enum FsckIssue {
+    /// A reachable object is not in the object store
+    Missing { hash: String, kind: ObjectKind },
+    /// A reachable object can not be read or parsed, or does not hash to its name
+    Corrupt { hash: String, kind: ObjectKind, reason: String },
+    /// An unreachable object referenced by no other unreachable object
+    Dangling { hash: String, kind: Option<ObjectKind> },
+    /// An unreachable object referenced by a dangling one
+    Unreachable { hash: String, kind: Option<ObjectKind> },
+    /// The content of a reachable large file is not in the content store
+    MissingLargeFile { oid: String },
+    /// The stored content of a reachable large file does not match its pointer
+    CorruptLargeFile { oid: String, reason: String },
+}
+
+impl FsckIssue {
+    pub fn get_hash(&self) -> &String {
+        match self {
+            FsckIssue::Missing { hash, .. }
+            | FsckIssue::Corrupt { hash, .. }
+            | FsckIssue::Dangling { hash, .. }
+            | FsckIssue::Unreachable { hash, .. } => hash,
+            FsckIssue::MissingLargeFile { oid } | FsckIssue::CorruptLargeFile { oid, .. } => oid,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            FsckIssue::Missing { .. } | FsckIssue::MissingLargeFile { .. } => "missing",
+            FsckIssue::Corrupt { .. } | FsckIssue::CorruptLargeFile { .. } => "corrupt",
+            FsckIssue::Dangling { .. } => "dangling",
+            FsckIssue::Unreachable { .. } => "unreachable",
+        }
+    }
+
+    /// Type of the object, `object` when it is unknown and `lfs` for the content of a large file
+    pub fn get_kind_name(&self) -> &'static str {
+        match self {
+            FsckIssue::MissingLargeFile { .. } | FsckIssue::CorruptLargeFile { .. } => "lfs",
+            FsckIssue::Missing { kind, .. } | FsckIssue::Corrupt { kind, .. } => kind.as_str(),
+            FsckIssue::Dangling { kind, .. } | FsckIssue::Unreachable { kind, .. } => kind.map(|k| k.as_str()).unwrap_or("object"),
+        }
+    }
+
+    /// Why the object is corrupt
+    pub fn get_reason(&self) -> Option<&String> {
+        match self {
+            FsckIssue::Corrupt { reason, .. } | FsckIssue::CorruptLargeFile { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Whether the issue means data is lost, unreachable objects are only garbage
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            FsckIssue::Missing { .. } | FsckIssue::Corrupt { .. } | FsckIssue::MissingLargeFile { .. } | FsckIssue::CorruptLargeFile { .. }
+        )
+    }
+}
+
+/// Check every object reachable from the branches, the tags, the reflogs and the index,
+/// and the content of the large files they reference, then report the objects of the store
+/// which are not reachable.
+///
+/// Return `CorruptRepository` when a reachable object is missing or corrupt.
+pub fn fsck(repo: &Repository, format: OutputFormat) -> Result<(), DitError> {
+    let issues = get_fsck_issues(repo)?;
+
+    match format {
+        OutputFormat::Json => {
+            let values: Vec<Value> = issues
+                .iter()
+                .map(|issue| {
+                    let mut value = json!({
+                        "issue": issue.get_name(),
+                        "type": issue.get_kind_name(),
+                        "hash": issue.get_hash(),
+                    });
+                    if let Some(reason) = issue.get_reason() {
+                        value["reason"] = json!(reason);
+                    }
+                    value
+                })
+                .collect();
+            report_json(repo, json!({
+                "ok": !issues.iter().any(FsckIssue::is_error),
+                "issues": values,
+            }));
+        }
+        OutputFormat::Porcelain => {
+            let records = issues
+                .iter()
+                .map(|issue| {
+                    let mut record = vec![issue.get_name().to_string(), issue.get_kind_name().to_string(), issue.get_hash().clone()];
+                    if let Some(reason) = issue.get_reason() {
+                        record.push(reason.clone());
+                    }
+                    record
+                })
+                .collect();
+            report_porcelain(repo, records);
+        }
+        OutputFormat::Human => {
+            for issue in issues.iter() {
+                let line = match issue.get_reason() {
+                    Some(reason) => format!("{} {} {}: {}", issue.get_name(), issue.get_kind_name(), issue.get_hash(), reason),
+                    None => format!("{} {} {}", issue.get_name(), issue.get_kind_name(), issue.get_hash()),
+                };
+                let color = if issue.is_error() { Color::RED } else { Color::DEFAULT };
+                repo.report(Event::Output(line, color));
+            }
+            if issues.is_empty() {
+                repo.report(Event::Message(String::from("No problem found"), Color::GREEN));
+            }
+        }
+    }
+
+    let errors = issues.iter().filter(|issue| issue.is_error()).count();
+    if errors > 0 {
+        return Err(DitError::CorruptRepository(errors));
+    }
+    Ok(())
+}
+
+/// Return the problems of the repository, missing and corrupt objects first
+pub fn get_fsck_issues(repo: &Repository) -> Result<Vec<FsckIssue>, DitError> {
+    let mut checker = Checker {
+        repo,
+        kinds: BTreeMap::new(),
+        large_files: BTreeSet::new(),
+        issues: Vec::new(),
+    };
+
+    let mut pending = get_referenced_commits(repo)?;
+    while let Some(hash) = pending.pop() {
+        if let Some(commit) = checker.check_commit(&hash) {
+            checker.check_tree(commit.get_tree());
+            if !commit.get_modes().is_empty() {
+                checker.check_modes(commit.get_modes());
+            }
+            if !repo.get_object_format().is_null_hash(commit.get_parent()) {
+                pending.push(commit.get_parent().clone());
+            }
+            if commit.is_merge() {
+                pending.push(commit.get_merged().clone());
+            }
+        }
+    }
+
+    for tag in Tag::get_tags(repo)? {
+        if let Some(object) = tag.get_object() {
+            checker.check_tag(object);
+        }
+    }
+
+    for (_, hash) in Index::load(repo)?.get_blobs() {
+        checker.check_blob(&hash);
+    }
+
+    let mut issues = checker.issues;
+    issues.extend(get_unreachable_issues(repo, &checker.kinds)?);
+    Ok(issues)
+}
+
+struct Checker<'a> {
+    repo: &'a Repository,
+    kinds: BTreeMap<String, ObjectKind>,
+    /// Oids of the large files already checked
+    large_files: BTreeSet<String>,
+    issues: Vec<FsckIssue>,
+}
+
+impl Checker<'_> {
+    /// Mark `hash` as reachable, return false if it was already checked or is missing
+    fn visit(&mut self, hash: &str, kind: ObjectKind) -> Result<bool, DitError> {
+        if self.kinds.insert(hash.to_string(), kind).is_some() {
+            return Ok(false);
+        }
+        if !self.repo.get_store().has(hash)? {
+            self.issues.push(FsckIssue::Missing { hash: hash.to_string(), kind });
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn corrupt(&mut self, hash: &str, kind: ObjectKind, reason: String) {
+        self.issues.push(FsckIssue::Corrupt { hash: hash.to_string(), kind, reason });
+    }
+
+    fn check_commit(&mut self, hash: &String) -> Option<Commit> {
+        match self.visit(hash, ObjectKind::Commit) {
+            Ok(true) => (),
+            Ok(false) => return None,
+            Err(e) => {
+                self.corrupt(hash, ObjectKind::Commit, e.to_string());
+                return None;
+            }
+        }
+        match Commit::get_commit_from_file(self.repo, hash.clone()) {
+            Ok(commit) if commit.get_hash() != hash => {
+                let reason = format!("content hashes to {}", commit.get_hash());
+                self.corrupt(hash, ObjectKind::Commit, reason);
+                Some(commit)
+            }
+            Ok(commit) => Some(commit),
+            Err(DitError::CorruptObject { reason, .. }) => {
+                self.corrupt(hash, ObjectKind::Commit, reason);
+                None
+            }
+            Err(e) => {
+                self.corrupt(hash, ObjectKind::Commit, e.to_string());
+                None
+            }
+        }
+    }
+
+    fn check_tree(&mut self, hash: &str) {
+        match self.visit(hash, ObjectKind::Tree) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => return self.corrupt(hash, ObjectKind::Tree, e.to_string()),
+        }
+        let Some(object) = self.read_object(hash, ObjectKind::Tree) else {
+            return;
+        };
+        match decode_tree(hash, object.get_content(), Path::new("")) {
+            Ok(children) => {
+                for child in children {
+                    match child {
+                        TreeNode(_) => self.check_tree(&child.get_id()),
+                        blob => self.check_blob(&blob.get_id()),
+                    }
+                }
+            }
+            Err(DitError::CorruptObject { reason, .. }) => self.corrupt(hash, ObjectKind::Tree, reason),
+            Err(e) => self.corrupt(hash, ObjectKind::Tree, e.to_string()),
+        }
+    }
+
+    fn check_blob(&mut self, hash: &str) {
+        match self.visit(hash, ObjectKind::Blob) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => return self.corrupt(hash, ObjectKind::Blob, e.to_string()),
+        }
+        if let Some(object) = self.read_object(hash, ObjectKind::Blob) {
+            if let Some(pointer) = Pointer::parse(object.get_content()) {
+                self.check_large_file(&pointer);
+            }
+        }
+    }
+
+    /// Read the tree or blob `hash`, check its kind and that it hashes to its id
+    fn read_object(&mut self, hash: &str, kind: ObjectKind) -> Option<Object> {
+        match Object::read(self.repo, hash) {
+            Ok(object) if object.get_kind() != kind => {
+                self.corrupt(hash, kind, format!("expected a {} but found a {}", kind, object.get_kind()));
+                None
+            }
+            Ok(object) => {
+                let content_hash = object.get_id(self.repo.get_object_format());
+                if content_hash != hash {
+                    self.corrupt(hash, kind, format!("content hashes to {}", content_hash));
+                }
+                Some(object)
+            }
+            Err(DitError::CorruptObject { reason, .. }) => {
+                self.corrupt(hash, kind, reason);
+                None
+            }
+            Err(e) => {
+                self.corrupt(hash, kind, e.to_string());
+                None
+            }
+        }
+    }
+
+    fn check_modes(&mut self, hash: &str) {
+        match self.visit(hash, ObjectKind::Modes) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => return self.corrupt(hash, ObjectKind::Modes, e.to_string()),
+        }
+        match Modes::load(self.repo, hash) {
+            Ok(_) => (),
+            Err(DitError::CorruptObject { reason, .. }) => self.corrupt(hash, ObjectKind::Modes, reason),
+            Err(e) => self.corrupt(hash, ObjectKind::Modes, e.to_string()),
+        }
+    }
+
+    fn check_tag(&mut self, hash: &str) {
+        match self.visit(hash, ObjectKind::Tag) {
+            Ok(true) => (),
+            Ok(false) => return,
+            Err(e) => return self.corrupt(hash, ObjectKind::Tag, e.to_string()),
+        }
+        match Object::read(self.repo, hash) {
+            Ok(object) => {
+                let content = String::from_utf8_lossy(object.get_content());
+                let content_hash = self.repo.get_object_format().hash(&content);
+                if content_hash != hash {
+                    self.corrupt(hash, ObjectKind::Tag, format!("content hashes to {}", content_hash));
+                }
+            }
+            Err(DitError::CorruptObject { reason, .. }) => self.corrupt(hash, ObjectKind::Tag, reason),
+            Err(e) => self.corrupt(hash, ObjectKind::Tag, e.to_string()),
+        }
+    }
+
+    fn check_large_file(&mut self, pointer: &Pointer) {
+        let oid = pointer.get_oid().clone();
+        if !self.large_files.insert(oid.clone()) {
+            return;
+        }
+        if !pointer.has_content(self.repo) {
+            return self.issues.push(FsckIssue::MissingLargeFile { oid });
+        }
+        match pointer.check_content(self.repo) {
+            Ok(None) => (),
+            Ok(Some(reason)) => self.issues.push(FsckIssue::CorruptLargeFile { oid, reason }),
+            Err(e) => self.issues.push(FsckIssue::CorruptLargeFile { oid, reason: e.to_string() }),
+        }
+    }
+}
+
+/// Return the objects of the store which are not in `reachable`. Unreachable commits are
+/// followed to find which unreachable objects are referenced by others.
+fn get_unreachable_issues(repo: &Repository, reachable: &BTreeMap<String, ObjectKind>) -> Result<Vec<FsckIssue>, DitError> {
+    let unreachable: Vec<String> = repo
+        .get_store()
+        .iterate()?
+        .into_iter()
+        .filter(|hash| !reachable.contains_key(hash))
+        .collect();
+
+    let mut kinds: BTreeMap<String, ObjectKind> = BTreeMap::new();
+    let mut referenced: BTreeSet<String> = BTreeSet::new();
+    for hash in unreachable.iter() {
+        let Ok(object) = Object::read(repo, hash) else {
+            continue;
+        };
+        kinds.insert(hash.clone(), object.get_kind());
+        if object.get_kind() != ObjectKind::Commit {
+            continue;
+        }
+        let Ok(commit) = Commit::get_commit_from_file(repo, hash.clone()) else {
+            continue;
+        };
+        referenced.insert(commit.get_parent().clone());
+        if commit.is_merge() {
+            referenced.insert(commit.get_merged().clone());
+        }
+        referenced.insert(commit.get_tree().clone());
+        if !commit.get_modes().is_empty() {
+            referenced.insert(commit.get_modes().clone());
+        }
+        kinds.entry(commit.get_tree().clone()).or_insert(ObjectKind::Tree);
+        if let Ok(tree) = load_tree(repo, commit.get_tree()) {
+            for child in tree.get_children() {
+                collect_node_kinds(child, &mut kinds, &mut referenced);
+            }
+        }
+    }
+
+    Ok(unreachable
+        .into_iter()
+        .map(|hash| {
+            let kind = kinds.get(&hash).copied();
+            if referenced.contains(&hash) {
+                FsckIssue::Unreachable { hash, kind }
+            } else {
+                FsckIssue::Dangling { hash, kind }
+            }
+        })
+        .collect())
+}
+
+fn collect_node_kinds(node: &Node, kinds: &mut BTreeMap<String, ObjectKind>, referenced: &mut BTreeSet<String>) {
+    referenced.insert(node.get_id());
+    match node {
+        TreeNode(tree) => {
+            kinds.entry(tree.get_id()).or_insert(ObjectKind::Tree);
+            for child in tree.get_children() {
+                collect_node_kinds(child, kinds, referenced);
+            }
+        }
+        blob => {
+            kinds.entry(blob.get_id()).or_insert(ObjectKind::Blob);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::revision::parse_date;
+use crate::objects::object::get_reachable_objects;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Unreachable objects younger than this are kept, a command may still be writing them
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2 weeks ago";
+
+/// Clean up the repository: remove the unreachable objects older than the default grace period
+/// and move the other ones to a single pack
+pub fn gc(repo: &Repository, dry_run: bool) -> Result<(), DitError> {
+    prune(repo, None, dry_run)?;
+    repack(repo, dry_run)
+}
+
+/// Write the reachable objects and the recent unreachable packed objects in a new pack
+/// replacing the loose objects and the previous packs. The unreachable loose objects stay
+/// loose until `prune` removes them.
+pub fn repack(repo: &Repository, dry_run: bool) -> Result<(), DitError> {
+    let limit = parse_date(DEFAULT_PRUNE_EXPIRE)?;
+    let reachable = get_reachable_objects(repo)?;
+    let store = repo.get_store();
+
+    let mut hashes: Vec<String> = Vec::new();
+    let mut dropped: Vec<String> = Vec::new();
+    for hash in store.iterate()? {
+        if reachable.contains_key(&hash) {
+            hashes.push(hash);
+            continue;
+        }
+        let stat = store.stat(&hash)?;
+        if !stat.is_packed() {
+            continue;
+        }
+        if stat.get_modified() > limit {
+            hashes.push(hash);
+        } else {
+            dropped.push(hash);
+        }
+    }
+
+    if dry_run {
+        for hash in dropped.iter() {
+            repo.report(Event::Output(format!("would remove {}", hash), Color::DEFAULT));
+        }
+        if !dropped.is_empty() {
+            let message = format!("{} unreachable packed objects would be removed", dropped.len());
+            repo.report(Event::Message(message, Color::GREEN));
+        }
+        return Ok(());
+    }
+    if hashes.is_empty() && dropped.is_empty() {
+        return Ok(());
+    }
+
+    repo.report(Event::StepStarted(format!("Packing {} objects", hashes.len())));
+    if let Some(stats) = store.repack(&hashes)? {
+        repo.report(Event::StepFinished(format!("Packed {} objects", stats.get_objects())));
+        for hash in dropped {
+            repo.report(Event::ObjectRemoved(hash));
+        }
+        let message = format!(
+            "{} objects packed, {} as deltas, in {} instead of {}",
+            stats.get_objects(),
+            stats.get_deltas(),
+            format_size(stats.get_size()),
+            format_size(stats.get_previous_size()),
+        );
+        repo.report(Event::Message(message, Color::GREEN));
+    }
+    Ok(())
+}
+
+/// Remove the loose objects which are not reachable from the branches, the tags, the reflogs or
+/// the index and were written before `expire`, two weeks ago by default. With
+/// `dry_run`, only display them.
+pub fn prune(repo: &Repository, expire: Option<&String>, dry_run: bool) -> Result<(), DitError> {
+    let limit = parse_date(expire.map(|e| e.as_str()).unwrap_or(DEFAULT_PRUNE_EXPIRE))?;
+    let reachable = get_reachable_objects(repo)?;
+    let store = repo.get_store();
+
+    let mut count: usize = 0;
+    let mut size: u64 = 0;
+    for hash in store.iterate()? {
+        if reachable.contains_key(&hash) {
+            continue;
+        }
+        let stat = store.stat(&hash)?;
+        if stat.is_packed() || stat.get_modified() > limit {
+            continue;
+        }
+        if dry_run {
+            repo.report(Event::Output(format!("would remove {}", hash), Color::DEFAULT));
+        } else {
+            store.remove(&hash)?;
+            repo.report(Event::ObjectRemoved(hash));
+        }
+        count += 1;
+        size += stat.get_size();
+    }
+
+    let message = if dry_run {
+        format!("{} unreachable objects would be removed, {} would be reclaimed", count, format_size(size))
+    } else {
+        format!("{} unreachable objects removed, {} reclaimed", count, format_size(size))
+    };
+    repo.report(Event::Message(message, Color::GREEN));
+    Ok(())
+}
+
+/// Return `bytes` in B, KiB, MiB or GiB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
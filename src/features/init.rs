@@ -1,150 +1,80 @@
-use std::{env, fs, io};
-use std::fs::{create_dir, File};
-use std::path::PathBuf;
+use std::{fs, io};
+use std::fs::File;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
+use crate::features::upgrade::{set_layout_version, LAYOUT_VERSION};
 use crate::objects::branch::Branch;
-use crate::utils::{NULL_HASH, read_hash_file, write_hash_file};
-
-pub const STAGED_HASH_POSITION: usize = 0;
-pub const INFO_HASH_POSITION: usize = 1;
-
-const STAGED_PATH: &str = "./.dit/staged";
-
-pub fn init_repository() -> Result<(), io::Error> {
-    let dit_path = PathBuf::from("./.dit");
+use crate::objects::config::{Config, OBJECT_FORMAT, REPOSITORY_FORMAT, REPOSITORY_FORMAT_VERSION};
+use crate::objects::crypt::KeySource;
+use crate::objects::store::create_object_dirs;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Create the files of the repository, recording in its config the cipher of `repo`
+/// protected by `encryption` if any
+pub fn init_repository(repo: &Repository, encryption: Option<&KeySource>) -> Result<(), DitError> {
+    let dit_path = repo.get_dit_path();
     if dit_path.is_dir() {
-        fs::remove_dir_all(dit_path)?;
+        fs::remove_dir_all(dit_path).map_err(DitError::IoError)?;
     }
-    fs::create_dir_all("./.dit/objects")?;
-    fs::create_dir("./.dit/refs/")?;
-
-    init_object_dir()?;
-
-    init_info_file()?;
+    fs::create_dir_all(repo.get_objects_path()).map_err(DitError::IoError)?;
+    fs::create_dir(repo.get_refs_path()).map_err(DitError::IoError)?;
+    fs::create_dir(repo.get_logs_path()).map_err(DitError::IoError)?;
+    fs::create_dir(repo.get_tags_path()).map_err(DitError::IoError)?;
 
-    init_staged_file()?;
+    init_object_dir(repo).map_err(DitError::IoError)?;
 
-    File::create("./.dit/deleted")?;
+    init_info_file(repo)?;
 
-    File::create("./.dit/commit")?;
+    File::create(repo.get_deleted_path()).map_err(DitError::IoError)?;
 
-    Ok(())
-}
-
-fn init_object_dir() -> Result<(), io::Error> {
-    display_message("Initializing objects directory", Color::DEFAULT);
-    display_message("Initialized 255 sub directory objects", Color::DEFAULT);
-
-    let parent_dir = PathBuf::from("./.dit/objects");
-    if !parent_dir.exists() {
-        fs::create_dir(&parent_dir)?;
-    }
+    File::create(repo.get_commit_path()).map_err(DitError::IoError)?;
 
-    for i in 0..=255 {
-        let folder_name = format!("{:02x}", i);
+    init_config_file(repo, encryption)?;
 
-        let folder_path = parent_dir.join(folder_name);
-
-        fs::create_dir(&folder_path)?;
-    }
-
-    display_message("Initialized 255 sub directory objects", Color::DEFAULT);
-    display_message("Initialized objects directory", Color::DEFAULT);
+    set_layout_version(repo, LAYOUT_VERSION)?;
 
     Ok(())
 }
 
-fn init_info_file() -> Result<(), io::Error> {
-    display_message("Initializing info file", Color::DEFAULT);
-    File::create("./.dit/info")?;
+fn init_object_dir(repo: &Repository) -> Result<(), io::Error> {
+    repo.report(Event::StepStarted(String::from("Initializing objects directory")));
 
-    Branch::new_branch(String::from("main"), String::from(NULL_HASH)).unwrap();
-    display_message("Initialized info file", Color::DEFAULT);
-    Ok(())
-}
+    create_object_dirs(&repo.get_objects_path())?;
+
+    repo.report(Event::StepFinished(String::from("Initialized 256 sub directory objects")));
 
-fn init_staged_file() -> Result<(), io::Error> {
-    display_message("Initializing staged file", Color::DEFAULT);
-    let staged_path = PathBuf::from(STAGED_PATH);
-    write_hash_file(String::from(NULL_HASH), staged_path, 0)?;
-    display_message("Initialized staged file", Color::DEFAULT);
     Ok(())
 }
 
-pub fn get_path_object_file(hash: String) -> Result<PathBuf, io::Error> {
-    let b_hash = &hash[..2];
-    let e_hash = &hash[2..];
-
-    let object_dir = find_objects().join(b_hash);
-    if object_dir.exists() {
-        let object_file = object_dir.join(e_hash);
-        if object_file.exists() {
-            return Ok(object_file);
-        }
+/// Record the repository format, the hash algorithm of the object ids and the keys of an
+/// encrypted repository
+fn init_config_file(repo: &Repository, encryption: Option<&KeySource>) -> Result<(), DitError> {
+    let mut config = Config::default();
+    config.set(String::from(REPOSITORY_FORMAT_VERSION), REPOSITORY_FORMAT.to_string());
+    config.set(String::from(OBJECT_FORMAT), repo.get_object_format().to_string());
+    if let (Some(cipher), Some(source)) = (repo.get_cipher(), encryption) {
+        cipher.save(&mut config, source)?;
     }
-    Err(io::Error::new(io::ErrorKind::NotFound, "Error file not found in objects: {hash}"))
+    config.save(repo)
 }
 
-pub fn get_staged_hash() -> Result<String, DitError> {
-    let staged_path = find_staged();
-    let hash = read_hash_file(staged_path, STAGED_HASH_POSITION)?;
-    Ok(hash)
-}
+fn init_info_file(repo: &Repository) -> Result<(), DitError> {
+    repo.report(Event::StepStarted(String::from("Initializing info file")));
+    File::create(repo.get_info_path()).map_err(DitError::IoError)?;
 
-pub fn get_head_hash() -> Result<String, DitError> {
-    let info_path = find_info();
-    let hash = read_hash_file(info_path, INFO_HASH_POSITION)?;
-    Ok(hash)
-}
-
-pub fn find_objects() -> PathBuf {
-    let dit_path = find_dit().unwrap();
-    dit_path.join("objects")
-}
-
-pub fn find_refs() -> PathBuf {
-    find_dit().unwrap().join("refs")
-}
-
-pub fn find_staged() -> PathBuf {
-    let dit_path = find_dit().unwrap();
-    dit_path.join("staged")
-}
-
-pub fn find_info() -> PathBuf {
-    let dit_path = find_dit().unwrap();
-    dit_path.join("info")
-}
-
-pub fn find_dit() -> Option<PathBuf> {
-    let mut current_path = env::current_dir().ok()?;
-
-    loop {
-        let dit = current_path.join(".dit");
-        if dit.is_dir() {
-            return Some(dit);
-        }
-        current_path = current_path.parent()?.to_path_buf();
-    }
+    Branch::new_branch(repo, String::from("main"), repo.get_null_hash())?;
+    repo.report(Event::StepFinished(String::from("Initialized info file")));
+    Ok(())
 }
 
-pub fn is_init() -> bool {
-    if let Some(_) = find_dit() {
-        return true;
-    }
-    return false;
+pub fn get_head_hash(repo: &Repository) -> Result<String, DitError> {
+    let (_, hash) = Branch::read_info_file(repo)?;
+    Ok(hash)
 }
 
-pub fn get_object_path(objects_path: &PathBuf, hash: &String) -> Result<PathBuf, io::Error> {
-    let b_hash = &hash[..2];
-    let e_hash = &hash[2..];
-
-    let object_dir_path = objects_path.join(b_hash);
-    if !object_dir_path.exists() {
-        create_dir(&object_dir_path)?;
-    }
-    let node_path = object_dir_path.join(e_hash);
-    Ok(node_path)
+/// Make `hash` the HEAD commit, without changing the current branch
+pub fn set_head_hash(repo: &Repository, hash: &str) -> Result<(), DitError> {
+    let branch = Branch::get_current_branch(repo)?;
+    Branch::set_info_file(repo, branch.get_name().clone(), hash.to_string())
 }
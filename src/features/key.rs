@@ -0,0 +1,86 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::objects::config::Config;
+use crate::objects::crypt::KeySource;
+use crate::objects::lock::{is_lock_file, LockFile, Transaction};
+use crate::process_path::get_all_files_in_directory;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::read_dit_file;
+
+/// Encrypt the repository with a new key protected by `source`. The config records the new key
+/// first, then every encrypted file of `.dit` and every object is encrypted again with it. The
+/// replaced keys are kept in the config until the end, so that an interrupted rotation is
+/// completed by running it again. No other dit process must run in the repository meanwhile.
+pub fn rotate_key(repo: &Repository, source: &KeySource) -> Result<(), DitError> {
+    let cipher = repo
+        .get_cipher()
+        .ok_or_else(|| DitError::UnexpectedComportement(String::from("The repository is not encrypted")))?;
+    let rotated = Arc::new(cipher.rotate()?);
+    let mut config = Config::load(repo)?;
+    rotated.save(&mut config, source)?;
+    config.save(repo)?;
+    let repo = repo.clone().with_cipher(rotated.clone());
+
+    repo.report(Event::StepStarted(String::from("Encrypting files")));
+    let files = reencrypt_files(&repo)?;
+    repo.report(Event::StepFinished(format!("Encrypted {} files", files)));
+
+    let hashes = repo.get_store().iterate()?;
+    repo.report(Event::StepStarted(format!("Encrypting {} objects", hashes.len())));
+    if !hashes.is_empty() {
+        repo.get_store().repack(&hashes)?;
+    }
+    repo.report(Event::StepFinished(format!("Encrypted {} objects", hashes.len())));
+
+    rotated.get_current().save(&mut config, source)?;
+    config.save(&repo)?;
+    let message = format!("Key rotated, {} files and {} objects encrypted with the new key", files, hashes.len());
+    repo.report(Event::Message(message, Color::GREEN));
+    Ok(())
+}
+
+/// Encrypt again with the current key of `repo` the files of `.dit` written by `LockFile::write`,
+/// the description of the next commit included, and the index, return their number
+fn reencrypt_files(repo: &Repository) -> Result<usize, DitError> {
+    let mut paths: Vec<PathBuf> = vec![
+        repo.get_info_path(),
+        repo.get_modes_path(),
+        repo.get_deleted_path(),
+        repo.get_commit_path(),
+    ];
+    for dir in [repo.get_refs_path(), repo.get_tags_path(), repo.get_logs_path()] {
+        if dir.is_dir() {
+            paths.extend(get_all_files_in_directory(&dir).map_err(DitError::IoError)?);
+        }
+    }
+
+    let mut count = 0;
+    let mut transaction = Transaction::new(repo);
+    for path in paths {
+        if !path.is_file() || is_lock_file(&path) {
+            continue;
+        }
+        transaction.lock(path.clone())?;
+        let content = read_dit_file(repo, &path)?;
+        if !content.is_empty() {
+            transaction.write(path, &content)?;
+            count += 1;
+        }
+    }
+    transaction.commit()?;
+
+    let index_path = repo.get_index_path();
+    if index_path.is_file() {
+        let lock = LockFile::acquire(&index_path)?;
+        let content = repo.decrypt(&index_path, &fs::read(&index_path).map_err(DitError::IoError)?)?;
+        lock.write_plain(&repo.encrypt(&content)?)?;
+        lock.commit()?;
+        count += 1;
+    }
+    Ok(count)
+}
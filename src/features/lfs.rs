@@ -0,0 +1,22 @@
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::status::get_head_blobs;
+use crate::objects::lfs::Pointer;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::read_blob;
+
+/// Display the large files of the HEAD commit: the start of the hash of their content, `*`
+/// when the content is in the content store or `-`, and their path
+pub fn ls_files(repo: &Repository) -> Result<(), DitError> {
+    let mut blobs = get_head_blobs(repo)?;
+    blobs.sort();
+    for (path, hash) in blobs {
+        let Some(pointer) = Pointer::parse(&read_blob(repo, &hash)?) else {
+            continue;
+        };
+        let state = if pointer.has_content(repo) { '*' } else { '-' };
+        repo.report(Event::Output(format!("{} {} {}", &pointer.get_oid()[..10], state, path.display()), Color::DEFAULT));
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::output::{change_to_json, change_to_porcelain, commit_to_json, commit_to_porcelain, OutputFormat, report_json, report_porcelain};
+use crate::features::status::get_rename_threshold;
+use crate::objects::branch::Branch;
+use crate::objects::change::{Change, compare_versions, detect_renames};
+use crate::objects::commit::Commit;
+use crate::objects::mode::Modes;
+use crate::objects::signature::{verify, Verification};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{get_tree_blobs, read_blob};
+
+/// Display the commits of the current branch, newest first, with the verification of their
+/// signature when `show_signature` is true
+pub fn log(repo: &Repository, name_status: bool, show_signature: bool, threshold: Option<u8>, format: OutputFormat) -> Result<(), DitError> {
+    let branch = Branch::get_current_branch(repo)?;
+    let commits = Commit::get_commit_list(repo, branch.get_name().to_owned())?;
+    let threshold = get_rename_threshold(repo, threshold)?;
+
+    match format {
+        OutputFormat::Json => {
+            let mut values = Vec::new();
+            for commit in commits.iter().rev() {
+                let mut value = commit_to_json(commit);
+                if show_signature {
+                    let verification = verify_commit_signature(repo, commit)?;
+                    value["signature"] = json!({ "status": verification.get_status(), "message": verification.describe() });
+                }
+                if name_status {
+                    let changes = get_commit_changes(repo, commit, threshold)?;
+                    value["changes"] = json!(changes.iter().map(change_to_json).collect::<Vec<_>>());
+                }
+                values.push(value);
+            }
+            report_json(repo, json!({ "branch": branch.get_name(), "commits": values }));
+            return Ok(());
+        }
+        OutputFormat::Porcelain => {
+            let mut records = Vec::new();
+            for commit in commits.iter().rev() {
+                records.extend(commit_to_porcelain(commit));
+                if show_signature {
+                    let verification = verify_commit_signature(repo, commit)?;
+                    records.push(vec![String::from("signature"), verification.get_status().to_string(), verification.describe()]);
+                }
+                if name_status {
+                    for change in get_commit_changes(repo, commit, threshold)? {
+                        records.push([vec![String::from("change")], change_to_porcelain(&change)].concat());
+                    }
+                }
+            }
+            report_porcelain(repo, records);
+            return Ok(());
+        }
+        OutputFormat::Human => (),
+    }
+
+    if commits.is_empty() {
+        repo.report(Event::Message(String::from("No commit on this branch"), Color::BLUE));
+        return Ok(());
+    }
+
+    for commit in commits.iter().rev() {
+        repo.report(Event::Output(format!("commit {}", commit.get_hash()), Color::BLUE));
+        if show_signature && !commit.get_signature().is_empty() {
+            let verification = verify_commit_signature(repo, commit)?;
+            let color = if verification.is_good() { Color::GREEN } else { Color::RED };
+            repo.report(Event::Output(verification.describe(), color));
+        }
+        for line in commit.get_description().lines() {
+            repo.report(Event::Output(format!("    {}", line), Color::DEFAULT));
+        }
+        if name_status {
+            for change in get_commit_changes(repo, commit, threshold)? {
+                repo.report(Event::Output(change.name_status(), Color::DEFAULT));
+            }
+        }
+        repo.report(Event::Output(String::from(""), Color::DEFAULT));
+    }
+    Ok(())
+}
+
+fn verify_commit_signature(repo: &Repository, commit: &Commit) -> Result<Verification, DitError> {
+    verify(repo, &commit.get_payload(), commit.get_signature())
+}
+
+/// Return the changes introduced by `commit` relative to its parent
+pub fn get_commit_changes(repo: &Repository, commit: &Commit, threshold: u8) -> Result<Vec<Change>, DitError> {
+    let (parent_blobs, parent_modes) = if repo.get_object_format().is_null_hash(commit.get_parent()) {
+        (vec![], Modes::default())
+    } else {
+        let parent = Commit::get_commit_from_file(repo, commit.get_parent().to_owned())?;
+        (get_tree_blobs(repo, parent.get_tree())?, Modes::load(repo, parent.get_modes())?)
+    };
+    let blobs = get_tree_blobs(repo, commit.get_tree())?;
+    let modes = Modes::load(repo, commit.get_modes())?;
+
+    let changes = compare_versions(&parent_blobs, &parent_modes, &blobs, &modes);
+    detect_renames(changes, threshold, |hash| read_blob(repo, hash))
+}
+
+/// File of a merge which differs from both of its parents: its path and its hashes in the first
+/// parent, in the merged parent and in the merge, None where the file is absent
+pub type CombinedChange = (PathBuf, [Option<String>; 2], Option<String>);
+
+/// Return the files of the merge `commit` which differ from both of its parents, the files
+/// changed by the merge itself rather than taken from one of the branches
+pub fn get_combined_changes(repo: &Repository, commit: &Commit) -> Result<Vec<CombinedChange>, DitError> {
+    let mut parents_blobs: Vec<BTreeMap<PathBuf, String>> = Vec::new();
+    for parent in [commit.get_parent(), commit.get_merged()] {
+        let parent = Commit::get_commit_from_file(repo, parent.to_owned())?;
+        parents_blobs.push(get_tree_blobs(repo, parent.get_tree())?.into_iter().collect());
+    }
+    let blobs: BTreeMap<PathBuf, String> = get_tree_blobs(repo, commit.get_tree())?.into_iter().collect();
+
+    let paths: BTreeSet<&PathBuf> = blobs.keys().chain(parents_blobs.iter().flat_map(BTreeMap::keys)).collect();
+    Ok(paths
+        .into_iter()
+        .filter(|path| parents_blobs.iter().all(|parent_blobs| parent_blobs.get(*path) != blobs.get(*path)))
+        .map(|path| {
+            let parents = [parents_blobs[0].get(path).cloned(), parents_blobs[1].get(path).cloned()];
+            (path.clone(), parents, blobs.get(path).cloned())
+        })
+        .collect())
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::revision::resolve_revision;
+use crate::objects::commit::Commit;
+use crate::objects::object::{Object, ObjectKind};
+use crate::objects::store::load_tree;
+use crate::objects::tree::Node;
+use crate::objects::tree::Node::TreeNode;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Display the entries of the tree of the commit `revision`, or of the tree `revision`.
+/// With `recursive`, display every file of the subtrees instead of the subtrees.
+pub fn ls_tree(repo: &Repository, revision: &String, recursive: bool) -> Result<(), DitError> {
+    let hash = resolve_revision(repo, revision)?;
+    let tree_hash = match Object::read(repo, &hash)?.get_kind() {
+        ObjectKind::Commit => Commit::get_commit_from_file(repo, hash)?.get_tree().clone(),
+        ObjectKind::Tree => hash,
+        _ => return Err(DitError::UnexpectedComportement(format!("{} is neither a commit nor a tree", revision))),
+    };
+
+    for (kind, hash, path) in get_tree_entries(repo, &tree_hash, recursive)? {
+        repo.report(Event::Output(format!("{} {}\t{}", kind, hash, path.display()), Color::DEFAULT));
+    }
+    Ok(())
+}
+
+/// Return the kind, hash and path of the entries of the tree `tree_hash`, sorted by path
+pub fn get_tree_entries(repo: &Repository, tree_hash: &str, recursive: bool) -> Result<Vec<(ObjectKind, String, PathBuf)>, DitError> {
+    let tree = load_tree(repo, tree_hash)?;
+    let mut entries: Vec<(ObjectKind, String, PathBuf)> = Vec::new();
+    for child in tree.get_children() {
+        collect_entries(child, recursive, &mut entries);
+    }
+    entries.sort_by(|a, b| a.2.cmp(&b.2));
+    Ok(entries)
+}
+
+fn collect_entries(node: &Node, recursive: bool, entries: &mut Vec<(ObjectKind, String, PathBuf)>) {
+    match node {
+        TreeNode(tree) if recursive => {
+            for child in tree.get_children() {
+                collect_entries(child, recursive, entries);
+            }
+        }
+        TreeNode(_) => entries.push((ObjectKind::Tree, node.get_id(), node.get_path())),
+        blob => entries.push((ObjectKind::Blob, blob.get_id(), blob.get_path())),
+    }
+}
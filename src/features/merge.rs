@@ -1,53 +1,144 @@
-use std::process;
-
-use repository_tree_creator::features::get_repository_tree_from_object_files::get_repository_tree_from_object_files;
-use repository_tree_creator::features::merge_repository_trees::{merge_repository_trees, Mode};
-use repository_tree_creator::features::transcript_repository_tree_to_object_files::transcript_repository_to_object_files;
-use repository_tree_creator::models::node::Node;
-use repository_tree_creator::models::node::Node::TreeNode;
-use repository_tree_creator::models::tree::Tree;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::PathBuf;
 
 use crate::error::DitError;
 use crate::features::commit::create_commit;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_objects, is_init};
+use crate::features::display_message::Color;
+use crate::features::status::has_staged_files;
 use crate::objects::branch::Branch;
 use crate::objects::commit::Commit;
+use crate::objects::config::Config;
+use crate::objects::mode::Modes;
+use crate::objects::store::save_tree;
+use crate::objects::tree::{create_tree, Tree};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{get_tree_blobs, read_blob};
+
+/// Merge the branch `name` into the current branch. The trees and the modes are merged file by
+/// file against the last commit the branches have in common: a file changed on one branch only
+/// takes its version of that branch, a file changed differently on both branches is a conflict,
+/// as files are never merged line by line. The binary files in conflict are reported so that
+/// one of their versions is picked. Nothing is done when the branch is already merged.
+pub fn merge(repo: &Repository, name: &String) -> Result<(), DitError> {
+    if has_staged_files(repo)? {
+        return Err(DitError::DirtyWorkTree);
+    }
+
+    let target_branch = Branch::get_branch(repo, name.to_owned())?;
+    let current_branch = Branch::get_current_branch(repo)?;
+
+    let current_commit = Commit::get_commit_from_file(repo, current_branch.get_head().to_owned())?;
+    let target_commit = Commit::get_commit_from_file(repo, target_branch.get_head().to_owned())?;
+
+    let merge_base = get_merge_base(repo, current_commit.get_hash(), target_commit.get_hash())?;
+    if merge_base.as_ref() == Some(target_commit.get_hash()) {
+        repo.report(Event::Message(String::from("Already up to date"), Color::BLUE));
+        return Ok(());
+    }
+    let (base_blobs, base_modes): (BTreeMap<PathBuf, String>, Modes) = match merge_base {
+        Some(base) => {
+            let base_commit = Commit::get_commit_from_file(repo, base)?;
+            let blobs = get_tree_blobs(repo, base_commit.get_tree())?.into_iter().collect();
+            (blobs, Modes::load(repo, base_commit.get_modes())?)
+        }
+        None => (BTreeMap::new(), Modes::default()),
+    };
+    let current_blobs: BTreeMap<PathBuf, String> = get_tree_blobs(repo, current_commit.get_tree())?.into_iter().collect();
+    let target_blobs: BTreeMap<PathBuf, String> = get_tree_blobs(repo, target_commit.get_tree())?.into_iter().collect();
+
+    let paths: BTreeSet<&PathBuf> = current_blobs.keys().chain(target_blobs.keys()).collect();
+    let mut merged_blobs: Vec<(PathBuf, String)> = Vec::new();
+    let mut conflicts: Vec<(PathBuf, Option<String>, Option<String>)> = Vec::new();
+    for path in paths {
+        let (base, current, target) = (base_blobs.get(path), current_blobs.get(path), target_blobs.get(path));
+        let merged = if current == target || target == base {
+            current
+        } else if current == base {
+            target
+        } else {
+            conflicts.push((path.clone(), current.cloned(), target.cloned()));
+            continue;
+        };
+        if let Some(hash) = merged {
+            merged_blobs.push((path.clone(), hash.clone()));
+        }
+    }
+
+    let current_modes = Modes::load(repo, current_commit.get_modes())?;
+    let target_modes = Modes::load(repo, target_commit.get_modes())?;
+    let mode_paths: BTreeSet<&PathBuf> = [&base_modes, &current_modes, &target_modes]
+        .into_iter()
+        .flat_map(|modes| modes.get_entries().keys())
+        .collect();
+    let mut modes = Modes::default();
+    for path in mode_paths {
+        let (base, current, target) = (base_modes.get(path), current_modes.get(path), target_modes.get(path));
+        if current == target || target == base {
+            modes.set(path.clone(), current);
+        } else if current == base {
+            modes.set(path.clone(), target);
+        } else if !conflicts.iter().any(|(conflict, _, _)| conflict == path) {
+            conflicts.push((path.clone(), None, None));
+        }
+    }
+    modes.retain_tracked(&merged_blobs);
+
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        report_binary_conflicts(repo, &conflicts)?;
+        return Err(DitError::Conflict { paths: conflicts.into_iter().map(|(path, _, _)| path).collect() });
+    }
+
+    let mut merge = create_tree(Tree::default(), &merged_blobs);
+    let merge_hash = save_tree(repo, &mut merge)?;
+    repo.report(Event::ObjectWritten(merge_hash.clone()));
+
+    let desc: String = format!("merge {} and {}", current_branch.get_name(), target_branch.get_name());
+    let reason = format!("merge {}", target_branch.get_name());
+    create_commit(repo, desc, current_commit.get_hash().to_owned(), target_commit.get_hash().to_owned(), merge_hash, &modes, reason.as_str())?;
+    Ok(())
+}
+
+/// Return the last commit of the history of `current` which is also in the history of
+/// `target`, None when the branches have no commit in common
+fn get_merge_base(repo: &Repository, current: &str, target: &str) -> Result<Option<String>, DitError> {
+    let target_history: BTreeSet<String> = get_history(repo, target)?.into_iter().collect();
+    Ok(get_history(repo, current)?.into_iter().find(|hash| target_history.contains(hash)))
+}
 
-pub fn merge(name: &String) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+/// Return `hash` and the hashes of its ancestors through both parents of the merges, nearest
+/// first
+fn get_history(repo: &Repository, hash: &str) -> Result<Vec<String>, DitError> {
+    let mut history: Vec<String> = Vec::new();
+    let mut visited: BTreeSet<String> = BTreeSet::new();
+    let mut pending: VecDeque<String> = VecDeque::from([hash.to_string()]);
+    while let Some(current) = pending.pop_front() {
+        if repo.get_object_format().is_null_hash(&current) || !visited.insert(current.clone()) {
+            continue;
+        }
+        let commit = Commit::get_commit_from_file(repo, current.clone())?;
+        pending.push_back(commit.get_parent().clone());
+        if commit.is_merge() {
+            pending.push_back(commit.get_merged().clone());
+        }
+        history.push(current);
     }
+    Ok(history)
+}
 
-    let target_branch = Branch::get_branch(name.to_owned())?;
-    let current_branch = Branch::get_current_branch()?;
-
-    let current_commit = Commit::get_commit_from_file(current_branch.get_head().to_owned()).map_err(DitError::IoError)?;
-    let target_commit = Commit::get_commit_from_file(target_branch.get_head().to_owned()).map_err(DitError::IoError)?;
-
-    let mut target_tree = Tree::default();
-    get_repository_tree_from_object_files(&mut target_tree, target_commit.get_tree(), &find_objects()).map_err(|e| {
-        display_message("Error recreating files", Color::RED);
-        DitError::UnexpectedComportement(format!("Details: {}", e))
-    })?;
-    let target_node: Node = TreeNode(target_tree);
-
-
-    let mut current_tree = Tree::default();
-    get_repository_tree_from_object_files(&mut current_tree, target_commit.get_tree(), &find_objects()).map_err(|e| {
-        display_message("Error recreating files", Color::RED);
-        DitError::UnexpectedComportement(format!("Details: {}", e))
-    })?;
-    let current_node: Node = TreeNode(current_tree);
-
-    if let Some(merge) = merge_repository_trees(current_node, target_node, &Mode::Complete) {
-        transcript_repository_to_object_files(&merge, &find_objects()).map_err(|e1| {
-            display_message("Error recreating files.", Color::RED);
-            DitError::UnexpectedComportement(format!("Details: {}.", e1))
-        })?;
-        let desc: String = format!("merge {} and {}", current_branch.get_name(), target_branch.get_name());
-        create_commit(desc, current_commit.get_hash().to_owned(), merge.get_id())?;
+/// Warn about the binary files among the `conflicts`, given with their hash on the current and
+/// the merged branch, None where the file was deleted or only its mode differs
+fn report_binary_conflicts(repo: &Repository, conflicts: &[(PathBuf, Option<String>, Option<String>)]) -> Result<(), DitError> {
+    let config = Config::load(repo)?;
+    for (path, current_hash, target_hash) in conflicts {
+        let mut is_binary = false;
+        for hash in [current_hash, target_hash].into_iter().flatten() {
+            is_binary |= config.is_binary(path, &read_blob(repo, hash)?);
+        }
+        if is_binary {
+            repo.report(Event::Warning(format!("Binary file {} differs on both branches, it cannot be merged", path.display())));
+        }
     }
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,14 +1,21 @@
-use std::io;
-use std::io::Write;
-use std::path::PathBuf;
+use crate::error::DitError;
+use crate::objects::lock::LockFile;
+use crate::repository::Repository;
+use crate::utils::read_dit_file;
 
-use dit_file_encryptor::CompressedFile;
+/// Set the description of the next commit, written to `.dit/commit` like the other files of
+/// `.dit`, encrypted in an encrypted repository
+pub fn message(repo: &Repository, message: String) -> Result<(), DitError> {
+    let lock = LockFile::acquire(&repo.get_commit_path())?;
+    lock.write(repo, message.as_bytes())?;
+    lock.commit()
+}
 
-pub fn message(message: String) -> Result<(), io::Error> {
-    let desc_path = PathBuf::from("./.dit/commit");
-
-    let mut writer = CompressedFile::create_file(desc_path)?
-        .open_for_write()?;
-    write!(writer, "{}", message)?;
-    Ok(())
-}
\ No newline at end of file
+/// Return the description of the next commit, empty when none was set
+pub fn read_message(repo: &Repository) -> Result<String, DitError> {
+    let commit_path = repo.get_commit_path();
+    if !commit_path.is_file() {
+        return Ok(String::new());
+    }
+    Ok(String::from_utf8_lossy(&read_dit_file(repo, &commit_path)?).to_string())
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::DitError;
+use crate::features::add::add_paths;
+use crate::features::delete::delete_paths;
+use crate::features::display_message::Color;
+use crate::features::restore::unstage_paths;
+use crate::features::status::{get_head_blobs, get_staged_paths};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::path_from_dit;
+
+/// Rename a tracked file on disk and stage the rename.
+/// If the staging fails the file is moved back to its original place.
+pub fn mv(repo: &Repository, source: &String, destination: &String) -> Result<(), DitError> {
+    let project_path = repo.get_work_dir();
+
+    let source = path_from_dit(repo, &PathBuf::from(source))?;
+    let mut destination = path_from_dit(repo, &PathBuf::from(destination))?;
+
+    if project_path.join(&destination).is_dir() {
+        if let Some(file_name) = source.file_name() {
+            destination = destination.join(file_name);
+        }
+    }
+
+    let is_committed = get_head_blobs(repo)?.iter().any(|(path, _)| *path == source);
+    let is_staged = get_staged_paths(repo)?.contains(&source);
+
+    if !is_committed && !is_staged {
+        return Err(DitError::UnexpectedComportement(format!("{} is not tracked", source.display())));
+    }
+    if !project_path.join(&source).is_file() {
+        return Err(DitError::UnexpectedComportement(format!("{} does not exist", source.display())));
+    }
+    if project_path.join(&destination).exists() {
+        return Err(DitError::UnexpectedComportement(format!("{} already exists", destination.display())));
+    }
+
+    if let Some(parent) = project_path.join(&destination).parent() {
+        fs::create_dir_all(parent).map_err(DitError::IoError)?;
+    }
+    fs::rename(project_path.join(&source), project_path.join(&destination)).map_err(DitError::IoError)?;
+
+    if let Err(e) = stage_rename(repo, &source, &destination, is_committed, is_staged) {
+        fs::rename(project_path.join(&destination), project_path.join(&source)).map_err(DitError::IoError)?;
+        return Err(e);
+    }
+
+    repo.report(Event::Message(format!("R {} -> {}", source.display(), destination.display()), Color::GREEN));
+    Ok(())
+}
+
+fn stage_rename(repo: &Repository, source: &Path, destination: &Path, is_committed: bool, is_staged: bool) -> Result<(), DitError> {
+    if is_staged {
+        unstage_paths(repo, vec![source.to_path_buf()])?;
+    }
+    add_paths(repo, vec![destination.to_path_buf()])?;
+    if is_committed {
+        delete_paths(repo, vec![source.to_path_buf()])?;
+    }
+    Ok(())
+}
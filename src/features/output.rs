@@ -0,0 +1,109 @@
+use serde_json::{json, Value};
+
+use crate::features::display_message::Color;
+use crate::objects::change::Change;
+use crate::objects::commit::Commit;
+use crate::objects::mode::FileMode;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Version of the porcelain and JSON formats, incremented on every incompatible change.
+/// See `docs/output-format.md` for the schemas.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// How a read command renders its result
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Colored text for humans, may change between versions
+    #[default]
+    Human,
+    /// Tab separated records, one per line, preceded by a `version` record
+    Porcelain,
+    /// One JSON document with a `version` field
+    Json,
+}
+
+/// Emit `records` as porcelain lines, after the `version` record
+pub fn report_porcelain(repo: &Repository, records: Vec<Vec<String>>) {
+    repo.report(Event::Output(format!("version\t{}", FORMAT_VERSION), Color::DEFAULT));
+    for record in records {
+        repo.report(Event::Output(record.join("\t"), Color::DEFAULT));
+    }
+}
+
+/// Emit `value` as one JSON document, adding the `version` field
+pub fn report_json(repo: &Repository, mut value: Value) {
+    if let Value::Object(map) = &mut value {
+        map.insert(String::from("version"), json!(FORMAT_VERSION));
+    }
+    repo.report(Event::Output(value.to_string(), Color::DEFAULT));
+}
+
+pub fn change_to_json(change: &Change) -> Value {
+    let mut value = json!({
+        "status": change.get_status().to_string(),
+        "path": change.get_path().to_string_lossy(),
+    });
+    if let Change::Renamed { from, similarity, .. } = change {
+        value["from"] = json!(from.to_string_lossy());
+        value["similarity"] = json!(similarity);
+    }
+    if let Change::ModeChanged { old_mode, new_mode, .. } = change {
+        value["old_mode"] = json!(old_mode.as_ref().map(FileMode::as_octal));
+        value["new_mode"] = json!(new_mode.as_ref().map(FileMode::as_octal));
+        if let Some(FileMode::Symlink(target)) = new_mode {
+            value["target"] = json!(target);
+        }
+    }
+    value
+}
+
+/// Return the fields `<status> <path>`, followed by `<from> <similarity>` for a rename and
+/// by `<old mode> <new mode>` for a mode change, `000000` standing for no entry
+pub fn change_to_porcelain(change: &Change) -> Vec<String> {
+    let mut fields = vec![change.get_status().to_string(), change.get_path().to_string_lossy().to_string()];
+    if let Change::Renamed { from, similarity, .. } = change {
+        fields.push(from.to_string_lossy().to_string());
+        fields.push(similarity.to_string());
+    }
+    if let Change::ModeChanged { old_mode, new_mode, .. } = change {
+        fields.push(old_mode.as_ref().map(FileMode::as_octal).unwrap_or("000000").to_string());
+        fields.push(new_mode.as_ref().map(FileMode::as_octal).unwrap_or("000000").to_string());
+    }
+    fields
+}
+
+pub fn commit_to_json(commit: &Commit) -> Value {
+    let mut value = json!({
+        "hash": commit.get_hash(),
+        "parent": commit.get_parent(),
+        "tree": commit.get_tree(),
+        "message": commit.get_description(),
+    });
+    if commit.is_merge() {
+        value["merged"] = json!(commit.get_merged());
+    }
+    if !commit.get_author().is_empty() {
+        value["author"] = json!(commit.get_author());
+        value["date"] = json!(commit.get_timestamp());
+    }
+    value
+}
+
+/// Return a `commit <hash> <parent> <tree>` record, followed by `<merged>` for a merge, and one
+/// `message <line>` record per line of the description
+pub fn commit_to_porcelain(commit: &Commit) -> Vec<Vec<String>> {
+    let mut records = vec![vec![
+        String::from("commit"),
+        commit.get_hash().clone(),
+        commit.get_parent().clone(),
+        commit.get_tree().clone(),
+    ]];
+    if commit.is_merge() {
+        records[0].push(commit.get_merged().clone());
+    }
+    for line in commit.get_description().lines() {
+        records.push(vec![String::from("message"), line.to_string()]);
+    }
+    records
+}
@@ -0,0 +1,70 @@
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::revision::parse_date;
+use crate::objects::reflog::{HEAD_LOG, ReflogEntry};
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+pub const DEFAULT_EXPIRE: &str = "90 days ago";
+
+/// Display the log of `name`, newest first
+pub fn show_reflog(repo: &Repository, name: Option<&String>) -> Result<(), DitError> {
+    let name = name.cloned().unwrap_or(HEAD_LOG.to_string());
+    let entries = ReflogEntry::get_entries(repo, &name)?;
+
+    if entries.is_empty() {
+        repo.report(Event::Message(format!("No log for {}", name), Color::BLUE));
+        return Ok(());
+    }
+
+    for (index, entry) in entries.iter().rev().enumerate() {
+        let short: String = entry.get_new().chars().take(7).collect();
+        repo.report(Event::Output(format!("{} {}@{{{}}}: {}", short.as_str(), name, index, entry.get_reason()), Color::DEFAULT));
+    }
+    Ok(())
+}
+
+/// Remove the entries older than `expire` from the log of `name`, or from every log
+pub fn expire_reflog(repo: &Repository, name: Option<&String>, expire: Option<&String>) -> Result<(), DitError> {
+    let limit = parse_date(expire.map(|e| e.as_str()).unwrap_or(DEFAULT_EXPIRE))?;
+    let names = match name {
+        Some(name) => vec![name.clone()],
+        None => ReflogEntry::get_log_names(repo)?,
+    };
+
+    for name in names {
+        let entries = ReflogEntry::get_entries(repo, &name)?;
+        let kept: Vec<ReflogEntry> = entries
+            .iter()
+            .filter(|entry| entry.get_timestamp() >= limit)
+            .cloned()
+            .collect();
+        if kept.len() != entries.len() {
+            ReflogEntry::write_entries(repo, &name, &kept)?;
+            repo.report(Event::Message(format!("{}: {} entries expired", name, entries.len() - kept.len()), Color::DEFAULT));
+        }
+    }
+    Ok(())
+}
+
+/// Remove the entry `<name>@{n}` from the log of `name`
+pub fn delete_reflog_entry(repo: &Repository, selector: &String) -> Result<(), DitError> {
+    let invalid = || DitError::UnexpectedComportement(format!("Invalid reflog entry {}, expected <ref>@{{n}}", selector));
+
+    let (name, rest) = selector.split_once("@{").ok_or_else(invalid)?;
+    let n = rest
+        .strip_suffix('}')
+        .and_then(|n| n.parse::<usize>().ok())
+        .ok_or_else(invalid)?;
+    let name = if name.is_empty() { HEAD_LOG.to_string() } else { name.to_string() };
+
+    let mut entries = ReflogEntry::get_entries(repo, &name)?;
+    match entries.len().checked_sub(n + 1) {
+        Some(index) => {
+            entries.remove(index);
+            ReflogEntry::write_entries(repo, &name, &entries)?;
+        }
+        None => return Err(DitError::UnexpectedComportement(format!("Log for {} only has {} entries", name, entries.len()))),
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use crate::error::DitError;
+use crate::features::status::{get_head_blobs, has_staged_files};
+use crate::objects::index::Index;
+use crate::objects::mode::Modes;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::clean_path;
+
+/// Unstage files, the working directory is left untouched
+pub fn restore_staged(repo: &Repository, elements: Vec<&String>) -> Result<(), DitError> {
+    if elements.is_empty() {
+        repo.report(Event::Warning(String::from("You need to specify files to unstage")));
+    } else if !has_staged_files(repo)? && Modes::load_staged(repo)?.get_entries().is_empty() {
+        repo.report(Event::Warning(String::from("You need to add files before unstage them")));
+    } else {
+        let elements = clean_path(
+            repo,
+            elements.into_iter()
+                .map(PathBuf::from)
+                .collect()
+        )?;
+        unstage_paths(repo, elements)?;
+    }
+
+    Ok(())
+}
+
+/// Give files whose paths are relative to the repository root their version of the HEAD
+/// commit in the index, removing the ones it does not contain, and remove their modes from
+/// the staged modes
+pub fn unstage_paths(repo: &Repository, elements: Vec<PathBuf>) -> Result<(), DitError> {
+    let mut staged_modes = Modes::load_staged(repo)?;
+    let previous_modes = staged_modes.clone();
+    staged_modes.retain(|path, _| !elements.iter().any(|element| path == element));
+    if staged_modes != previous_modes {
+        staged_modes.save_staged(repo)?;
+    }
+
+    let head_blobs = get_head_blobs(repo)?;
+    let (mut index, lock) = Index::lock(repo)?;
+    for element in elements {
+        match head_blobs.iter().find(|(path, _)| *path == element) {
+            Some((_, hash)) => index.set(element, hash.clone()),
+            None => index.remove(&element),
+        }
+    }
+    index.save_to(repo, lock)
+}
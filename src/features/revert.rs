@@ -1,32 +1,30 @@
-use std::process;
-
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_info, get_head_hash, is_init};
+use crate::features::revision::resolve_revision;
+use crate::objects::branch::Branch;
 use crate::objects::commit::Commit;
-use crate::utils::{NULL_HASH, write_hash_file};
+use crate::objects::lock::Transaction;
+use crate::objects::reflog::record_head_movement;
+use crate::repository::Repository;
 
-pub fn revert(hash: String) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+pub fn revert(repo: &Repository, revision: String) -> Result<(), DitError> {
+    let hash = resolve_revision(repo, &revision)?;
+    if repo.get_object_format().is_null_hash(&hash) || !repo.get_store().has(&hash)? {
+        return Err(DitError::UnknownRevision(revision));
     }
 
-    if Commit::commit_exist(&hash)? {
-        let head = get_head_hash()?;
-        if head == NULL_HASH {
-            display_message("Commit staged files before revert", Color::BLUE);
-        } else {
-            let info_path = find_info();
+    let mut transaction = Transaction::new(repo);
+    transaction.lock(repo.get_info_path())?;
+    let branch = Branch::get_current_branch(repo)?;
+    let head = branch.get_head().clone();
+    if repo.get_object_format().is_null_hash(&head) {
+        return Err(DitError::NoCommit);
+    }
 
-            Commit::get_commit_from_file(head).map_err(DitError::IoError)?;
-            let commit = Commit::get_commit_from_file(hash).map_err(DitError::IoError)?;
-            commit.recreate_files()?;
+    Commit::get_commit_from_file(repo, head.clone())?;
+    let commit = Commit::get_commit_from_file(repo, hash)?;
+    commit.recreate_files(repo)?;
 
-            write_hash_file(commit.get_hash().clone(), info_path, 5).map_err(DitError::IoError)?;
-        }
-    } else {
-        display_message("Commit ID not recognized", Color::BLUE);
-    }
-    Ok(())
+    Branch::write_info_to(&mut transaction, branch.get_name(), commit.get_hash())?;
+    record_head_movement(&mut transaction, &head, commit.get_hash(), format!("revert: {}", commit.get_hash()).as_str())?;
+    transaction.commit()
 }
\ No newline at end of file
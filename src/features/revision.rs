@@ -0,0 +1,147 @@
+use crate::error::DitError;
+use crate::features::init::get_head_hash;
+use crate::objects::branch::Branch;
+use crate::objects::reflog::{HEAD_LOG, now, ReflogEntry};
+use crate::objects::tag::Tag;
+use crate::repository::Repository;
+
+const MIN_PREFIX_LENGTH: usize = 4;
+
+/// Return the commit hash designated by `revision`.
+///
+/// Accepted forms are `HEAD`, a branch name, a tag name, a full or abbreviated hash, `<ref>@{n}` for the
+/// n-th previous value of a ref and `<ref>@{<date>}` for its value at a date such as
+/// `yesterday` or `2 hours ago`. Without `<ref>` the current branch is used.
+pub fn resolve_revision(repo: &Repository, revision: &str) -> Result<String, DitError> {
+    if let Some((name, selector)) = split_reflog_selector(revision) {
+        let name = match name {
+            "" => Branch::get_current_branch(repo)?.get_name().clone(),
+            name => name.to_string(),
+        };
+        return resolve_reflog_selector(repo, &name, selector);
+    }
+
+    if revision == HEAD_LOG {
+        return get_head_hash(repo);
+    }
+
+    if Branch::is_name_ok(&revision.to_string()) && Branch::exist(repo, revision.to_string()) {
+        return Ok(Branch::get_branch(repo, revision.to_string())?.get_head().clone());
+    }
+
+    if Branch::is_name_ok(&revision.to_string()) && Tag::exist(repo, &revision.to_string()) {
+        return Ok(Tag::get_tag(repo, &revision.to_string())?.get_target().clone());
+    }
+
+    resolve_hash_prefix(repo, revision)
+}
+
+fn split_reflog_selector(revision: &str) -> Option<(&str, &str)> {
+    let (name, rest) = revision.split_once("@{")?;
+    let selector = rest.strip_suffix('}')?;
+    Some((name, selector))
+}
+
+fn resolve_reflog_selector(repo: &Repository, name: &String, selector: &str) -> Result<String, DitError> {
+    let entries = ReflogEntry::get_entries(repo, name)?;
+
+    if let Ok(n) = selector.parse::<usize>() {
+        if n == 0 && entries.is_empty() {
+            return resolve_revision(repo, name);
+        }
+        return match entries.len().checked_sub(n + 1) {
+            Some(index) => Ok(entries[index].get_new().clone()),
+            None => Err(DitError::UnexpectedComportement(format!("Log for {} only has {} entries", name, entries.len()))),
+        };
+    }
+
+    let timestamp = parse_date(selector)?;
+    match entries.iter().rev().find(|entry| entry.get_timestamp() <= timestamp) {
+        Some(entry) => Ok(entry.get_new().clone()),
+        None => match entries.first() {
+            Some(entry) if !repo.get_object_format().is_null_hash(entry.get_old()) => Ok(entry.get_old().clone()),
+            _ => Err(DitError::UnexpectedComportement(format!("Log for {} does not go back to {}", name, selector))),
+        },
+    }
+}
+
+fn resolve_hash_prefix(repo: &Repository, prefix: &str) -> Result<String, DitError> {
+    let unknown = || DitError::UnknownRevision(prefix.to_string());
+
+    if prefix.len() < MIN_PREFIX_LENGTH
+        || prefix.len() > repo.get_object_format().get_hex_length()
+        || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(unknown());
+    }
+
+    let mut matches: Vec<String> = repo
+        .get_store()
+        .iterate()?
+        .into_iter()
+        .filter(|hash| hash.starts_with(prefix))
+        .collect();
+
+    match matches.len() {
+        0 => Err(unknown()),
+        1 => Ok(matches.remove(0)),
+        _ => Err(DitError::AmbiguousRevision(prefix.to_string())),
+    }
+}
+
+/// Return the unix timestamp of `date`: `now`, `yesterday`, `<n> <unit>(s) ago`,
+/// `<n>.<unit>(s).ago` or a unix timestamp
+pub fn parse_date(date: &str) -> Result<u64, DitError> {
+    let invalid = || DitError::UnexpectedComportement(format!("Invalid date {}", date));
+
+    let date = date.trim();
+    match date {
+        "now" => return Ok(now()),
+        "yesterday" => return Ok(now().saturating_sub(86400)),
+        _ => (),
+    }
+    if let Ok(timestamp) = date.parse::<u64>() {
+        return Ok(timestamp);
+    }
+
+    let words: Vec<&str> = date.split([' ', '.']).filter(|w| !w.is_empty()).collect();
+    if words.len() != 3 || words[2] != "ago" {
+        return Err(invalid());
+    }
+    let count = words[0].parse::<u64>().map_err(|_| invalid())?;
+    let unit = match words[1].trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 3600,
+        "day" => 86400,
+        "week" => 7 * 86400,
+        "month" => 30 * 86400,
+        "year" => 365 * 86400,
+        _ => return Err(invalid()),
+    };
+    Ok(now().saturating_sub(count * unit))
+}
+
+/// Return `timestamp` as `YYYY-MM-DD HH:MM:SS +0000`, in UTC
+pub fn format_date(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+    // Civil date of a day count since 1970-01-01, with years starting in March
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} +0000",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
@@ -1,94 +1,78 @@
-use std::path::{Path, PathBuf};
-use std::process;
-
-use repository_tree_creator::features::get_repository_tree_from_object_files::get_repository_tree_from_object_files;
-use repository_tree_creator::features::transcript_repository_tree_to_object_files::transcript_repository_to_object_files;
-use repository_tree_creator::models::node::Node;
-use repository_tree_creator::models::node::Node::TreeNode;
-use repository_tree_creator::models::tree::Tree;
+use std::fs;
+use std::path::PathBuf;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_objects, find_staged, get_staged_hash, is_init};
-use crate::utils::{clean_path, NULL_HASH, write_hash_file};
+use crate::features::delete::delete_paths;
+use crate::features::init::get_head_hash;
+use crate::features::restore::unstage_paths;
+use crate::features::status::{get_head_modes, get_staged_paths};
+use crate::objects::commit::Commit;
+use crate::objects::mode::Modes;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{clean_path, get_tree_blobs};
 
-pub fn rm(elements: Vec<&String>) -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+/// Stop tracking files and record their removal for the next commit.
+/// Unless `cached` is set, files are also removed from the working directory.
+pub fn rm(repo: &Repository, elements: Vec<&String>, cached: bool) -> Result<(), DitError> {
+    if elements.is_empty() {
+        repo.report(Event::Warning(String::from("You need to specify files to remove")));
+        return Ok(());
     }
 
-    let staged_hash = get_staged_hash()?;
-    let object_path = find_objects();
-    let staged_path = find_staged();
+    let project_path = repo.get_work_dir();
 
-    if elements.is_empty() {
-        display_message("You need to specify files to remove", Color::BLUE);
-    } else if staged_hash == NULL_HASH {
-        display_message("You need to add files before remove them", Color::BLUE);
+    let head_hash = get_head_hash(repo)?;
+    let mut committed: Vec<PathBuf> = if repo.get_object_format().is_null_hash(&head_hash) {
+        vec![]
     } else {
-        let mut tree = Tree::default();
+        let commit = Commit::get_commit_from_file(repo, head_hash)?;
+        get_tree_blobs(repo, commit.get_tree())?.into_iter().map(|(path, _)| path).collect()
+    };
+    committed.extend(get_head_modes(repo)?.get_special_paths());
 
-        get_repository_tree_from_object_files(&mut tree, &staged_hash, &object_path).map_err(|e| {
-            display_message("Error getting files", Color::RED);
-            DitError::UnexpectedComportement(format!("Details: {}", e))
-        })?;
+    let mut staged: Vec<PathBuf> = get_staged_paths(repo)?;
+    staged.extend(Modes::load_staged(repo)?.get_special_paths());
 
-        let mut root = TreeNode(tree);
+    let elements = clean_path(
+        repo,
+        elements.into_iter()
+            .map(PathBuf::from)
+            .collect()
+    )?;
 
-        let elements = clean_path(
-            elements.into_iter()
-                .map(|p| PathBuf::from(p))
-                .collect()
-        )?;
+    let mut to_unstage: Vec<PathBuf> = vec![];
+    let mut to_delete: Vec<PathBuf> = vec![];
 
-        for element in elements {
-            let mut ancestors: Vec<_> = element.ancestors().collect();
-            ancestors.pop();
-            ancestors.reverse();
+    for element in elements {
+        let is_committed = committed.contains(&element);
+        let is_staged = staged.contains(&element);
 
-            find_element_to_remove(&mut root, &mut ancestors);
+        if !is_committed && !is_staged {
+            repo.report(Event::Warning(format!("{} is not tracked", element.display())));
+            continue;
+        }
+        if is_staged {
+            to_unstage.push(element.clone());
+        }
+        if is_committed {
+            to_delete.push(element.clone());
+        }
+        if !cached {
+            let file_path = project_path.join(&element);
+            if file_path.is_symlink() || file_path.is_file() {
+                fs::remove_file(file_path).map_err(DitError::IoError)?;
+            } else if file_path.is_dir() {
+                fs::remove_dir(file_path).map_err(DitError::IoError)?;
+            }
         }
-        transcript_repository_to_object_files(&root, &object_path).map_err(|e1| {
-            display_message("Error recreating files.", Color::RED);
-            DitError::UnexpectedComportement(format!("Details: {}.", e1))
-        })?;
-
-        write_hash_file(root.get_id(), staged_path, 0).unwrap();
     }
 
-    Ok(())
-}
-
-pub fn find_element_to_remove(root: &mut Node, paths: &mut Vec<&Path>) {
-    if paths.is_empty() {
-        return;
+    if !to_unstage.is_empty() {
+        unstage_paths(repo, to_unstage)?;
     }
-    let path = paths[0];
-    let file_name = path.file_name().unwrap().to_str().unwrap();
-
-    if let TreeNode(ref mut tree) = root {
-        let mut tree_copy = tree.clone();
-        if let Some(node) = tree.get_mut_children()
-            .iter()
-            .find(|x| x.get_name() == file_name) {
-            if paths.len() == 1 {
-                if let Some(index) = tree_copy
-                    .get_mut_children()
-                    .iter()
-                    .position(|x1| x1.get_path() == node.get_path() && Node::is_same_type(x1, node)) {
-                    tree.get_mut_children().remove(index);
-                }
-            } else {
-                if let Some(index) = tree_copy
-                    .get_mut_children()
-                    .iter()
-                    .position(|x1| x1.get_path() == node.get_path() && Node::is_same_type(x1, node)) {
-                    let children = tree.get_mut_children();
-                    paths.remove(0);
-                    find_element_to_remove(&mut children[index], paths);
-                }
-            }
-        }
+    if !to_delete.is_empty() {
+        delete_paths(repo, to_delete)?;
     }
-}
\ No newline at end of file
+    Ok(())
+}
@@ -1,19 +1,162 @@
-use std::process;
+use std::fs;
+use std::path::Path;
+
+use serde_json::json;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::is_init;
+use crate::features::diff::{display_changes, display_combined_changes};
+use crate::features::display_message::Color;
+use crate::features::log::{get_combined_changes, get_commit_changes};
+use crate::features::output::{change_to_json, change_to_porcelain, commit_to_json, commit_to_porcelain, OutputFormat, report_json, report_porcelain};
+use crate::features::revision::{format_date, resolve_revision};
+use crate::features::status::get_rename_threshold;
 use crate::objects::branch::Branch;
 use crate::objects::commit::Commit;
+use crate::objects::config::Config;
+use crate::objects::lfs::Pointer;
+use crate::objects::mode::{FileMode, Modes};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{get_tree_blobs, normalize_path, read_blob};
+
+/// What `show` displays after the metadata of a commit
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShowMode {
+    Patch,
+    Stat,
+    NameOnly,
+    NameStatus,
+}
+
+/// Display the commit tree of the current branch, or its commits oldest first for the
+/// porcelain and JSON formats
+pub fn show_commit(repo: &Repository, format: OutputFormat) -> Result<(), DitError> {
+    let branch: Branch = Branch::get_current_branch(repo)?;
+    match format {
+        OutputFormat::Json => {
+            let commits = Commit::get_commit_list(repo, branch.get_name().to_owned())?;
+            report_json(repo, json!({
+                "branch": branch.get_name(),
+                "commits": commits.iter().map(commit_to_json).collect::<Vec<_>>(),
+            }));
+        }
+        OutputFormat::Porcelain => {
+            let commits = Commit::get_commit_list(repo, branch.get_name().to_owned())?;
+            let mut records = vec![vec![String::from("branch"), branch.get_name().clone(), branch.get_head().clone()]];
+            records.extend(commits.iter().flat_map(commit_to_porcelain));
+            report_porcelain(repo, records);
+        }
+        OutputFormat::Human => {
+            repo.report(Event::Output(format!("Branch: {}\nCommit tree:\n", branch.get_name()), Color::GREEN));
+            Commit::display_commit_tree(repo)?;
+        }
+    }
+    Ok(())
+}
 
-pub fn show_commit() -> Result<(), DitError> {
-    if !is_init() {
-        display_message("dit repository is not initialized.", Color::RED);
-        process::exit(1);
+/// Display the commit designated by `revision`: its hash, its parents, its author, its date and
+/// its description, followed by its changes relative to its parent as selected by `mode`.
+///
+/// The patch of a merge is the combined diff of the files which differ from both of its parents,
+/// its other modes and the porcelain and JSON formats list its changes relative to its first
+/// parent, the commit of the branch merged into. `<revision>:<path>` displays instead the file
+/// `path`, relative to the root of the repository, as it is in the commit.
+pub fn show(repo: &Repository, revision: &str, mode: ShowMode, threshold: Option<u8>, format: OutputFormat) -> Result<(), DitError> {
+    if let Some((revision, path)) = revision.split_once(':') {
+        return show_file(repo, revision, Path::new(path));
     }
 
-    let branch: Branch = Branch::get_current_branch()?;
-    display_message(format!("Branch: {}\nCommit tree:\n", branch.get_name()).as_str(), Color::GREEN);
-    Commit::display_commit_tree()?;
+    let commit = Commit::get_commit_from_file(repo, resolve_revision(repo, revision)?)?;
+    let changes = get_commit_changes(repo, &commit, get_rename_threshold(repo, threshold)?)?;
+    let combined = if commit.is_merge() { get_combined_changes(repo, &commit)? } else { vec![] };
+    match format {
+        OutputFormat::Json => {
+            let mut value = commit_to_json(&commit);
+            value["changes"] = json!(changes.iter().map(change_to_json).collect::<Vec<_>>());
+            if commit.is_merge() {
+                value["combined"] = json!(combined.iter().map(|(path, _, _)| path.to_string_lossy()).collect::<Vec<_>>());
+            }
+            report_json(repo, json!({ "commit": value }));
+            return Ok(());
+        }
+        OutputFormat::Porcelain => {
+            let mut records = commit_to_porcelain(&commit);
+            for change in changes.iter() {
+                records.push([vec![String::from("change")], change_to_porcelain(change)].concat());
+            }
+            for (path, _, _) in combined.iter() {
+                records.push(vec![String::from("combined"), path.to_string_lossy().to_string()]);
+            }
+            report_porcelain(repo, records);
+            return Ok(());
+        }
+        OutputFormat::Human => (),
+    }
+
+    repo.report(Event::Output(format!("commit {}", commit.get_hash()), Color::BLUE));
+    if !repo.get_object_format().is_null_hash(commit.get_parent()) {
+        repo.report(Event::Output(format!("Parent: {}", commit.get_parent()), Color::DEFAULT));
+    }
+    if commit.is_merge() {
+        repo.report(Event::Output(format!("Merged: {}", commit.get_merged()), Color::DEFAULT));
+    }
+    if !commit.get_author().is_empty() {
+        repo.report(Event::Output(format!("Author: {}", commit.get_author()), Color::DEFAULT));
+        repo.report(Event::Output(format!("Date:   {}", format_date(commit.get_timestamp())), Color::DEFAULT));
+    }
+    repo.report(Event::Output(String::from(""), Color::DEFAULT));
+    for line in commit.get_description().lines() {
+        repo.report(Event::Output(format!("    {}", line), Color::DEFAULT));
+    }
+    repo.report(Event::Output(String::from(""), Color::DEFAULT));
+
+    match mode {
+        ShowMode::Patch if commit.is_merge() => display_combined_changes(repo, &combined)?,
+        ShowMode::Patch => display_changes(repo, changes, false)?,
+        ShowMode::Stat => display_changes(repo, changes, true)?,
+        ShowMode::NameOnly => {
+            for change in changes.iter() {
+                repo.report(Event::Output(change.get_path().display().to_string(), Color::DEFAULT));
+            }
+        }
+        ShowMode::NameStatus => {
+            for change in changes.iter() {
+                repo.report(Event::Output(change.name_status(), Color::DEFAULT));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Display the content of the file `path` in the commit designated by `revision`, the target of
+/// a symlink, or only the size of a binary file
+fn show_file(repo: &Repository, revision: &str, path: &Path) -> Result<(), DitError> {
+    let commit = Commit::get_commit_from_file(repo, resolve_revision(repo, revision)?)?;
+    let path = normalize_path(path.to_path_buf());
+    let blobs = get_tree_blobs(repo, commit.get_tree())?;
+    let Some((_, hash)) = blobs.iter().find(|(p, _)| *p == path) else {
+        return match Modes::load(repo, commit.get_modes())?.get(&path) {
+            FileMode::Symlink(target) => {
+                repo.report(Event::Output(target.clone(), Color::DEFAULT));
+                Ok(())
+            }
+            _ => Err(DitError::UnexpectedComportement(format!("Path {} is not a file of {}", path.display(), revision))),
+        };
+    };
+
+    let mut content = read_blob(repo, hash)?;
+    if let Some(pointer) = Pointer::parse(&content) {
+        if !pointer.has_content(repo) {
+            return Err(DitError::UnexpectedComportement(format!("Content of the large file {} is missing", path.display())));
+        }
+        content = fs::read(pointer.get_content_path(repo)).map_err(DitError::IoError)?;
+    }
+    if Config::load(repo)?.is_binary(&path, &content) {
+        repo.report(Event::Output(format!("Binary file {} ({} bytes)", path.display(), content.len()), Color::DEFAULT));
+        return Ok(());
+    }
+    for line in String::from_utf8_lossy(&content).lines() {
+        repo.report(Event::Output(line.to_string(), Color::DEFAULT));
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,245 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::error::DitError;
+use crate::features::delete::get_deleted_elements;
+use crate::features::display_message::Color;
+use crate::features::init::get_head_hash;
+use crate::features::output::{change_to_json, change_to_porcelain, OutputFormat, report_json, report_porcelain};
+use crate::objects::branch::Branch;
+use crate::objects::change::{Change, compare_blobs, compare_modes, compare_versions, detect_renames};
+use crate::objects::commit::Commit;
+use crate::objects::config::Config;
+use crate::objects::index::Index;
+use crate::objects::mode::Modes;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{get_indexed_working_blobs, get_tree_blobs, get_working_files, read_blob};
+
+/// Display the staged changes, the unstaged changes and the untracked files
+pub fn status(repo: &Repository, threshold: Option<u8>, format: OutputFormat) -> Result<(), DitError> {
+    let threshold = get_rename_threshold(repo, threshold)?;
+    let head_blobs = get_head_blobs(repo)?;
+    let head_modes = get_head_modes(repo)?;
+    let mut index = Index::load(repo)?;
+    let next_blobs = filter_next_commit_blobs(repo, &head_blobs, &index);
+    let next_modes = filter_next_commit_modes(repo, &head_modes, &next_blobs)?;
+
+    let changes = compare_versions(&head_blobs, &head_modes, &next_blobs, &next_modes);
+    let staged_changes = detect_renames(changes, threshold, |hash| read_blob(repo, hash))?;
+    let unstaged_changes = compare_working_files(repo, &mut index, &next_blobs, &next_modes)?;
+    let untracked_files = filter_untracked_files(repo, &next_blobs, &next_modes)?;
+
+    match format {
+        OutputFormat::Json => {
+            let branch = Branch::get_current_branch(repo)?;
+            report_json(repo, json!({
+                "branch": branch.get_name(),
+                "head": branch.get_head(),
+                "staged": staged_changes.iter().map(change_to_json).collect::<Vec<_>>(),
+                "unstaged": unstaged_changes.iter().map(change_to_json).collect::<Vec<_>>(),
+                "untracked": untracked_files.iter().map(|file| file.to_string_lossy()).collect::<Vec<_>>(),
+            }));
+            return Ok(());
+        }
+        OutputFormat::Porcelain => {
+            let branch = Branch::get_current_branch(repo)?;
+            let mut records = vec![vec![String::from("branch"), branch.get_name().clone(), branch.get_head().clone()]];
+            for change in staged_changes.iter() {
+                records.push([vec![String::from("staged")], change_to_porcelain(change)].concat());
+            }
+            for change in unstaged_changes.iter() {
+                records.push([vec![String::from("unstaged")], change_to_porcelain(change)].concat());
+            }
+            for file in untracked_files.iter() {
+                records.push(vec![String::from("untracked"), file.to_string_lossy().to_string()]);
+            }
+            report_porcelain(repo, records);
+            return Ok(());
+        }
+        OutputFormat::Human => (),
+    }
+
+    if staged_changes.is_empty() && unstaged_changes.is_empty() && untracked_files.is_empty() {
+        repo.report(Event::Output(String::from("Nothing to commit, working tree clean"), Color::DEFAULT));
+        return Ok(());
+    }
+
+    if !staged_changes.is_empty() {
+        repo.report(Event::Output(String::from("Changes to be committed:"), Color::DEFAULT));
+        for change in staged_changes.iter() {
+            repo.report(Event::Output(format!("    {}", change.name_status()), Color::GREEN));
+        }
+    }
+    if !unstaged_changes.is_empty() {
+        repo.report(Event::Output(String::from("Changes not staged for commit:"), Color::DEFAULT));
+        for change in unstaged_changes.iter() {
+            repo.report(Event::Output(format!("    {}", change.name_status()), Color::RED));
+        }
+    }
+    if !untracked_files.is_empty() {
+        repo.report(Event::Output(String::from("Untracked files:"), Color::DEFAULT));
+        for file in untracked_files.iter() {
+            repo.report(Event::Output(format!("    {}", file.display()), Color::RED));
+        }
+    }
+    Ok(())
+}
+
+/// Return the threshold given on the command line, or the one of the repository config
+pub fn get_rename_threshold(repo: &Repository, threshold: Option<u8>) -> Result<u8, DitError> {
+    match threshold {
+        Some(threshold) => Ok(threshold),
+        None => Config::load(repo)?.get_rename_threshold(),
+    }
+}
+
+/// Return the files of the HEAD commit
+pub fn get_head_blobs(repo: &Repository) -> Result<Vec<(PathBuf, String)>, DitError> {
+    let head_hash = get_head_hash(repo)?;
+    if repo.get_object_format().is_null_hash(&head_hash) {
+        return Ok(vec![]);
+    }
+    let commit = Commit::get_commit_from_file(repo, head_hash)?;
+    get_tree_blobs(repo, commit.get_tree())
+}
+
+/// Return the files the next commit will contain: the files of the index, without the
+/// committed files missing from the working directory which were not staged again
+pub fn get_next_commit_blobs(repo: &Repository) -> Result<Vec<(PathBuf, String)>, DitError> {
+    Ok(filter_next_commit_blobs(repo, &get_head_blobs(repo)?, &Index::load(repo)?))
+}
+
+fn filter_next_commit_blobs(repo: &Repository, head_blobs: &[(PathBuf, String)], index: &Index) -> Vec<(PathBuf, String)> {
+    let project_path = repo.get_work_dir();
+    let head_blobs: BTreeMap<&PathBuf, &String> = head_blobs.iter().map(|(path, hash)| (path, hash)).collect();
+    index
+        .get_blobs()
+        .into_iter()
+        .filter(|(path, hash)| head_blobs.get(path) != Some(&hash) || project_path.join(path).exists())
+        .collect()
+}
+
+/// Return the files of the index added or modified since the HEAD commit
+pub fn get_staged_paths(repo: &Repository) -> Result<Vec<PathBuf>, DitError> {
+    let head_blobs: BTreeMap<PathBuf, String> = get_head_blobs(repo)?.into_iter().collect();
+    let paths = Index::load(repo)?
+        .get_blobs()
+        .into_iter()
+        .filter(|(path, hash)| head_blobs.get(path) != Some(hash))
+        .map(|(path, _)| path)
+        .collect();
+    Ok(paths)
+}
+
+/// Whether the files of the index differ from the files of the HEAD commit
+pub fn has_staged_files(repo: &Repository) -> Result<bool, DitError> {
+    let mut head_blobs = get_head_blobs(repo)?;
+    head_blobs.sort();
+    Ok(Index::load(repo)?.get_blobs() != head_blobs)
+}
+
+/// Return the modes recorded by the HEAD commit
+pub fn get_head_modes(repo: &Repository) -> Result<Modes, DitError> {
+    let head_hash = get_head_hash(repo)?;
+    if repo.get_object_format().is_null_hash(&head_hash) {
+        return Ok(Modes::default());
+    }
+    let commit = Commit::get_commit_from_file(repo, head_hash)?;
+    Modes::load(repo, commit.get_modes())
+}
+
+/// Return the modes the next commit will record: HEAD modes updated with the staged ones, for
+/// the entries the next commit will contain
+pub fn get_next_commit_modes(repo: &Repository) -> Result<Modes, DitError> {
+    filter_next_commit_modes(repo, &get_head_modes(repo)?, &get_next_commit_blobs(repo)?)
+}
+
+fn filter_next_commit_modes(repo: &Repository, head_modes: &Modes, next_blobs: &[(PathBuf, String)]) -> Result<Modes, DitError> {
+    let project_path = repo.get_work_dir();
+    let deleted: Vec<PathBuf> = get_deleted_elements(repo)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+    let mut modes = head_modes.clone();
+    modes.overlay(&Modes::load_staged(repo)?);
+    modes.retain(|path, mode| {
+        !deleted.iter().any(|deleted| path.starts_with(deleted))
+            && (!mode.is_special() || fs::symlink_metadata(project_path.join(path)).is_ok())
+    });
+    modes.retain_tracked(next_blobs);
+    Ok(modes)
+}
+
+/// Return the changes between the HEAD commit and the next commit
+pub fn get_staged_changes(repo: &Repository, threshold: u8) -> Result<Vec<Change>, DitError> {
+    let changes = compare_versions(
+        &get_head_blobs(repo)?,
+        &get_head_modes(repo)?,
+        &get_next_commit_blobs(repo)?,
+        &get_next_commit_modes(repo)?,
+    );
+    detect_renames(changes, threshold, |hash| read_blob(repo, hash))
+}
+
+/// Return the tracked files modified in the working directory since they were staged or
+/// committed, and the tracked entries whose mode changed
+pub fn get_unstaged_changes(repo: &Repository) -> Result<Vec<Change>, DitError> {
+    let mut index = Index::load(repo)?;
+    let next_blobs = filter_next_commit_blobs(repo, &get_head_blobs(repo)?, &index);
+    let next_modes = filter_next_commit_modes(repo, &get_head_modes(repo)?, &next_blobs)?;
+    compare_working_files(repo, &mut index, &next_blobs, &next_modes)
+}
+
+fn compare_working_files(repo: &Repository, index: &mut Index, next_blobs: &[(PathBuf, String)], next_modes: &Modes) -> Result<Vec<Change>, DitError> {
+    let project_path = repo.get_work_dir();
+    let tracked: Vec<(PathBuf, String)> = next_blobs
+        .iter()
+        .filter(|(path, _)| project_path.join(path).is_file())
+        .cloned()
+        .collect();
+    let paths: Vec<PathBuf> = tracked.iter().map(|(path, _)| path.clone()).collect();
+
+    let working_blobs = get_indexed_working_blobs(repo, index, paths.clone())?;
+    index.try_save(repo)?;
+
+    let mut mode_paths = paths;
+    mode_paths.extend(next_modes.get_special_paths());
+    let working_modes = Modes::from_working(repo, &mode_paths)?;
+
+    let mut changes: Vec<Change> = compare_blobs(&tracked, &working_blobs)
+        .into_iter()
+        .filter(|change| matches!(change, Change::Modified { .. }))
+        .collect();
+    changes.extend(
+        compare_modes(&tracked, next_modes, &working_blobs, &working_modes)
+            .into_iter()
+            .filter(|change| matches!(change, Change::ModeChanged { old_mode: Some(_), new_mode: Some(_), .. })),
+    );
+    changes.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+    Ok(changes)
+}
+
+/// Return the files of the working directory which are neither committed nor staged
+pub fn get_untracked_files(repo: &Repository) -> Result<Vec<PathBuf>, DitError> {
+    let next_blobs = get_next_commit_blobs(repo)?;
+    let next_modes = filter_next_commit_modes(repo, &get_head_modes(repo)?, &next_blobs)?;
+    filter_untracked_files(repo, &next_blobs, &next_modes)
+}
+
+fn filter_untracked_files(repo: &Repository, next_blobs: &[(PathBuf, String)], next_modes: &Modes) -> Result<Vec<PathBuf>, DitError> {
+    let mut tracked: HashSet<PathBuf> = next_blobs.iter().map(|(path, _)| path.clone()).collect();
+    tracked.extend(next_modes.get_special_paths());
+
+    let mut untracked: Vec<PathBuf> = get_working_files(repo)?
+        .into_iter()
+        .filter(|file| !tracked.contains(file))
+        .collect();
+    untracked.sort();
+    Ok(untracked)
+}
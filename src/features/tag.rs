@@ -0,0 +1,55 @@
+use serde_json::json;
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::output::{OutputFormat, report_json, report_porcelain};
+use crate::features::revision::resolve_revision;
+use crate::objects::config::{Config, TAG_SIGN};
+use crate::objects::reflog::HEAD_LOG;
+use crate::objects::tag::Tag;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Tag the commit designated by `revision`, HEAD by default, with a signed tag object when
+/// `sign` or `tag.sign` is true
+pub fn new_tag(repo: &Repository, name: &String, revision: Option<&String>, sign: bool) -> Result<(), DitError> {
+    let target = resolve_revision(repo, revision.map(|r| r.as_str()).unwrap_or(HEAD_LOG))?;
+    if repo.get_object_format().is_null_hash(&target) {
+        return Err(DitError::NoCommit);
+    }
+    if sign || Config::load(repo)?.get_bool(TAG_SIGN)? {
+        Tag::new_signed_tag(repo, name.to_owned(), target)?;
+    } else {
+        Tag::new_tag(repo, name.to_owned(), target)?;
+    }
+    repo.report(Event::Message(format!("tag {} created.", name), Color::GREEN));
+    Ok(())
+}
+
+/// Display every tag with the commit it designates
+pub fn list_tags(repo: &Repository, format: OutputFormat) -> Result<(), DitError> {
+    let tags = Tag::get_tags(repo)?;
+
+    match format {
+        OutputFormat::Json => {
+            let values: Vec<_> = tags
+                .iter()
+                .map(|tag| json!({ "name": tag.get_name(), "target": tag.get_target() }))
+                .collect();
+            report_json(repo, json!({ "tags": values }));
+        }
+        OutputFormat::Porcelain => {
+            let records = tags
+                .iter()
+                .map(|tag| vec![String::from("tag"), tag.get_name().clone(), tag.get_target().clone()])
+                .collect();
+            report_porcelain(repo, records);
+        }
+        OutputFormat::Human => {
+            for tag in tags.iter() {
+                repo.report(Event::Output(tag.get_name().clone(), Color::DEFAULT));
+            }
+        }
+    }
+    Ok(())
+}
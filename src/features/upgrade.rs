@@ -0,0 +1,491 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use dit_file_encryptor::CompressedFile;
+use repository_tree_creator::features::get_repository_tree_from_object_files::get_repository_tree_from_object_files;
+use repository_tree_creator::models::node::Node as LegacyNode;
+use repository_tree_creator::models::tree::Tree as LegacyTree;
+
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::delete::get_deleted_elements;
+use crate::features::status::get_head_blobs;
+use crate::objects::branch::Branch;
+use crate::objects::commit::Commit;
+use crate::objects::index::Index;
+use crate::objects::lock::LockFile;
+use crate::objects::reflog::{now, ReflogEntry};
+use crate::objects::store::{create_object_dirs, save_tree, LooseObjectStore, ObjectStore};
+use crate::objects::tag::Tag;
+use crate::objects::tree::{create_tree, Tree};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{get_tree_blobs, read_hash_file, ScratchDir, SCRATCH_DIR_PREFIX};
+
+/// Layout of the files of `.dit`, written in `.dit/format` and checked when a repository is
+/// opened.
+///
+/// Layout 1, from before the file existed, lists every commit of a branch in its ref and
+/// writes HEAD as `HEAD <hash> <branch>` in the info file. Layout 2 only keeps the tip of a
+/// branch in its ref and writes `branch <name>` and `head <hash>` lines in the info file.
+/// Layout 3 writes the trees and blobs with a type header and hashes them with the object
+/// format of the repository, instead of the headerless objects of `repository_tree_creator`,
+/// and records the files of the next commit in the index instead of a staged tree in
+/// `.dit/staged`.
+pub const LAYOUT_VERSION: u32 = 3;
+const FIRST_LAYOUT_VERSION: u32 = 1;
+
+const BACKUP_DIR: &str = "backup";
+/// Position of the hash of the staged tree in `.dit/staged`, before layout 3
+const STAGED_HASH_POSITION: usize = 0;
+
+/// Return the layout version of the repository
+pub fn get_layout_version(repo: &Repository) -> Result<u32, DitError> {
+    let format_path = repo.get_format_path();
+    if !format_path.is_file() {
+        return Ok(FIRST_LAYOUT_VERSION);
+    }
+    let content = fs::read_to_string(format_path).map_err(DitError::IoError)?;
+    content
+        .trim()
+        .parse::<u32>()
+        .map_err(|_| DitError::UnexpectedComportement(format!("Invalid layout version {} in .dit/format", content.trim())))
+}
+
+pub fn set_layout_version(repo: &Repository, version: u32) -> Result<(), DitError> {
+    let lock = LockFile::acquire(&repo.get_format_path())?;
+    lock.write_plain(format!("{}\n", version).as_bytes())?;
+    lock.commit()
+}
+
+/// Fail when the repository was written with another layout than the one of this version of dit
+pub fn check_layout_version(repo: &Repository) -> Result<(), DitError> {
+    match get_layout_version(repo)? {
+        LAYOUT_VERSION => Ok(()),
+        version if version < LAYOUT_VERSION => Err(DitError::OutdatedRepository(version)),
+        version => Err(DitError::UnsupportedRepositoryFormat(format!("layout version {}", version))),
+    }
+}
+
+/// Migrate the repository to the current layout. The files of `.dit`, except the objects
+/// which are not changed, are first copied to `.dit/backup/layout-<version>-<timestamp>`.
+pub fn upgrade(repo: &Repository) -> Result<(), DitError> {
+    let version = get_layout_version(repo)?;
+    if version > LAYOUT_VERSION {
+        return Err(DitError::UnsupportedRepositoryFormat(format!("layout version {}", version)));
+    }
+    if version == LAYOUT_VERSION {
+        repo.report(Event::Message(format!("Repository already uses layout {}", LAYOUT_VERSION), Color::BLUE));
+        return Ok(());
+    }
+
+    let backup_path = backup(repo, version)?;
+    if version < 2 {
+        upgrade_to_layout_2(repo)?;
+    }
+    if version < 3 {
+        upgrade_to_layout_3(repo)?;
+    }
+    set_layout_version(repo, LAYOUT_VERSION)?;
+
+    let message = format!(
+        "Repository upgraded from layout {} to {}, previous files saved in {}",
+        version,
+        LAYOUT_VERSION,
+        backup_path.display()
+    );
+    repo.report(Event::Message(message, Color::GREEN));
+    Ok(())
+}
+
+fn backup(repo: &Repository, version: u32) -> Result<PathBuf, DitError> {
+    let backup_path = repo
+        .get_dit_path()
+        .join(BACKUP_DIR)
+        .join(format!("layout-{}-{}", version, now()));
+    repo.report(Event::StepStarted(format!("Saving .dit to {}", backup_path.display())));
+
+    fs::create_dir_all(&backup_path).map_err(DitError::IoError)?;
+    for entry in fs::read_dir(repo.get_dit_path()).map_err(DitError::IoError)? {
+        let entry = entry.map_err(DitError::IoError)?;
+        let is_scratch = entry.file_name().to_string_lossy().starts_with(SCRATCH_DIR_PREFIX);
+        if entry.path() == repo.get_objects_path() || entry.file_name() == BACKUP_DIR || is_scratch {
+            continue;
+        }
+        copy_recursively(&entry.path(), &backup_path.join(entry.file_name()))?;
+    }
+
+    repo.report(Event::StepFinished(format!("Saved .dit to {}", backup_path.display())));
+    Ok(backup_path)
+}
+
+fn copy_recursively(source: &Path, destination: &Path) -> Result<(), DitError> {
+    if source.is_dir() {
+        fs::create_dir_all(destination).map_err(DitError::IoError)?;
+        for entry in fs::read_dir(source).map_err(DitError::IoError)? {
+            let entry = entry.map_err(DitError::IoError)?;
+            copy_recursively(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(source, destination).map_err(DitError::IoError)?;
+    }
+    Ok(())
+}
+
+/// Keep only the tip in the refs and write the info file as lines
+fn upgrade_to_layout_2(repo: &Repository) -> Result<(), DitError> {
+    repo.report(Event::StepStarted(String::from("Rewriting refs and info file")));
+
+    for branch in Branch::get_branches(repo)? {
+        let listed = read_listed_commits(repo, branch.get_name())?;
+        let mut ancestors: BTreeSet<String> = BTreeSet::new();
+        let mut hash = branch.get_head().clone();
+        while !repo.get_object_format().is_null_hash(&hash) && ancestors.insert(hash.clone()) {
+            hash = Commit::get_commit_from_file(repo, hash)?.get_parent().clone();
+        }
+
+        // A revert followed by a commit leaves commits in the list which are no longer
+        // ancestors of the tip, the reflog keeps them reachable
+        for hash in listed.into_iter().filter(|hash| !ancestors.contains(hash)) {
+            ReflogEntry::new(repo, hash.clone(), hash.clone(), String::from("upgrade: no longer an ancestor of the tip"))
+                .append(repo, branch.get_name())?;
+            repo.report(Event::Warning(format!("Commit {} of branch {} is only kept in its reflog", hash, branch.get_name())));
+        }
+        Branch::write_ref(repo, branch.get_name(), branch.get_head())?;
+    }
+
+    let mut reader = CompressedFile::new(repo.get_info_path())
+        .open_for_read()
+        .map_err(DitError::IoError)?;
+    let mut content = String::new();
+    reader.read_to_string(&mut content).map_err(DitError::IoError)?;
+    // The info file is already converted when a previous upgrade was interrupted
+    if let ["HEAD", head, name] = content.split_whitespace().collect::<Vec<&str>>()[..] {
+        Branch::set_info_file(repo, name.to_string(), head.to_string())?;
+    } else {
+        Branch::read_info_file(repo)?;
+    }
+
+    repo.report(Event::StepFinished(String::from("Rewrote refs and info file")));
+    Ok(())
+}
+
+fn read_listed_commits(repo: &Repository, name: &String) -> Result<Vec<String>, DitError> {
+    let reader = CompressedFile::new(repo.get_refs_path().join(name))
+        .open_for_read()
+        .map_err(DitError::IoError)?;
+    let mut hashes: Vec<String> = Vec::new();
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(DitError::IoError)?;
+        if !line.trim().is_empty() {
+            hashes.push(line.trim().to_string());
+        }
+    }
+    Ok(hashes)
+}
+
+/// Write the trees and blobs of the commits named by the branches, HEAD, the tags and the
+/// reflogs, and of the staged tree, again as typed objects, then the commits naming them, the
+/// refs naming the commits and the index. The previous objects are left to `gc`.
+///
+/// Signed commits and signed tags whose content changes lose their signature, which no longer
+/// matches, and the signed tags become lightweight tags.
+fn upgrade_to_layout_3(repo: &Repository) -> Result<(), DitError> {
+    repo.report(Event::StepStarted(String::from("Rewriting trees and blobs")));
+
+    // `repository_tree_creator` reads the trees from a directory of loose objects, which the
+    // packed and encrypted objects are not
+    let scratch = ScratchDir::create(repo)?;
+    create_object_dirs(scratch.get_path()).map_err(DitError::IoError)?;
+    let legacy = LooseObjectStore::new(scratch.get_path().clone());
+    for hash in repo.get_store().iterate()? {
+        legacy.write(&hash, &repo.get_store().read(&hash)?)?;
+    }
+
+    let mut rewriter = LayoutRewriter {
+        repo,
+        legacy_path: scratch.get_path().clone(),
+        trees: BTreeMap::new(),
+        blobs: BTreeMap::new(),
+        commits: BTreeMap::new(),
+    };
+
+    for branch in Branch::get_branches(repo)? {
+        let head = rewriter.rewrite_commit(branch.get_head())?;
+        if head != *branch.get_head() {
+            Branch::write_ref(repo, branch.get_name(), &head)?;
+        }
+    }
+
+    let (name, head) = Branch::read_info_file(repo)?;
+    let new_head = rewriter.rewrite_commit(&head)?;
+    if new_head != head {
+        Branch::set_info_file(repo, name, new_head)?;
+    }
+
+    for tag in Tag::get_tags(repo)? {
+        let target = rewriter.rewrite_commit(tag.get_target())?;
+        if target == *tag.get_target() {
+            continue;
+        }
+        if tag.get_object().is_some() {
+            repo.report(Event::Warning(format!("Tag {} is no longer signed, its commit was rewritten", tag.get_name())));
+        }
+        let lock = LockFile::acquire(&repo.get_tags_path().join(tag.get_name()))?;
+        lock.write(repo, target.as_bytes())?;
+        lock.commit()?;
+    }
+
+    for name in ReflogEntry::get_log_names(repo)? {
+        let mut entries = ReflogEntry::get_entries(repo, &name)?;
+        for entry in entries.iter_mut() {
+            let old = rewriter.rewrite_commit(entry.get_old())?;
+            let new = rewriter.rewrite_commit(entry.get_new())?;
+            entry.set_old(old);
+            entry.set_new(new);
+        }
+        ReflogEntry::write_entries(repo, &name, &entries)?;
+    }
+
+    let staged_blobs = match read_hash_file(repo, repo.get_staged_path(), STAGED_HASH_POSITION) {
+        Ok(staged_hash) if !repo.get_object_format().is_null_hash(&staged_hash) => get_tree_blobs(repo, &rewriter.rewrite_tree(&staged_hash)?)?,
+        _ => vec![],
+    };
+
+    // The index cached the previous ids of the files, it now holds the files of the next
+    // commit, which were the files of HEAD updated by the staged tree
+    if repo.get_index_path().is_file() {
+        fs::remove_file(repo.get_index_path()).map_err(DitError::IoError)?;
+    }
+    let deleted = get_deleted_elements(repo)?.unwrap_or_default();
+    let mut blobs: BTreeMap<PathBuf, String> = get_head_blobs(repo)?.into_iter().collect();
+    blobs.extend(staged_blobs);
+    blobs.retain(|path, _| !deleted.contains(&path.to_string_lossy().to_string()));
+    let mut index = Index::default();
+    index.reset(&blobs.into_iter().collect::<Vec<_>>());
+    index.save(repo)?;
+    if repo.get_staged_path().is_file() {
+        fs::remove_file(repo.get_staged_path()).map_err(DitError::IoError)?;
+    }
+
+    repo.report(Event::StepFinished(String::from("Rewrote trees and blobs")));
+    Ok(())
+}
+
+/// Ids of the trees, blobs and commits rewritten by `upgrade_to_layout_3`, by previous id
+struct LayoutRewriter<'a> {
+    repo: &'a Repository,
+    legacy_path: PathBuf,
+    trees: BTreeMap<String, String>,
+    blobs: BTreeMap<String, String>,
+    commits: BTreeMap<String, String>,
+}
+
+impl LayoutRewriter<'_> {
+    /// Rewrite the commit `hash` and its ancestors, parents and merged parents before their
+    /// children, and return its new id
+    fn rewrite_commit(&mut self, hash: &String) -> Result<String, DitError> {
+        let mut pending: Vec<(Commit, bool)> = Vec::new();
+        if !self.repo.get_object_format().is_null_hash(hash) && !self.commits.contains_key(hash) {
+            pending.push((Commit::get_commit_from_file(self.repo, hash.clone())?, false));
+        }
+        while let Some((commit, ready)) = pending.pop() {
+            if self.commits.contains_key(commit.get_hash()) {
+                continue;
+            }
+            if !ready {
+                let parents = [commit.get_parent().clone(), commit.get_merged().clone()];
+                pending.push((commit, true));
+                for parent in parents.iter() {
+                    if !parent.is_empty() && !self.repo.get_object_format().is_null_hash(parent) && !self.commits.contains_key(parent) {
+                        pending.push((Commit::get_commit_from_file(self.repo, parent.clone())?, false));
+                    }
+                }
+                continue;
+            }
+
+            let parent = self.get_commit(commit.get_parent());
+            let merged = self.get_commit(commit.get_merged());
+            let tree = self.rewrite_tree(commit.get_tree())?;
+            let new_hash = if parent == *commit.get_parent() && merged == *commit.get_merged() && tree == *commit.get_tree() {
+                commit.get_hash().clone()
+            } else {
+                if !commit.get_signature().is_empty() {
+                    self.repo.report(Event::Warning(format!("Commit {} is no longer signed, its tree was rewritten", commit.get_hash())));
+                }
+                let mut rewritten = Commit::new(self.repo, tree, commit.get_modes().clone(), parent, commit.get_description().clone());
+                if !commit.get_author().is_empty() {
+                    rewritten.set_author(commit.get_author().clone(), commit.get_timestamp());
+                }
+                if commit.is_merge() {
+                    rewritten.set_merged(merged);
+                }
+                rewritten.write_object(self.repo)?;
+                rewritten.get_hash().clone()
+            };
+            self.commits.insert(commit.get_hash().clone(), new_hash);
+        }
+        Ok(self.get_commit(hash))
+    }
+
+    fn get_commit(&self, hash: &String) -> String {
+        self.commits.get(hash).cloned().unwrap_or_else(|| hash.clone())
+    }
+
+    /// Rewrite the tree `hash` and its blobs, return its new id
+    fn rewrite_tree(&mut self, hash: &String) -> Result<String, DitError> {
+        if let Some(new_hash) = self.trees.get(hash) {
+            return Ok(new_hash.clone());
+        }
+
+        let mut legacy_tree = LegacyTree::default();
+        get_repository_tree_from_object_files(&mut legacy_tree, hash, &self.legacy_path).map_err(|e| {
+            DitError::UnexpectedComportement(format!("Error reading tree {}: {}", hash, e))
+        })?;
+        let mut legacy_blobs: Vec<(PathBuf, String)> = Vec::new();
+        collect_legacy_blobs(&mut LegacyNode::TreeNode(legacy_tree), &mut legacy_blobs);
+
+        let mut blobs: Vec<(PathBuf, String)> = Vec::new();
+        for (path, blob_hash) in legacy_blobs {
+            let new_blob_hash = match self.blobs.get(&blob_hash) {
+                Some(new_blob_hash) => new_blob_hash.clone(),
+                None => {
+                    let content = self.repo.get_store().read(&blob_hash)?;
+                    let (new_blob_hash, _) = self.repo.get_store().write_blob(self.repo.get_object_format(), &content)?;
+                    self.blobs.insert(blob_hash, new_blob_hash.clone());
+                    new_blob_hash
+                }
+            };
+            blobs.push((path, new_blob_hash));
+        }
+
+        let new_hash = save_tree(self.repo, &mut create_tree(Tree::default(), &blobs))?;
+        self.trees.insert(hash.clone(), new_hash.clone());
+        Ok(new_hash)
+    }
+}
+
+fn collect_legacy_blobs(node: &mut LegacyNode, blobs: &mut Vec<(PathBuf, String)>) {
+    match node {
+        LegacyNode::TreeNode(ref mut tree) => {
+            for child in tree.get_mut_children().iter_mut() {
+                collect_legacy_blobs(child, blobs);
+            }
+        }
+        blob => blobs.push((blob.get_path(), blob.get_id())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+
+    use repository_tree_creator::features::create_repository_tree::create_repository_tree;
+    use repository_tree_creator::features::transcript_repository_tree_to_object_files::transcript_repository_to_object_files;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn write_compressed(path: PathBuf, content: &str) {
+        let mut writer = CompressedFile::create_file(path).unwrap().open_for_write().unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+
+    /// Commit `files` like the first versions of dit, with the headerless trees and blobs of
+    /// `repository_tree_creator` and a headerless commit
+    fn legacy_commit(repo: &Repository, files: &[(&str, &str)], parent: &str, description: &str) -> String {
+        for (path, content) in files.iter() {
+            let path = repo.get_work_dir().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        // `repository_tree_creator` reads the paths from the current directory
+        let current_dir = env::current_dir().unwrap();
+        env::set_current_dir(repo.get_work_dir()).unwrap();
+        let root = create_repository_tree(LegacyTree::default(), files.iter().map(|(path, _)| PathBuf::from(path)).collect());
+        env::set_current_dir(current_dir).unwrap();
+        let root = root.unwrap();
+        transcript_repository_to_object_files(&root, &repo.get_objects_path()).unwrap();
+
+        let tree = root.get_id();
+        let hash = repo.get_object_format().hash(&format!("{}{}{}", tree, parent, description));
+        let content = format!("tree {}\npare {}\n{}", tree, parent, description);
+        write_compressed(repo.get_objects_path().join(&hash[..2]).join(&hash[2..]), &content);
+        hash
+    }
+
+    /// Repository of layout 1 whose branch `main` has two commits, listed in its ref
+    fn legacy_repository(dir: &TempDir) -> Repository {
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::remove_file(repo.get_format_path()).unwrap();
+        let null_hash = repo.get_null_hash();
+        let first = legacy_commit(&repo, &[("a.txt", "one\n")], &null_hash, "first");
+        let second = legacy_commit(&repo, &[("a.txt", "two\n"), ("dir/b.txt", "b\n")], &first, "second");
+        write_compressed(repo.get_refs_path().join("main"), &format!("{}\n{}\n", first, second));
+        write_compressed(repo.get_info_path(), &format!("HEAD {} main", second));
+        write_compressed(repo.get_staged_path(), &null_hash);
+        repo
+    }
+
+    #[test]
+    fn repository_of_the_first_layout_is_upgraded() {
+        let dir = TempDir::new("upgrade").unwrap();
+        let repo = legacy_repository(&dir);
+        assert!(matches!(Repository::open(dir.path()), Err(DitError::OutdatedRepository(1))));
+        upgrade(&repo).unwrap();
+
+        let repo = Repository::open(dir.path()).unwrap();
+        assert_eq!(get_layout_version(&repo).unwrap(), LAYOUT_VERSION);
+        let (name, head) = Branch::read_info_file(&repo).unwrap();
+        assert_eq!(name, "main");
+        let head = Commit::get_commit_from_file(&repo, head).unwrap();
+        assert_eq!(head.get_description(), "second");
+        let parent = Commit::get_commit_from_file(&repo, head.get_parent().clone()).unwrap();
+        assert_eq!(parent.get_description(), "first");
+        assert!(repo.get_object_format().is_null_hash(parent.get_parent()));
+
+        let blobs = get_tree_blobs(&repo, head.get_tree()).unwrap();
+        let files: Vec<(PathBuf, Vec<u8>)> = blobs
+            .iter()
+            .map(|(path, hash)| (path.clone(), repo.get_store().read_blob(hash).unwrap()))
+            .collect();
+        assert_eq!(files, vec![(PathBuf::from("a.txt"), b"two\n".to_vec()), (PathBuf::from("dir/b.txt"), b"b\n".to_vec())]);
+        assert_eq!(get_head_blobs(&repo).unwrap(), blobs);
+        assert_eq!(Index::load(&repo).unwrap().get_entries().len(), 2);
+    }
+
+    #[test]
+    fn merged_parents_are_rewritten_with_the_merges() {
+        let dir = TempDir::new("upgrade").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::remove_file(repo.get_format_path()).unwrap();
+        let null_hash = repo.get_null_hash();
+        let first = legacy_commit(&repo, &[("a.txt", "one\n")], &null_hash, "first");
+        let other = legacy_commit(&repo, &[("a.txt", "other\n")], &first, "other");
+        let main = legacy_commit(&repo, &[("a.txt", "main\n")], &first, "main");
+
+        let merge = Commit::get_commit_from_file(&repo, main.clone()).unwrap();
+        let content = format!("tree {}\npare {}\nmrge {}\nmerge", merge.get_tree(), main, other);
+        let hash = repo.get_object_format().hash(&format!("{}{}{}merge", merge.get_tree(), main, other));
+        write_compressed(repo.get_objects_path().join(&hash[..2]).join(&hash[2..]), &content);
+        write_compressed(repo.get_refs_path().join("main"), &format!("{}\n{}\n{}\n", first, main, hash));
+        write_compressed(repo.get_info_path(), &format!("HEAD {} main", hash));
+        write_compressed(repo.get_staged_path(), &null_hash);
+        upgrade(&repo).unwrap();
+
+        let repo = Repository::open(dir.path()).unwrap();
+        let (_, head) = Branch::read_info_file(&repo).unwrap();
+        let head = Commit::get_commit_from_file(&repo, head).unwrap();
+        assert!(head.is_merge());
+        let merged = Commit::get_commit_from_file(&repo, head.get_merged().clone()).unwrap();
+        assert_ne!(*merged.get_hash(), other);
+        assert_eq!(merged.get_description(), "other");
+        let blobs = get_tree_blobs(&repo, merged.get_tree()).unwrap();
+        assert_eq!(repo.get_store().read_blob(&blobs[0].1).unwrap(), b"other\n");
+        let parent = Commit::get_commit_from_file(&repo, head.get_parent().clone()).unwrap();
+        assert_eq!(merged.get_parent(), parent.get_parent());
+    }
+}
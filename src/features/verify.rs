@@ -0,0 +1,33 @@
+use crate::error::DitError;
+use crate::features::display_message::Color;
+use crate::features::revision::resolve_revision;
+use crate::objects::commit::Commit;
+use crate::objects::signature::{verify, Verification};
+use crate::objects::tag::Tag;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Check the signature of the commit designated by `revision` against the allowed signers
+/// file, return `UnverifiedSignature` unless it is a good signature
+pub fn verify_commit(repo: &Repository, revision: &str) -> Result<(), DitError> {
+    let hash = resolve_revision(repo, revision)?;
+    let commit = Commit::get_commit_from_file(repo, hash)?;
+    let verification = verify(repo, &commit.get_payload(), commit.get_signature())?;
+    report_verification(repo, format!("commit {}", commit.get_hash()), verification)
+}
+
+/// Check the signature of the tag `name` against the allowed signers file, return
+/// `UnverifiedSignature` unless it is a good signature
+pub fn verify_tag(repo: &Repository, name: &String) -> Result<(), DitError> {
+    let tag = Tag::get_tag(repo, name)?;
+    let verification = verify(repo, &tag.get_payload(), tag.get_signature())?;
+    report_verification(repo, format!("tag {}", name), verification)
+}
+
+fn report_verification(repo: &Repository, name: String, verification: Verification) -> Result<(), DitError> {
+    if !verification.is_good() {
+        return Err(DitError::UnverifiedSignature { name, reason: verification.describe() });
+    }
+    repo.report(Event::Message(verification.describe(), Color::GREEN));
+    Ok(())
+}
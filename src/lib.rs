@@ -3,3 +3,6 @@ pub mod utils;
 pub mod objects;
 pub mod error;
 pub mod process_path;
+pub mod parallel;
+pub mod reporter;
+pub mod repository;
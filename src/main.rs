@@ -1,28 +1,102 @@
-use std::{fs, process};
+use std::{env, process};
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgGroup, ArgMatches, Command, value_parser};
 
-use dit::features;
+use dit::error::DitError;
 use dit::features::add;
+use dit::features::cat_file::{cat_file, CatFileMode};
 use dit::features::checkout::checkout;
-use dit::features::commit::commit;
-use dit::features::create_branch::new_branch;
+use dit::features::commit::{amend, commit, stage_tracked_changes};
+use dit::features::config::config;
+use dit::features::create_branch::{list_branches, new_branch};
+use dit::features::diff::diff;
 use dit::features::display_message::Color;
 use dit::features::display_message::display_message;
+use dit::features::fsck::fsck;
+use dit::features::gc::{gc, prune};
+use dit::features::key::rotate_key;
+use dit::features::lfs::ls_files;
+use dit::features::log::log;
+use dit::features::ls_tree::ls_tree;
 use dit::features::merge::merge;
 use dit::features::message::message;
+use dit::features::mv::mv;
+use dit::features::output::OutputFormat;
+use dit::features::reflog::{delete_reflog_entry, expire_reflog, show_reflog};
 use dit::features::revert::revert;
+use dit::features::restore;
 use dit::features::rm;
-use dit::features::show::show_commit;
+use dit::features::show::{show, show_commit, ShowMode};
+use dit::features::status::status;
+use dit::features::tag::{list_tags, new_tag};
+use dit::features::upgrade::upgrade;
+use dit::features::verify::{verify_commit, verify_tag};
+use dit::objects::config::Config;
+use dit::objects::crypt::{KeySource, NEW_PASSPHRASE_VARIABLE};
+use dit::objects::hash::ObjectFormat;
+use dit::objects::reflog::HEAD_LOG;
+use dit::reporter::{Event, Reporter};
+use dit::repository::Repository;
+
+use crate::terminal_reporter::{TerminalReporter, Verbosity};
+
+mod terminal_reporter;
 
 fn main() {
     let matches = Command::new("cli")
         .version("0.1.0")
         .author("Schaeffer divino divinoschaeffer@gmail.com")
         .about("Local version control tool: Divino Information Tracker ")
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .required(false)
+                .num_args(0)
+                .conflicts_with("verbose")
+                .help("Only print results and errors"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .required(false)
+                .num_args(0)
+                .help("Also print every step, written object and checked out file"),
+        )
         .subcommands([
-            Command::new("init").about("Initialize dit repository"),
+            Command::new("init")
+                .about("Initialize dit repository")
+                .arg(
+                    Arg::new("object-format")
+                        .long("object-format")
+                        .required(false)
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["sha1", "sha256"])
+                        .default_value("sha1")
+                        .help("Hash algorithm of the object ids"),
+                )
+                .arg(
+                    Arg::new("encrypt")
+                        .long("encrypt")
+                        .required(false)
+                        .num_args(0)
+                        .help("Encrypt the objects and the files of the repository with the key given by DIT_PASSPHRASE or --key-file"),
+                )
+                .arg(
+                    Arg::new("key-file")
+                        .long("key-file")
+                        .required(false)
+                        .num_args(1)
+                        .value_name("FILE")
+                        .requires("encrypt")
+                        .help("File holding the key of the encrypted repository"),
+                ),
             Command::new("add").about("Index file(s)").arg(
                 Arg::new("files")
                     .help("files to add")
@@ -31,14 +105,40 @@ fn main() {
                     .num_args(0..)
                     .value_name("FILE(S)"),
             ),
-            Command::new("rm").about("Remove index file(s)").arg(
-                Arg::new("files")
-                    .help("Files to remove")
-                    .index(1)
-                    .required(true)
-                    .num_args(0..)
-                    .value_name("FILE(S)"),
-            ),
+            Command::new("rm")
+                .about("Remove file(s) from the working directory and from the next commit")
+                .arg(
+                    Arg::new("files")
+                        .help("Files to remove")
+                        .index(1)
+                        .required(true)
+                        .num_args(0..)
+                        .value_name("FILE(S)"),
+                )
+                .arg(
+                    Arg::new("cached")
+                        .long("cached")
+                        .required(false)
+                        .num_args(0)
+                        .help("Stop tracking file(s) but keep them in the working directory"),
+                ),
+            Command::new("restore")
+                .about("Restore file(s)")
+                .arg(
+                    Arg::new("files")
+                        .help("Files to restore")
+                        .index(1)
+                        .required(true)
+                        .num_args(0..)
+                        .value_name("FILE(S)"),
+                )
+                .arg(
+                    Arg::new("staged")
+                        .long("staged")
+                        .required(true)
+                        .num_args(0)
+                        .help("Remove index file(s)"),
+                ),
             Command::new("commit")
                 .about("Commit")
                 .arg(
@@ -61,17 +161,96 @@ fn main() {
                         .short('r')
                         .required(false)
                         .num_args(1)
-                        .value_name("COMMIT ID")
-                        .help("Revert files to their state at a specified commit."),
+                        .value_name("REVISION")
+                        .help("Revert files to their state at a specified commit, e.g. a hash, main@{1} or @{yesterday}."),
+                )
+                .arg(
+                    Arg::new("amend")
+                        .long("amend")
+                        .required(false)
+                        .num_args(0)
+                        .help("Replace the last commit with a new one"),
+                )
+                .arg(
+                    Arg::new("all")
+                        .short('a')
+                        .long("all")
+                        .required(false)
+                        .num_args(0)
+                        .conflicts_with_all(["show", "revert"])
+                        .help("Stage modified and deleted tracked files before committing"),
+                )
+                .arg(
+                    Arg::new("no-edit")
+                        .long("no-edit")
+                        .required(false)
+                        .num_args(0)
+                        .requires("amend")
+                        .help("Keep the message of the amended commit"),
+                )
+                .args(output_format_args().map(|arg| arg.requires("show"))),
+            Command::new("branch")
+                .about("Create a branch, or list the branches without NAME")
+                .arg(
+                    Arg::new("branch")
+                        .num_args(1)
+                        .index(1)
+                        .required(false)
+                        .conflicts_with_all(["porcelain", "json"])
+                        .value_name("NAME")
+                        .help("Create a new branch and switch to the branch"),
+                )
+                .args(output_format_args()),
+            Command::new("tag")
+                .about("Create a tag, or list the tags without NAME")
+                .arg(
+                    Arg::new("name")
+                        .num_args(1)
+                        .index(1)
+                        .required(false)
+                        .conflicts_with_all(["porcelain", "json"])
+                        .value_name("NAME")
+                        .help("Name of the new tag"),
+                )
+                .arg(
+                    Arg::new("revision")
+                        .num_args(1)
+                        .index(2)
+                        .required(false)
+                        .requires("name")
+                        .value_name("REVISION")
+                        .help("Commit to tag, HEAD by default"),
+                )
+                .arg(
+                    Arg::new("sign")
+                        .short('s')
+                        .long("sign")
+                        .required(false)
+                        .num_args(0)
+                        .requires("name")
+                        .help("Create a tag object signed with the key of user.signingKey"),
+                )
+                .args(output_format_args()),
+            Command::new("verify-commit")
+                .about("Check the signature of a commit against the allowed signers")
+                .arg(
+                    Arg::new("revision")
+                        .num_args(1)
+                        .index(1)
+                        .required(false)
+                        .value_name("REVISION")
+                        .help("Commit to verify, HEAD by default"),
+                ),
+            Command::new("verify-tag")
+                .about("Check the signature of a tag against the allowed signers")
+                .arg(
+                    Arg::new("name")
+                        .num_args(1)
+                        .index(1)
+                        .required(true)
+                        .value_name("NAME")
+                        .help("Tag to verify"),
                 ),
-            Command::new("branch").about("Branch").arg(
-                Arg::new("branch")
-                    .num_args(1)
-                    .index(1)
-                    .required(true)
-                    .value_name("NAME")
-                    .help("Create a new branch and switch to the branch"),
-            ),
             Command::new("checkout").about("Checkout").arg(
                 Arg::new("branch")
                     .num_args(1)
@@ -80,40 +259,317 @@ fn main() {
                     .value_name("NAME")
                     .help("Switch to the branch"),
             ),
-            Command::new("merge").about("Merge").arg(
-                Arg::new("branch")
-                    .num_args(1)
-                    .index(1)
-                    .required(true)
-                    .value_name("NAME")
-                    .help("Merge branch with the current branch"),
-            ),
+            Command::new("merge")
+                .about("Merge a branch by whole files: files are not merged line by line, a file changed on both branches is a conflict")
+                .arg(
+                    Arg::new("branch")
+                        .num_args(1)
+                        .index(1)
+                        .required(true)
+                        .value_name("NAME")
+                        .help("Merge branch with the current branch"),
+                ),
+            Command::new("mv")
+                .about("Move or rename a tracked file")
+                .arg(
+                    Arg::new("source")
+                        .num_args(1)
+                        .index(1)
+                        .required(true)
+                        .value_name("SOURCE")
+                        .help("File to move"),
+                )
+                .arg(
+                    Arg::new("destination")
+                        .num_args(1)
+                        .index(2)
+                        .required(true)
+                        .value_name("DESTINATION")
+                        .help("New path of the file"),
+                ),
+            Command::new("status")
+                .about("Show the working tree status")
+                .arg(find_renames_arg())
+                .args(output_format_args()),
+            Command::new("diff")
+                .about("Show changes of the working directory")
+                .arg(
+                    Arg::new("staged")
+                        .long("staged")
+                        .required(false)
+                        .num_args(0)
+                        .help("Show changes of the next commit"),
+                )
+                .arg(
+                    Arg::new("stat")
+                        .long("stat")
+                        .required(false)
+                        .num_args(0)
+                        .help("Only show the number of inserted and deleted lines of each file"),
+                )
+                .arg(find_renames_arg())
+                .args(output_format_args().map(|arg| arg.requires("stat"))),
+            Command::new("log")
+                .about("Show the commits of the current branch")
+                .arg(
+                    Arg::new("name-status")
+                        .long("name-status")
+                        .required(false)
+                        .num_args(0)
+                        .help("Show the files changed by each commit"),
+                )
+                .arg(
+                    Arg::new("show-signature")
+                        .long("show-signature")
+                        .required(false)
+                        .num_args(0)
+                        .help("Verify the signature of each signed commit"),
+                )
+                .arg(find_renames_arg())
+                .args(output_format_args()),
+            Command::new("reflog")
+                .about("Show or manage the log of HEAD and branch movements")
+                .subcommands([
+                    Command::new("show").about("Show the log of a ref").arg(
+                        Arg::new("ref")
+                            .num_args(1)
+                            .index(1)
+                            .required(false)
+                            .value_name("REF")
+                            .help("HEAD or a branch name, HEAD by default"),
+                    ),
+                    Command::new("expire")
+                        .about("Remove old entries")
+                        .arg(
+                            Arg::new("ref")
+                                .num_args(1)
+                                .index(1)
+                                .required(false)
+                                .value_name("REF")
+                                .help("HEAD or a branch name, every log by default"),
+                        )
+                        .arg(
+                            Arg::new("expire")
+                                .long("expire")
+                                .required(false)
+                                .num_args(1)
+                                .value_name("TIME")
+                                .help("Remove entries older than TIME, 90 days by default"),
+                        ),
+                    Command::new("delete").about("Remove one entry").arg(
+                        Arg::new("entry")
+                            .num_args(1)
+                            .index(1)
+                            .required(true)
+                            .value_name("REF@{N}")
+                            .help("Entry to remove"),
+                    ),
+                ]),
+            Command::new("show")
+                .about("Show a commit with its changes, or a file at a revision with REVISION:PATH")
+                .arg(
+                    Arg::new("revision")
+                        .num_args(1)
+                        .index(1)
+                        .required(false)
+                        .value_name("REVISION")
+                        .help("Commit to show, HEAD by default, or REVISION:PATH"),
+                )
+                .arg(
+                    Arg::new("stat")
+                        .long("stat")
+                        .required(false)
+                        .num_args(0)
+                        .help("Only show the number of inserted and deleted lines of each file"),
+                )
+                .arg(
+                    Arg::new("name-only")
+                        .long("name-only")
+                        .required(false)
+                        .num_args(0)
+                        .help("Only show the paths of the changed files"),
+                )
+                .arg(
+                    Arg::new("name-status")
+                        .long("name-status")
+                        .required(false)
+                        .num_args(0)
+                        .help("Only show the status and the paths of the changed files"),
+                )
+                .group(ArgGroup::new("mode").args(["stat", "name-only", "name-status"]))
+                .arg(find_renames_arg())
+                .args(output_format_args()),
+            Command::new("cat-file")
+                .about("Show the type, size or content of an object")
+                .arg(
+                    Arg::new("type")
+                        .short('t')
+                        .required(false)
+                        .num_args(0)
+                        .help("Show the type of the object"),
+                )
+                .arg(
+                    Arg::new("size")
+                        .short('s')
+                        .required(false)
+                        .num_args(0)
+                        .help("Show the size of the object content in bytes"),
+                )
+                .arg(
+                    Arg::new("pretty")
+                        .short('p')
+                        .required(false)
+                        .num_args(0)
+                        .help("Show the content of the object"),
+                )
+                .group(ArgGroup::new("mode").args(["type", "size", "pretty"]).required(true))
+                .arg(
+                    Arg::new("object")
+                        .num_args(1)
+                        .index(1)
+                        .required(true)
+                        .value_name("OBJECT")
+                        .help("Hash of the object, or a revision"),
+                ),
+            Command::new("ls-tree")
+                .about("List the content of a tree")
+                .arg(
+                    Arg::new("recursive")
+                        .short('r')
+                        .required(false)
+                        .num_args(0)
+                        .help("List the files of the subtrees"),
+                )
+                .arg(
+                    Arg::new("revision")
+                        .num_args(1)
+                        .index(1)
+                        .required(true)
+                        .value_name("REVISION")
+                        .help("Commit or tree to list"),
+                ),
+            Command::new("lfs")
+                .about("Manage the large files stored outside of the objects")
+                .subcommand_required(true)
+                .subcommands([Command::new("ls-files").about("List the large files of the HEAD commit")]),
+            Command::new("key")
+                .about("Manage the key of an encrypted repository")
+                .subcommand_required(true)
+                .subcommands([Command::new("rotate").about("Encrypt the repository again with a new key").arg(
+                    Arg::new("new-key-file")
+                        .long("new-key-file")
+                        .required(false)
+                        .num_args(1)
+                        .value_name("FILE")
+                        .help("File holding the new key, read from DIT_NEW_PASSPHRASE otherwise"),
+                )]),
+            Command::new("fsck")
+                .about("Check that every reachable object exists and is valid, and list unreachable objects")
+                .args(output_format_args()),
+            Command::new("gc")
+                .about("Remove unreachable objects older than two weeks")
+                .arg(dry_run_arg()),
+            Command::new("prune")
+                .about("Remove unreachable objects")
+                .arg(
+                    Arg::new("expire")
+                        .long("expire")
+                        .required(false)
+                        .num_args(1)
+                        .value_name("TIME")
+                        .help("Only remove objects older than TIME, 2 weeks by default"),
+                )
+                .arg(dry_run_arg()),
+            Command::new("upgrade").about("Migrate the repository to the layout of this version of dit"),
+            Command::new("config")
+                .about("Get or set a repository option")
+                .arg(
+                    Arg::new("key")
+                        .num_args(1)
+                        .index(1)
+                        .required(true)
+                        .value_name("KEY")
+                        .help("Option name"),
+                )
+                .arg(
+                    Arg::new("value")
+                        .num_args(1)
+                        .index(2)
+                        .required(false)
+                        .value_name("VALUE")
+                        .help("New value of the option"),
+                ),
         ])
         .get_matches();
 
+    let verbosity = match matches.subcommand() {
+        Some((_, matches)) if matches.get_flag("quiet") => Verbosity::Quiet,
+        Some((_, matches)) if matches.get_flag("verbose") => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    };
+    let reporter: Arc<dyn Reporter> = Arc::new(TerminalReporter::new(verbosity));
+
     // INIT
-    if let Some(_) = matches.subcommand_matches("init") {
-        match features::init::init_repository() {
-            Ok(()) => display_message("dit initialized.", Color::GREEN),
+    if let Some(matches) = matches.subcommand_matches("init") {
+        let object_format = matches
+            .get_one::<String>("object-format")
+            .and_then(|name| ObjectFormat::from_name(name))
+            .unwrap_or_default();
+        let encryption = matches
+            .get_one::<String>("key-file")
+            .map(|path| KeySource::KeyFile(PathBuf::from(path)))
+            .or_else(|| KeySource::from_environment(&Config::default()));
+        let result = current_dir().and_then(|path| match encryption {
+            Some(source) if matches.get_flag("encrypt") => Repository::init_encrypted(path, object_format, &source, reporter.clone()),
+            None if matches.get_flag("encrypt") => Err(DitError::MissingKey),
+            _ => Repository::init_with_format(path, object_format, reporter.clone()),
+        });
+        match result {
+            Ok(_) => reporter.report(&Event::Message(String::from("dit initialized."), Color::GREEN)),
             Err(e) => {
-                if PathBuf::from("./.dit").is_dir() {
-                    let _ = fs::remove_dir_all("./.dit");
-                }
                 display_message(format!("Error initializing dit repository: {}.", e).as_str(), Color::RED);
-                process::exit(1);
+                process::exit(exit_code(&e));
             }
         };
+        process::exit(0);
     }
 
+    if matches.subcommand().is_none() {
+        process::exit(0);
+    }
+
+    // UPGRADE
+    if matches.subcommand_matches("upgrade").is_some() {
+        let result = current_dir()
+            .and_then(Repository::discover_unchecked)
+            .and_then(|repo| upgrade(&repo.with_reporter(reporter.clone())));
+        if let Err(e) = result {
+            display_message(format!("Error upgrading dit repository: {}.", e).as_str(), Color::RED);
+            process::exit(exit_code(&e));
+        }
+        process::exit(0);
+    }
+    let repo = match current_dir().and_then(Repository::discover) {
+        Ok(repo) => repo.with_reporter(reporter.clone()),
+        Err(DitError::NotInitialized) => {
+            display_message("dit repository is not initialized.", Color::RED);
+            process::exit(exit_code(&DitError::NotInitialized));
+        }
+        Err(e) => {
+            display_message(format!("Error opening dit repository: {}.", e).as_str(), Color::RED);
+            process::exit(exit_code(&e));
+        }
+    };
+
     // ADD
     if let Some(matches) = matches.subcommand_matches("add") {
         if let Some(elements) = matches.get_many::<String>("files") {
             let elements: Vec<_> = elements.collect();
-            match add::add(elements) {
-                Ok(()) => display_message("Files added.", Color::GREEN),
+            match add::add(&repo, elements) {
+                Ok(()) => reporter.report(&Event::Message(String::from("Files added."), Color::GREEN)),
                 Err(e) => {
                     display_message(format!("Error adding elements to dit : {}.", e).as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
                 }
             };
         }
@@ -123,11 +579,25 @@ fn main() {
     if let Some(matches) = matches.subcommand_matches("rm") {
         if let Some(elements) = matches.get_many::<String>("files") {
             let elements: Vec<_> = elements.collect();
-            match rm::rm(elements) {
+            match rm::rm(&repo, elements, matches.get_flag("cached")) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error removing elements to dit : {}", e).as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
+                }
+            }
+        }
+    }
+
+    // RESTORE
+    if let Some(matches) = matches.subcommand_matches("restore") {
+        if let Some(elements) = matches.get_many::<String>("files") {
+            let elements: Vec<_> = elements.collect();
+            match restore::restore_staged(&repo, elements) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error unstaging elements: {}", e).as_str(), Color::RED);
+                    process::exit(exit_code(&e));
                 }
             }
         }
@@ -135,49 +605,79 @@ fn main() {
 
     // COMMIT
     if let Some(matches) = matches.subcommand_matches("commit") {
+        let amend_flag = matches.get_flag("amend");
+        // ALL
+        if matches.get_flag("all") {
+            match stage_tracked_changes(&repo) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error staging tracked files: {}.", e).as_str(), Color::RED);
+                    process::exit(exit_code(&e));
+                }
+            }
+        }
         // MESSAGE
         if let Some(mes) = matches.get_one::<String>("message") {
-            match message(mes.parse().unwrap()) {
+            match message(&repo, mes.to_string()) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error writing message: {}.", e).as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
                 }
             }
-            match commit(true) {
-                Ok(()) => display_message("Commit created.", Color::GREEN),
-                Err(e) => {
-                    display_message(format!("Error commiting elements: {}.", e).as_str(), Color::RED);
-                    process::exit(1);
+            if amend_flag {
+                match amend(&repo, true, false) {
+                    Ok(()) => reporter.report(&Event::Message(String::from("Commit amended."), Color::GREEN)),
+                    Err(e) => {
+                        display_message(format!("Error amending commit: {}.", e).as_str(), Color::RED);
+                        process::exit(exit_code(&e));
+                    }
+                }
+            } else {
+                match commit(&repo, true) {
+                    Ok(()) => reporter.report(&Event::Message(String::from("Commit created."), Color::GREEN)),
+                    Err(e) => {
+                        display_message(format!("Error commiting elements: {}.", e).as_str(), Color::RED);
+                        process::exit(exit_code(&e));
+                    }
                 }
             }
         }
         // SHOWCOMMIT
         else if matches.get_flag("show") {
-            match show_commit() {
+            match show_commit(&repo, output_format(matches)) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error displaying commit tree: {}", e).as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
                 }
             }
         }
         //REVERT
         else if let Some(hash) = matches.get_one::<String>("revert") {
-            match revert(hash.to_string()) {
+            match revert(&repo, hash.to_string()) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error reverting to the previous state: {e}").as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
+                }
+            }
+            // AMEND
+        } else if amend_flag {
+            match amend(&repo, false, matches.get_flag("no-edit")) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error while amending commit: {}", e).as_str(), Color::RED);
+                    process::exit(exit_code(&e));
                 }
             }
             // COMMIT
         } else {
-            match commit(false) {
+            match commit(&repo, false) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error while commiting: {}", e).as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
                 }
             }
         }
@@ -186,24 +686,64 @@ fn main() {
     // CREATE BRANCH
     if let Some(matches) = matches.subcommand_matches("branch") {
         if let Some(name) = matches.get_one::<String>("branch") {
-            match new_branch(name) {
+            match new_branch(&repo, name) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error while creating new branch: {e}").as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
+                }
+            }
+        } else {
+            match list_branches(&repo, output_format(matches)) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error while listing branches: {e}").as_str(), Color::RED);
+                    process::exit(exit_code(&e));
                 }
             }
         }
     }
 
+    // TAG
+    if let Some(matches) = matches.subcommand_matches("tag") {
+        let result = match matches.get_one::<String>("name") {
+            Some(name) => new_tag(&repo, name, matches.get_one::<String>("revision"), matches.get_flag("sign")),
+            None => list_tags(&repo, output_format(matches)),
+        };
+        match result {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while accessing tags: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // VERIFY
+    if let Some(matches) = matches.subcommand_matches("verify-commit") {
+        let revision = matches.get_one::<String>("revision").map(|r| r.as_str()).unwrap_or(HEAD_LOG);
+        if let Err(e) = verify_commit(&repo, revision) {
+            display_message(format!("Error while verifying commit: {e}").as_str(), Color::RED);
+            process::exit(exit_code(&e));
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("verify-tag") {
+        if let Some(name) = matches.get_one::<String>("name") {
+            if let Err(e) = verify_tag(&repo, name) {
+                display_message(format!("Error while verifying tag: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
     // CHECKOUT
     if let Some(matches) = matches.subcommand_matches("checkout") {
         if let Some(name) = matches.get_one::<String>("branch") {
-            match checkout(name) {
+            match checkout(&repo, name) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error while changing branch : {e}").as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
                 }
             }
         }
@@ -212,14 +752,305 @@ fn main() {
     // MERGE
     if let Some(matches) = matches.subcommand_matches("merge") {
         if let Some(name) = matches.get_one::<String>("branch") {
-            match merge(name) {
+            match merge(&repo, name) {
                 Ok(()) => (),
                 Err(e) => {
                     display_message(format!("Error while merging branch: {e}").as_str(), Color::RED);
-                    process::exit(1);
+                    process::exit(exit_code(&e));
+                }
+            }
+        }
+    }
+
+    // MV
+    if let Some(matches) = matches.subcommand_matches("mv") {
+        if let (Some(source), Some(destination)) = (
+            matches.get_one::<String>("source"),
+            matches.get_one::<String>("destination"),
+        ) {
+            match mv(&repo, source, destination) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error while moving file: {e}").as_str(), Color::RED);
+                    process::exit(exit_code(&e));
+                }
+            }
+        }
+    }
+
+    // STATUS
+    if let Some(matches) = matches.subcommand_matches("status") {
+        match status(&repo, matches.get_one::<u8>("find-renames").copied(), output_format(matches)) {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while computing status: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // DIFF
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        match diff(
+            &repo,
+            matches.get_flag("staged"),
+            matches.get_one::<u8>("find-renames").copied(),
+            matches.get_flag("stat"),
+            output_format(matches),
+        ) {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while computing diff: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // LOG
+    if let Some(matches) = matches.subcommand_matches("log") {
+        match log(
+            &repo,
+            matches.get_flag("name-status"),
+            matches.get_flag("show-signature"),
+            matches.get_one::<u8>("find-renames").copied(),
+            output_format(matches),
+        ) {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while displaying log: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // REFLOG
+    if let Some(matches) = matches.subcommand_matches("reflog") {
+        let result = match matches.subcommand() {
+            Some(("expire", matches)) => expire_reflog(
+                &repo,
+                matches.get_one::<String>("ref"),
+                matches.get_one::<String>("expire"),
+            ),
+            Some(("delete", matches)) => match matches.get_one::<String>("entry") {
+                Some(entry) => delete_reflog_entry(&repo, entry),
+                None => Ok(()),
+            },
+            Some(("show", matches)) => show_reflog(&repo, matches.get_one::<String>("ref")),
+            _ => show_reflog(&repo, None),
+        };
+        match result {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while accessing reflog: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // SHOW
+    if let Some(matches) = matches.subcommand_matches("show") {
+        let revision = matches.get_one::<String>("revision").map(|r| r.as_str()).unwrap_or(HEAD_LOG);
+        let mode = if matches.get_flag("stat") {
+            ShowMode::Stat
+        } else if matches.get_flag("name-only") {
+            ShowMode::NameOnly
+        } else if matches.get_flag("name-status") {
+            ShowMode::NameStatus
+        } else {
+            ShowMode::Patch
+        };
+        if let Err(e) = show(&repo, revision, mode, matches.get_one::<u8>("find-renames").copied(), output_format(matches)) {
+            display_message(format!("Error while showing {revision}: {e}").as_str(), Color::RED);
+            process::exit(exit_code(&e));
+        }
+    }
+
+    // CAT-FILE
+    if let Some(matches) = matches.subcommand_matches("cat-file") {
+        if let Some(object) = matches.get_one::<String>("object") {
+            let mode = if matches.get_flag("type") {
+                CatFileMode::Type
+            } else if matches.get_flag("size") {
+                CatFileMode::Size
+            } else {
+                CatFileMode::Pretty
+            };
+            match cat_file(&repo, object, mode) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error while reading object: {e}").as_str(), Color::RED);
+                    process::exit(exit_code(&e));
+                }
+            }
+        }
+    }
+
+    // LS-TREE
+    if let Some(matches) = matches.subcommand_matches("ls-tree") {
+        if let Some(revision) = matches.get_one::<String>("revision") {
+            match ls_tree(&repo, revision, matches.get_flag("recursive")) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error while listing tree: {e}").as_str(), Color::RED);
+                    process::exit(exit_code(&e));
+                }
+            }
+        }
+    }
+
+    // LFS
+    if let Some(matches) = matches.subcommand_matches("lfs") {
+        let result = match matches.subcommand() {
+            Some(("ls-files", _)) => ls_files(&repo),
+            _ => Ok(()),
+        };
+        match result {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while listing large files: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // KEY
+    if let Some(matches) = matches.subcommand_matches("key") {
+        let result = match matches.subcommand() {
+            Some(("rotate", matches)) => {
+                let source = matches
+                    .get_one::<String>("new-key-file")
+                    .map(|path| KeySource::KeyFile(PathBuf::from(path)))
+                    .or_else(|| env::var(NEW_PASSPHRASE_VARIABLE).ok().filter(|value| !value.is_empty()).map(KeySource::Passphrase));
+                match source {
+                    Some(source) => rotate_key(&repo, &source),
+                    None => Err(DitError::UnexpectedComportement(format!("Give the new key with --new-key-file or {}", NEW_PASSPHRASE_VARIABLE))),
+                }
+            }
+            _ => Ok(()),
+        };
+        match result {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while rotating the key: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // FSCK
+    if let Some(matches) = matches.subcommand_matches("fsck") {
+        match fsck(&repo, output_format(matches)) {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while checking repository: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // GC
+    if let Some(matches) = matches.subcommand_matches("gc") {
+        match gc(&repo, matches.get_flag("dry-run")) {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while collecting garbage: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // PRUNE
+    if let Some(matches) = matches.subcommand_matches("prune") {
+        match prune(&repo, matches.get_one::<String>("expire"), matches.get_flag("dry-run")) {
+            Ok(()) => (),
+            Err(e) => {
+                display_message(format!("Error while pruning objects: {e}").as_str(), Color::RED);
+                process::exit(exit_code(&e));
+            }
+        }
+    }
+
+    // CONFIG
+    if let Some(matches) = matches.subcommand_matches("config") {
+        if let Some(key) = matches.get_one::<String>("key") {
+            match config(&repo, key, matches.get_one::<String>("value")) {
+                Ok(()) => (),
+                Err(e) => {
+                    display_message(format!("Error while accessing config: {e}").as_str(), Color::RED);
+                    process::exit(exit_code(&e));
                 }
             }
         }
     }
     process::exit(0);
 }
+
+fn find_renames_arg() -> Arg {
+    Arg::new("find-renames")
+        .short('M')
+        .long("find-renames")
+        .required(false)
+        .num_args(1)
+        .value_name("PERCENT")
+        .value_parser(value_parser!(u8).range(0..=100))
+        .help("Minimum similarity for a deleted and an added file to be shown as a rename")
+}
+
+fn dry_run_arg() -> Arg {
+    Arg::new("dry-run")
+        .short('n')
+        .long("dry-run")
+        .required(false)
+        .num_args(0)
+        .help("Only show what would be removed")
+}
+
+/// `--porcelain` and `--json` flags of the read commands
+fn output_format_args() -> [Arg; 2] {
+    [
+        Arg::new("porcelain")
+            .long("porcelain")
+            .required(false)
+            .num_args(0)
+            .conflicts_with("json")
+            .help("Print stable tab separated records, see docs/output-format.md"),
+        Arg::new("json")
+            .long("json")
+            .required(false)
+            .num_args(0)
+            .help("Print one stable JSON document, see docs/output-format.md"),
+    ]
+}
+
+fn output_format(matches: &ArgMatches) -> OutputFormat {
+    if matches.get_flag("json") {
+        OutputFormat::Json
+    } else if matches.get_flag("porcelain") {
+        OutputFormat::Porcelain
+    } else {
+        OutputFormat::Human
+    }
+}
+
+fn current_dir() -> Result<PathBuf, DitError> {
+    env::current_dir().map_err(DitError::IoError)
+}
+
+/// Exit code of a failed command: 128 when the command could not start, as for an unknown
+/// revision or branch, 1 otherwise
+fn exit_code(error: &DitError) -> i32 {
+    match error {
+        DitError::NotInitialized
+        | DitError::InvalidRefName(_)
+        | DitError::BranchNotFound(_)
+        | DitError::UnknownRevision(_)
+        | DitError::AmbiguousRevision(_)
+        | DitError::OutsideRepository(_)
+        | DitError::UnsupportedRepositoryFormat(_)
+        | DitError::OutdatedRepository(_)
+        | DitError::Locked { .. }
+        | DitError::MissingKey
+        | DitError::WrongKey => 128,
+        _ => 1,
+    }
+}
\ No newline at end of file
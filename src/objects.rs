@@ -2,5 +2,20 @@ pub const TREE: &str = "tree";
 pub const BLOB: &str = "blob";
 
 pub mod branch;
+pub mod change;
 pub mod commit;
+pub mod config;
+pub mod crypt;
+pub mod hash;
+pub mod index;
+pub mod lfs;
+pub mod lock;
+pub mod mode;
 pub mod node;
+pub mod object;
+pub mod pack;
+pub mod reflog;
+pub mod signature;
+pub mod store;
+pub mod tag;
+pub mod tree;
@@ -1,14 +1,10 @@
-use std::{io, process};
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
-use std::path::PathBuf;
-
-use dit_file_encryptor::CompressedFile;
+use std::fs;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_info, find_refs, get_head_hash};
-use crate::utils::NULL_HASH;
+use crate::objects::lock::{is_lock_file, Transaction, LOCK_EXTENSION};
+use crate::objects::reflog::{record_head_movement, ReflogEntry};
+use crate::repository::Repository;
+use crate::utils::read_dit_file;
 
 pub struct Branch {
     head: String,
@@ -24,29 +20,26 @@ impl Branch {
         &self.head
     }
 
-    pub fn new_branch(name: String, head: String) -> Result<Branch, DitError> {
+    pub fn new_branch(repo: &Repository, name: String, head: String) -> Result<Branch, DitError> {
         if !Self::is_name_ok(&name) {
-            display_message("Branch name must not contains '/'.", Color::RED);
-            process::exit(0);
+            return Err(DitError::InvalidRefName(name));
         }
-        let ref_path = find_refs();
-        let file_path = ref_path.join(&name);
-
-        if file_path.exists() {
-            display_message("Branch with same name already exist, cannot create the branch", Color::RED);
-            process::exit(0);
+        let mut transaction = Transaction::new(repo);
+        transaction.lock(repo.get_refs_path().join(&name))?;
+        transaction.lock(repo.get_info_path())?;
+        if Self::exist(repo, name.clone()) {
+            return Err(DitError::BranchExists(name));
         }
-        let file = CompressedFile::create_file(file_path)
-            .map_err(|e| DitError::IoError(e))?;
-        Self::set_info_file(name.clone(), head.clone()).map_err(DitError::IoError)?;
-        if head != NULL_HASH {
-            let writer = file
-                .open_for_write()
-                .map_err(|e| DitError::IoError(e))?;
-
-            let mut writer = BufWriter::new(writer);
-            writeln!(writer, "{}", head).map_err(DitError::IoError)?;
+        if !repo.get_object_format().is_null_hash(&head) {
+            let current_branch = Self::get_current_branch(repo)?;
+            ReflogEntry::new(repo, repo.get_null_hash(), head.clone(), String::from("branch: Created from HEAD"))
+                .append_to(&mut transaction, &name)?;
+            let reason = format!("checkout: moving from {} to {}", current_branch.get_name(), name);
+            record_head_movement(&mut transaction, &head, &head, reason.as_str())?;
         }
+        Self::write_info_to(&mut transaction, &name, &head)?;
+        Self::write_ref_to(&mut transaction, &name, &head)?;
+        transaction.commit()?;
         Ok(Branch {
             head,
             name,
@@ -54,11 +47,11 @@ impl Branch {
     }
 
     pub fn is_name_ok(name: &String) -> bool {
-        !(name.contains('/') || name.contains('\\'))
+        !(name.contains('/') || name.contains('\\') || name.ends_with(&format!(".{}", LOCK_EXTENSION)))
     }
 
-    pub fn exist(name: String) -> bool {
-        let ref_path = find_refs();
+    pub fn exist(repo: &Repository, name: String) -> bool {
+        let ref_path = repo.get_refs_path();
         let file_path = ref_path.join(name.clone());
 
         if file_path.is_file() {
@@ -68,62 +61,102 @@ impl Branch {
         false
     }
 
-    pub fn set_info_file(name: String, head: String) -> Result<(), io::Error> {
-        let path_info_file = PathBuf::from("./.dit/info");
-        let _ = File::create(path_info_file.clone())?;
-        let mut writer = CompressedFile::new(path_info_file)
-            .open_for_write()
-            .map_err(|e| {
-                io::Error::new(io::ErrorKind::InvalidData, e)
-            })?;
-
-        write!(writer, "{} {} {}", "HEAD", head, name)?;
-
-        Ok(())
+    /// Write the info file: `branch <name>` and `head <hash>` lines, the HEAD commit being
+    /// the tip of the branch unless a revert moved it
+    pub fn set_info_file(repo: &Repository, name: String, head: String) -> Result<(), DitError> {
+        let mut transaction = Transaction::new(repo);
+        Self::write_info_to(&mut transaction, &name, &head)?;
+        transaction.commit()
     }
 
-    pub fn get_current_branch() -> Result<Branch, DitError> {
-        let head = get_head_hash()?;
-        let info_path = find_info();
+    /// Write the info file as part of `transaction`
+    pub fn write_info_to(transaction: &mut Transaction, name: &String, head: &String) -> Result<(), DitError> {
+        let path = transaction.get_repo().get_info_path();
+        transaction.write(path, format!("branch {}\nhead {}\n", name, head).as_bytes())
+    }
 
-        let mut reader = CompressedFile::new(info_path)
-            .open_for_read()
-            .map_err(|e| {
-                DitError::IoError(e)
-            })?;
+    /// Return the name of the current branch and the HEAD commit from the info file
+    pub fn read_info_file(repo: &Repository) -> Result<(String, String), DitError> {
+        let buf = read_dit_file(repo, &repo.get_info_path())?;
+        let buf = String::from_utf8_lossy(&buf);
+
+        let mut name: Option<String> = None;
+        let mut head: Option<String> = None;
+        for line in buf.lines() {
+            match line.split_once(' ') {
+                Some(("branch", value)) => name = Some(value.trim().to_string()),
+                Some(("head", value)) => head = Some(value.trim().to_string()),
+                _ => (),
+            }
+        }
+        match (name, head) {
+            (Some(name), Some(head)) => Ok((name, head)),
+            _ => Err(DitError::UnexpectedComportement(String::from("Info file is corrupt"))),
+        }
+    }
 
-        let mut buf = String::new();
-        let _ = reader.read_to_string(&mut buf);
-        let infos: Vec<_> = buf.split_whitespace().collect();
-        let name = String::from(infos[2]);
+    pub fn get_current_branch(repo: &Repository) -> Result<Branch, DitError> {
+        let (name, head) = Self::read_info_file(repo)?;
+        Ok(Branch {
+            head,
+            name,
+        })
+    }
 
+    pub fn get_branch(repo: &Repository, name: String) -> Result<Branch, DitError> {
+        if !Self::exist(repo, name.clone()) {
+            return Err(DitError::BranchNotFound(name));
+        }
+        let head = Self::read_ref(repo, &name)?;
         Ok(Branch {
             head,
             name,
         })
     }
 
-    pub fn get_branch(name: String) -> Result<Branch, DitError> {
-        let ref_path = find_refs();
-        let file_path = ref_path.join(name.clone());
+    /// Return the tip of the branch `name`, the null hash when it has no commit
+    pub fn read_ref(repo: &Repository, name: &String) -> Result<String, DitError> {
+        let content = read_dit_file(repo, &repo.get_refs_path().join(name))?;
+        // Refs of the first layout list every commit of the branch, the tip last
+        let mut head = repo.get_null_hash();
+        for line in String::from_utf8_lossy(&content).lines() {
+            if !line.trim().is_empty() {
+                head = line.trim().to_string();
+            }
+        }
+        Ok(head)
+    }
+
+    /// Make `head` the tip of the branch `name`, creating it if needed
+    pub fn write_ref(repo: &Repository, name: &String, head: &String) -> Result<(), DitError> {
+        let mut transaction = Transaction::new(repo);
+        Self::write_ref_to(&mut transaction, name, head)?;
+        transaction.commit()
+    }
 
-        let reader = CompressedFile::new(file_path)
-            .open_for_read()
-            .map_err(|e| {
-                DitError::IoError(e)
-            })?;
-        let buf_reader = BufReader::new(reader);
-        let lines: Vec<_> = buf_reader.lines().collect();
-        let line = lines.last().ok_or_else(|| DitError::IoError(io::Error::new(io::ErrorKind::UnexpectedEof, "File is empty")))?;
-        return match line {
-            Ok(hash) => {
-                let branch = Branch {
-                    head: hash.to_string(),
-                    name,
-                };
-                Ok(branch)
+    /// Make `head` the tip of the branch `name` as part of `transaction`
+    pub fn write_ref_to(transaction: &mut Transaction, name: &String, head: &String) -> Result<(), DitError> {
+        let repo = transaction.get_repo();
+        let path = repo.get_refs_path().join(name);
+        let content = if repo.get_object_format().is_null_hash(head) { String::new() } else { format!("{}\n", head) };
+        transaction.write(path, content.as_bytes())
+    }
+
+    /// Return every branch sorted by name, with a null head for a branch without commit
+    pub fn get_branches(repo: &Repository) -> Result<Vec<Branch>, DitError> {
+        let mut branches: Vec<Branch> = Vec::new();
+        for entry in fs::read_dir(repo.get_refs_path()).map_err(DitError::IoError)? {
+            let entry = entry.map_err(DitError::IoError)?;
+            if !entry.path().is_file() || is_lock_file(&entry.path()) {
+                continue;
             }
-            _ => Err(DitError::IoError(io::Error::new(ErrorKind::InvalidData, "Head not found")))
-        };
+            let name = entry.file_name().to_string_lossy().to_string();
+            branches.push(Branch {
+                head: Self::read_ref(repo, &name)?,
+                name,
+            });
+        }
+        branches.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(branches)
     }
 }
@@ -0,0 +1,246 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::error::DitError;
+use crate::objects::mode::{FileMode, Modes};
+use crate::utils::is_binary_content;
+
+/// Difference on one file between two versions of the repository
+#[derive(Clone, Debug)]
+pub enum Change {
+    Added { path: PathBuf, hash: String },
+    Deleted { path: PathBuf, hash: String },
+    Modified { path: PathBuf, old_hash: String, new_hash: String },
+    Renamed { from: PathBuf, to: PathBuf, old_hash: String, new_hash: String, similarity: u8 },
+    /// Change of the mode of a file, or a symlink or an empty directory added, removed or
+    /// changed, which have a mode but no content
+    ModeChanged { path: PathBuf, old_mode: Option<FileMode>, new_mode: Option<FileMode> },
+}
+
+impl Change {
+    pub fn get_status(&self) -> char {
+        match self {
+            Change::Added { .. } => 'A',
+            Change::Deleted { .. } => 'D',
+            Change::Modified { .. } => 'M',
+            Change::Renamed { .. } => 'R',
+            Change::ModeChanged { old_mode: None, .. } => 'A',
+            Change::ModeChanged { new_mode: None, .. } => 'D',
+            Change::ModeChanged { .. } => 'M',
+        }
+    }
+
+    /// Path of the file in the new version, or in the old one for a deletion
+    pub fn get_path(&self) -> &PathBuf {
+        match self {
+            Change::Added { path, .. } => path,
+            Change::Deleted { path, .. } => path,
+            Change::Modified { path, .. } => path,
+            Change::Renamed { to, .. } => to,
+            Change::ModeChanged { path, .. } => path,
+        }
+    }
+
+    /// Return a line like `M path`, `R old -> new` or `M path (100644 => 100755)`
+    pub fn name_status(&self) -> String {
+        match self {
+            Change::Renamed { from, to, .. } => format!("R {} -> {}", from.display(), to.display()),
+            Change::ModeChanged { path, old_mode: Some(old_mode), new_mode: Some(new_mode) } => {
+                format!("M {} ({} => {})", path.display(), old_mode, new_mode)
+            }
+            Change::ModeChanged { path, old_mode, new_mode } => match old_mode.as_ref().or(new_mode.as_ref()) {
+                Some(mode) => format!("{} {} ({})", self.get_status(), path.display(), mode),
+                None => format!("{} {}", self.get_status(), path.display()),
+            },
+            change => format!("{} {}", change.get_status(), change.get_path().display()),
+        }
+    }
+}
+
+/// Compare two lists of `(path, hash)` and return added, deleted and modified files sorted by path
+pub fn compare_blobs(old: &[(PathBuf, String)], new: &[(PathBuf, String)]) -> Vec<Change> {
+    let old_map: HashMap<&PathBuf, &String> = old.iter().map(|(path, hash)| (path, hash)).collect();
+    let new_map: HashMap<&PathBuf, &String> = new.iter().map(|(path, hash)| (path, hash)).collect();
+
+    let mut changes: Vec<Change> = Vec::new();
+
+    for (path, old_hash) in old_map.iter() {
+        match new_map.get(path) {
+            Some(new_hash) if new_hash != old_hash => changes.push(Change::Modified {
+                path: (*path).clone(),
+                old_hash: (*old_hash).clone(),
+                new_hash: (*new_hash).clone(),
+            }),
+            Some(_) => (),
+            None => changes.push(Change::Deleted { path: (*path).clone(), hash: (*old_hash).clone() }),
+        }
+    }
+    for (path, hash) in new_map.iter() {
+        if !old_map.contains_key(path) {
+            changes.push(Change::Added { path: (*path).clone(), hash: (*hash).clone() });
+        }
+    }
+
+    changes.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+    changes
+}
+
+/// Compare the modes of two versions of the repository, made of the files `old` with the
+/// modes `old_modes` and of the files `new` with the modes `new_modes`. Return the files
+/// whose mode changed, and the symlinks and empty directories added, removed or changed.
+pub fn compare_modes(old: &[(PathBuf, String)], old_modes: &Modes, new: &[(PathBuf, String)], new_modes: &Modes) -> Vec<Change> {
+    let get_mode = |blobs: &[(PathBuf, String)], modes: &Modes, path: &PathBuf| {
+        let mode = modes.get(path);
+        (mode.is_special() || blobs.iter().any(|(p, _)| p == path)).then_some(mode)
+    };
+
+    let mut paths: BTreeSet<PathBuf> = BTreeSet::new();
+    paths.extend(old.iter().map(|(path, _)| path.clone()));
+    paths.extend(old_modes.get_special_paths());
+    paths.extend(new.iter().map(|(path, _)| path.clone()));
+    paths.extend(new_modes.get_special_paths());
+
+    let mut changes: Vec<Change> = Vec::new();
+    for path in paths {
+        let old_mode = get_mode(old, old_modes, &path);
+        let new_mode = get_mode(new, new_modes, &path);
+        let changed = match (&old_mode, &new_mode) {
+            (Some(old_mode), Some(new_mode)) => old_mode != new_mode,
+            (Some(mode), None) | (None, Some(mode)) => mode.is_special(),
+            (None, None) => false,
+        };
+        if changed {
+            changes.push(Change::ModeChanged { path, old_mode, new_mode });
+        }
+    }
+    changes
+}
+
+/// Compare two versions of the repository like `compare_blobs` and `compare_modes`. A file
+/// replaced by a symlink or a directory is only reported as a mode change.
+pub fn compare_versions(old: &[(PathBuf, String)], old_modes: &Modes, new: &[(PathBuf, String)], new_modes: &Modes) -> Vec<Change> {
+    let mode_changes = compare_modes(old, old_modes, new, new_modes);
+    let mut changes: Vec<Change> = compare_blobs(old, new)
+        .into_iter()
+        .filter(|change| {
+            !matches!(change, Change::Added { .. } | Change::Deleted { .. })
+                || !mode_changes.iter().any(|mode_change| mode_change.get_path() == change.get_path())
+        })
+        .collect();
+    changes.extend(mode_changes);
+    changes.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+    changes
+}
+
+/// Replace pairs of deleted and added files by renames.
+///
+/// A pair is a rename when both files have the same hash, or when the similarity of their
+/// contents is at least `threshold` percent. `read_content` returns the content of a blob.
+pub fn detect_renames<F>(changes: Vec<Change>, threshold: u8, read_content: F) -> Result<Vec<Change>, DitError>
+where
+    F: Fn(&String) -> Result<Vec<u8>, DitError>,
+{
+    let (mut deleted, others): (Vec<Change>, Vec<Change>) = changes
+        .into_iter()
+        .partition(|change| matches!(change, Change::Deleted { .. }));
+    let (mut added, mut result): (Vec<Change>, Vec<Change>) = others
+        .into_iter()
+        .partition(|change| matches!(change, Change::Added { .. }));
+
+    let mut renames: Vec<Change> = Vec::new();
+
+    // Exact renames first, they don't need the content of the files
+    deleted.retain(|del| {
+        if let Change::Deleted { path: from, hash: old_hash } = del {
+            let position = added.iter().position(|add| matches!(add, Change::Added { hash, .. } if hash == old_hash));
+            if let Some(index) = position {
+                if let Change::Added { path: to, hash: new_hash } = added.remove(index) {
+                    renames.push(Change::Renamed { from: from.clone(), to, old_hash: old_hash.clone(), new_hash, similarity: 100 });
+                    return false;
+                }
+            }
+        }
+        true
+    });
+
+    if !deleted.is_empty() && !added.is_empty() && threshold < 100 {
+        let mut added_contents: Vec<Vec<u8>> = Vec::new();
+        for add in added.iter() {
+            if let Change::Added { hash, .. } = add {
+                added_contents.push(read_content(hash)?);
+            }
+        }
+
+        let mut remaining_deleted: Vec<Change> = Vec::new();
+        for del in deleted.into_iter() {
+            let Change::Deleted { path: ref from, hash: ref old_hash } = del else {
+                continue;
+            };
+            let old_content = read_content(old_hash)?;
+
+            let best = added_contents
+                .iter()
+                .enumerate()
+                .map(|(index, content)| (index, similarity(&old_content, content)))
+                .filter(|(_, score)| *score >= threshold)
+                .max_by_key(|(_, score)| *score);
+
+            match best {
+                Some((index, score)) => {
+                    added_contents.remove(index);
+                    if let Change::Added { path: to, hash: new_hash } = added.remove(index) {
+                        renames.push(Change::Renamed { from: from.clone(), to, old_hash: old_hash.clone(), new_hash, similarity: score });
+                    }
+                }
+                None => remaining_deleted.push(del),
+            }
+        }
+        deleted = remaining_deleted;
+    }
+
+    result.extend(deleted);
+    result.extend(added);
+    result.extend(renames);
+    result.sort_by(|a, b| a.get_path().cmp(b.get_path()));
+    Ok(result)
+}
+
+/// Percentage of lines shared by two contents, binary contents only being similar when equal
+pub fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    if old == new {
+        return 100;
+    }
+    if is_binary_content(old) || is_binary_content(new) {
+        return 0;
+    }
+
+    let mut old_lines: HashMap<&[u8], usize> = HashMap::new();
+    for line in split_lines(old) {
+        *old_lines.entry(line).or_insert(0) += 1;
+    }
+
+    let mut common = 0;
+    for line in split_lines(new) {
+        if let Some(count) = old_lines.get_mut(line) {
+            if *count > 0 {
+                *count -= 1;
+                common += 1;
+            }
+        }
+    }
+
+    let total = split_lines(old).count() + split_lines(new).count();
+    if total == 0 {
+        return 100;
+    }
+    (common * 2 * 100 / total) as u8
+}
+
+/// Iterate over the lines of `content`, without their line ending
+fn split_lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    content
+        .split(|byte| *byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(move |_| !content.is_empty())
+}
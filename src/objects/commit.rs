@@ -2,37 +2,61 @@ use std::fs::File;
 use std::io::{BufRead, BufReader, Error, Read, Write};
 use std::path::PathBuf;
 
-use dit_file_encryptor::CompressedFile;
-use dit_id_generator::features::generator::generate;
 use dit_id_generator::traits::generator::Generator;
-use ptree2::print_tree;
-use repository_tree_creator::features::get_repository_tree_from_object_files::get_repository_tree_from_object_files;
-use repository_tree_creator::features::transcript_repository_to_files::{Mode, transcript_repository_tree_to_files};
-use repository_tree_creator::models::node::Node::TreeNode;
-use repository_tree_creator::models::tree::Tree;
+use ptree2::write_tree;
 
 use crate::error::DitError;
-use crate::features::display_message::{Color, display_message};
-use crate::features::init::{find_dit, find_info, find_objects, find_refs, find_staged, get_object_path, get_path_object_file};
+use crate::features::display_message::Color;
+use crate::features::init::get_head_hash;
 use crate::objects::branch::Branch;
+use crate::objects::hash::ObjectFormat;
+use crate::objects::index::Index;
+use crate::objects::lfs::smudge_files;
+use crate::objects::lock::Transaction;
+use crate::objects::mode::Modes;
 use crate::objects::node::Node;
-use crate::utils::{NULL_HASH, write_hash_file};
+use crate::objects::object::{Object, ObjectKind};
+use crate::objects::reflog::record_branch_movement;
+use crate::objects::signature::{decode_signature, encode_signature, sign};
+use crate::objects::store::load_tree;
+use crate::objects::tree::Node::TreeNode;
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::{collect_blobs, write_tree_files};
 
 #[derive(Clone, Debug)]
 pub struct Commit {
     hash: String,
     tree: String,
+    modes: String,
     parent: String,
+    /// Hash of the tip of the branch merged by the commit, empty when it is not a merge
+    merged: String,
     description: String,
+    /// Identity of the author, empty for the commits made before authors were recorded
+    author: String,
+    /// Unix timestamp of the creation of the commit, 0 when the author is not recorded
+    timestamp: u64,
+    /// Armored SSH signature of the other fields, empty when the commit is not signed
+    signature: String,
+    object_format: ObjectFormat,
 }
 
 impl Commit {
-    pub fn new(tree: String, parent: String, description: String) -> Commit {
+    /// Create a commit, its hash computed with the object format of `repo`. `modes` is the
+    /// hash of the mode object of the commit, empty when every entry is a regular file.
+    pub fn new(repo: &Repository, tree: String, modes: String, parent: String, description: String) -> Commit {
         let mut commit = Commit {
             hash: String::from(""),
             tree,
+            modes,
             parent,
+            merged: String::new(),
             description,
+            author: String::new(),
+            timestamp: 0,
+            signature: String::new(),
+            object_format: repo.get_object_format(),
         };
         let _ = commit.generate_id();
         commit
@@ -54,6 +78,21 @@ impl Commit {
         self.parent = parent;
     }
 
+    pub fn get_merged(&self) -> &String {
+        &self.merged
+    }
+
+    /// Record `merged` as the tip of the branch merged by the commit, which changes its hash
+    pub fn set_merged(&mut self, merged: String) {
+        self.merged = merged;
+        let _ = self.generate_id();
+    }
+
+    /// Whether the commit merges a branch, and has two parents
+    pub fn is_merge(&self) -> bool {
+        !self.merged.is_empty()
+    }
+
     pub fn get_tree(&self) -> &String {
         &self.tree
     }
@@ -62,6 +101,10 @@ impl Commit {
         self.tree = tree;
     }
 
+    pub fn get_modes(&self) -> &String {
+        &self.modes
+    }
+
     pub fn get_description(&self) -> &String {
         &self.description
     }
@@ -70,131 +113,258 @@ impl Commit {
         self.description = description;
     }
 
-    pub fn display(&self) {
-        println!("hash: {} \n description: {}", self.hash, self.description);
+    pub fn get_author(&self) -> &String {
+        &self.author
     }
 
-    pub fn transcript_commit_to_file(&self) -> Result<(), DitError> {
-        let object_path = find_objects();
-        let commit_path = get_object_path(&object_path, &self.hash).map_err(DitError::IoError)?;
-        let info_path = find_info();
-        let staged_path = find_staged();
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
 
-        if !commit_path.exists() {
-            let _ = File::create(&commit_path).map_err(DitError::IoError)?;
+    /// Record `author` as the author of the commit made at `timestamp`, which changes its hash
+    pub fn set_author(&mut self, author: String, timestamp: u64) {
+        self.author = author;
+        self.timestamp = timestamp;
+        let _ = self.generate_id();
+    }
 
-            let mut writer = CompressedFile::new(commit_path)
-                .open_for_write()
-                .map_err(|e| DitError::IoError(e))?;
+    pub fn get_signature(&self) -> &String {
+        &self.signature
+    }
 
-            self.write_commit(&mut writer).map_err(DitError::IoError)?;
+    /// Return the content of the commit object without its signature, which is what is signed
+    pub fn get_payload(&self) -> Vec<u8> {
+        let mut payload: Vec<u8> = Vec::new();
+        let _ = self.write_fields(&mut payload, "");
+        payload
+    }
 
-            self.reference_commit()?;
+    /// Sign the commit with the signing key of `repo`, which changes its hash
+    pub fn sign(&mut self, repo: &Repository) -> Result<(), DitError> {
+        self.signature = sign(repo, &self.get_payload())?;
+        let _ = self.generate_id();
+        Ok(())
+    }
 
-            write_hash_file(self.hash.clone(), info_path, 5)
-                .map_err(DitError::IoError)?;
+    pub fn display(&self) {
+        println!("hash: {} \n description: {}", self.hash, self.description);
+    }
 
-            write_hash_file(String::from(NULL_HASH), staged_path, 0)
-                .map_err(DitError::IoError)?;
+    /// Write the commit, make it the tip of the current branch, record the movement in the
+    /// reflog with `reason` and empty the staged tree and the deleted elements.
+    pub fn transcript_commit_to_file(&self, repo: &Repository, reason: &str) -> Result<(), DitError> {
+        let mut transaction = Transaction::new(repo);
+        transaction.lock(repo.get_info_path())?;
+        let old_head = get_head_hash(repo)?;
+
+        if self.write_object(repo)? {
+            let branch = Branch::get_current_branch(repo)?;
+            self.update_branch(&mut transaction, &branch, &old_head, reason)?;
+            transaction.commit()?;
         }
         Ok(())
     }
 
-    fn write_commit(&self, writer: &mut Box<dyn Write>) -> Result<(), Error> {
-        writeln!(writer, "tree {}", self.tree)?;
-        writeln!(writer, "pare {}", self.parent)?;
-        write!(writer, "{}", self.description)?;
-        Ok(())
+    /// Replace the commit `old_hash` at the tip of the current branch by this commit
+    /// and record the old tip in the reflog.
+    pub fn amend_commit_to_file(&self, repo: &Repository, old_hash: &String) -> Result<(), DitError> {
+        let mut transaction = Transaction::new(repo);
+        transaction.lock(repo.get_info_path())?;
+        let branch = Branch::get_current_branch(repo)?;
+        transaction.lock(repo.get_refs_path().join(branch.get_name()))?;
+        if Branch::read_ref(repo, branch.get_name())? != *old_hash {
+            return Err(DitError::UnexpectedComportement(format!("Commit {} is not the tip of branch {}", old_hash, branch.get_name())));
+        }
+
+        self.write_object(repo)?;
+        self.update_branch(&mut transaction, &branch, old_hash, "commit (amend)")?;
+        transaction.commit()
     }
 
-    fn reference_commit(&self) -> Result<(), DitError> {
-        let branch = Branch::get_current_branch()?;
-        let branch_path = PathBuf::from(format!("./.dit/refs/{}", branch.get_name()));
+    /// Move the tip of `branch` and HEAD from `old_hash` to this commit, and empty the staged
+    /// modes and the deleted elements, as part of `transaction`
+    fn update_branch(&self, transaction: &mut Transaction, branch: &Branch, old_hash: &str, reason: &str) -> Result<(), DitError> {
+        let repo = transaction.get_repo();
+        Branch::write_ref_to(transaction, branch.get_name(), &self.hash)?;
+        record_branch_movement(transaction, branch.get_name(), old_hash, &self.hash, reason)?;
+        Branch::write_info_to(transaction, branch.get_name(), &self.hash)?;
+        transaction.write(repo.get_modes_path(), &[])?;
+        transaction.write(repo.get_deleted_path(), &[])
+    }
 
-        let mut commit_hash: String = self.hash.clone();
-        commit_hash.push('\n');
+    /// Write the commit object, return false if it already exists.
+    pub fn write_object(&self, repo: &Repository) -> Result<bool, DitError> {
+        let mut content: Vec<u8> = Vec::new();
+        self.write_commit(&mut content).map_err(DitError::IoError)?;
 
-        CompressedFile::new(branch_path)
-            .append_to_file(commit_hash.as_bytes())
-            .map_err(|e| DitError::IoError(e))?;
-        Ok(())
+        let written = Object::new(ObjectKind::Commit, content).write(repo, &self.hash)?;
+        if written {
+            repo.report(Event::ObjectWritten(self.hash.clone()));
+        }
+        Ok(written)
+    }
+
+    fn write_commit<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        self.write_fields(writer, &encode_signature(&self.signature))
     }
 
-    pub fn reset_description_file() -> Result<(), Error> {
-        let path = PathBuf::from("./.dit/commit");
-        File::open(path)?;
+    /// Write the headers, then `signature` as `sig ` lines, then the description
+    fn write_fields<W: Write>(&self, writer: &mut W, signature: &str) -> Result<(), Error> {
+        writeln!(writer, "tree {}", self.tree)?;
+        if !self.modes.is_empty() {
+            writeln!(writer, "mode {}", self.modes)?;
+        }
+        writeln!(writer, "pare {}", self.parent)?;
+        if !self.merged.is_empty() {
+            writeln!(writer, "mrge {}", self.merged)?;
+        }
+        if !self.author.is_empty() {
+            writeln!(writer, "auth {} {}", self.author, self.timestamp)?;
+        }
+        write!(writer, "{}{}", signature, self.description)?;
         Ok(())
     }
 
-    pub fn get_commit_from_file(hash: String) -> Result<Commit, Error> {
-        let file = get_path_object_file(hash)?;
-        let reader = CompressedFile::new(file)
-            .open_for_read()?;
+    /// Empty the description of the next commit
+    pub fn reset_description_file(repo: &Repository) -> Result<(), Error> {
+        File::create(repo.get_commit_path())?;
+        Ok(())
+    }
 
-        let mut buf_reader = BufReader::new(reader);
+    pub fn get_commit_from_file(repo: &Repository, hash: String) -> Result<Commit, DitError> {
+        let object = Object::read(repo, &hash)?;
+        if object.get_kind() != ObjectKind::Commit {
+            return Err(DitError::CorruptObject {
+                hash,
+                reason: format!("expected a commit, found a {}", object.get_kind()),
+            });
+        }
+        let mut buf_reader = BufReader::new(object.get_content().as_slice());
 
         let mut tree_line: String = Default::default();
-        buf_reader.read_line(&mut tree_line)?;
-        let tree = &tree_line[5..45];
+        buf_reader.read_line(&mut tree_line).map_err(DitError::IoError)?;
+        let tree = Self::read_header(&hash, &tree_line, "tree ")?;
 
         let mut parent_line: String = Default::default();
-        buf_reader.read_line(&mut parent_line)?;
-        let parent = &parent_line[5..45];
+        buf_reader.read_line(&mut parent_line).map_err(DitError::IoError)?;
+        let mut modes = "";
+        let mode_line = parent_line.clone();
+        if mode_line.starts_with("mode ") {
+            modes = Self::read_header(&hash, &mode_line, "mode ")?;
+            parent_line.clear();
+            buf_reader.read_line(&mut parent_line).map_err(DitError::IoError)?;
+        }
+        let parent = Self::read_header(&hash, &parent_line, "pare ")?;
+
+        let mut rest: String = Default::default();
+        buf_reader.read_to_string(&mut rest).map_err(DitError::IoError)?;
+        let (merged, rest) = if rest.starts_with("mrge ") {
+            let (line, rest) = rest.split_once('\n').unwrap_or((rest.as_str(), ""));
+            (Self::read_header(&hash, line, "mrge ")?, rest)
+        } else {
+            ("", rest.as_str())
+        };
+        let ids = [("tree", tree), ("modes", modes), ("parent", parent), ("merged parent", merged)];
+        for (name, id) in ids.iter().filter(|(_, id)| !id.is_empty()) {
+            if !repo.get_object_format().is_valid_hash(id) {
+                return Err(DitError::CorruptObject {
+                    hash,
+                    reason: format!("{} {} is not a {} id", name, id, repo.get_object_format()),
+                });
+            }
+        }
 
-        let mut description: String = Default::default();
-        buf_reader.read_to_string(&mut description)?;
+        let (author, rest) = match rest.strip_prefix("auth ") {
+            Some(line) => {
+                let (line, rest) = line.split_once('\n').unwrap_or((line, ""));
+                (Some(Self::read_author(&hash, line)?), rest)
+            }
+            None => (None, rest),
+        };
+        let (signature, description) = decode_signature(rest);
 
-        Ok(Commit::new(
+        let mut commit = Commit::new(
+            repo,
             String::from(tree),
+            String::from(modes),
             String::from(parent),
-            description,
-        ))
+            description.to_string(),
+        );
+        commit.merged = merged.to_string();
+        if let Some((author, timestamp)) = author {
+            commit.author = author;
+            commit.timestamp = timestamp;
+        }
+        if !signature.is_empty() {
+            commit.signature = signature;
+        }
+        let _ = commit.generate_id();
+        Ok(commit)
     }
 
-    pub fn create_commit_tree(branch: Branch) -> Result<Option<Node>, Error> {
-        let commits = Self::get_commit_list(branch.get_name().to_owned())?;
+    /// Parse the value of the `auth` header, the identity of the author followed by a timestamp
+    fn read_author(hash: &str, line: &str) -> Result<(String, u64), DitError> {
+        match line.rsplit_once(' ') {
+            Some((author, timestamp)) if !author.is_empty() => match timestamp.parse::<u64>() {
+                Ok(timestamp) => Ok((author.to_string(), timestamp)),
+                Err(_) => Err(DitError::CorruptObject { hash: hash.to_string(), reason: format!("invalid author date '{}'", timestamp) }),
+            },
+            _ => Err(DitError::CorruptObject { hash: hash.to_string(), reason: String::from("malformed auth header") }),
+        }
+    }
+
+    fn read_header<'a>(hash: &String, line: &'a str, name: &str) -> Result<&'a str, DitError> {
+        match line.strip_prefix(name).map(str::trim_end) {
+            Some(value) if !value.is_empty() && value.chars().all(|c| c.is_ascii_hexdigit()) => Ok(value),
+            _ => Err(DitError::CorruptObject {
+                hash: hash.clone(),
+                reason: format!("missing {}header", name),
+            }),
+        }
+    }
+
+    pub fn create_commit_tree(repo: &Repository, branch: Branch) -> Result<Option<Node>, DitError> {
+        let commits = Self::get_commit_list(repo, branch.get_name().to_owned())?;
 
         if commits.is_empty() {
             return Ok(None);
         }
 
-        let mut root = None;
+        let mut root: Option<Node> = None;
 
         for commit in commits.iter() {
             let node = Node::new(commit.clone(), Vec::new());
-
-            if root.is_none() {
-                root = Some(node.clone());
-            }
-
-            root.as_mut().unwrap().add_child_to_tree(&node);
+            root.get_or_insert_with(|| node.clone()).add_child_to_tree(&node);
         }
 
         return Ok(root);
     }
 
-    pub fn get_commit_list(branch_name: String) -> Result<Vec<Commit>, Error> {
-        let branch_path = find_refs().join(branch_name);
-
-        let reader = BufReader::new(
-            CompressedFile::new(branch_path)
-                .open_for_read()?
-        );
-
+    /// Return the commits of a branch, oldest first, following the parents from its tip
+    pub fn get_commit_list(repo: &Repository, branch_name: String) -> Result<Vec<Commit>, DitError> {
+        let mut hash = Branch::get_branch(repo, branch_name)?.get_head().clone();
         let mut commits: Vec<Commit> = Vec::new();
-
-        for line in reader.lines() {
-            let content = line.unwrap();
-            let c = Commit::get_commit_from_file(content)?;
-            commits.push(c);
+        while !repo.get_object_format().is_null_hash(&hash) {
+            let commit = Commit::get_commit_from_file(repo, hash)?;
+            hash = commit.get_parent().clone();
+            commits.push(commit);
         }
-
+        commits.reverse();
         Ok(commits)
     }
-    pub fn commit_exist(hash: &String) -> Result<bool, DitError> {
-        let branch = Branch::get_current_branch()?;
+
+    /// Return the hashes of the commits of a branch, oldest first
+    pub fn get_commit_hashes(repo: &Repository, branch_name: String) -> Result<Vec<String>, DitError> {
+        Ok(Self::get_commit_list(repo, branch_name)?
+            .into_iter()
+            .map(|commit| commit.hash)
+            .collect())
+    }
+
+    pub fn commit_exist(repo: &Repository, hash: &String) -> Result<bool, DitError> {
+        let branch = Branch::get_current_branch(repo)?;
         let commits =
-            Self::get_commit_list(branch.get_name().to_string()).map_err(DitError::IoError)?;
+            Self::get_commit_list(repo, branch.get_name().to_string())?;
         for c in commits.iter() {
             if *c.get_hash() == *hash {
                 return Ok(true);
@@ -203,36 +373,63 @@ impl Commit {
         return Ok(false);
     }
 
-    pub fn display_commit_tree() -> Result<(), DitError> {
+    pub fn display_commit_tree(repo: &Repository) -> Result<(), DitError> {
         let root =
-            Commit::create_commit_tree(Branch::get_current_branch()?).map_err(DitError::IoError)?;
+            Commit::create_commit_tree(repo, Branch::get_current_branch(repo)?)?;
         match root {
-            Some(tree) => print_tree(&tree).expect("Error while displaying commit tree"),
-            None => display_message("No commit on this branch", Color::BLUE),
+            Some(tree) => {
+                let mut output: Vec<u8> = Vec::new();
+                write_tree(&tree, &mut output).map_err(DitError::IoError)?;
+                for line in String::from_utf8_lossy(&output).lines() {
+                    repo.report(Event::Output(line.to_string(), Color::DEFAULT));
+                }
+            }
+            None => repo.report(Event::Message(String::from("No commit on this branch"), Color::BLUE)),
         }
         Ok(())
     }
 
-    pub fn recreate_files(&self) -> Result<(), DitError> {
-        let mut tree = Tree::default();
-        let dit_path = find_dit().unwrap();
-        let project_path = dit_path.parent().unwrap();
-        get_repository_tree_from_object_files(&mut tree, &self.tree, &find_objects()).map_err(|e| {
-            display_message("Error getting files", Color::RED);
-            DitError::UnexpectedComportement(format!("Details: {}", e))
-        })?;
-        transcript_repository_tree_to_files(&TreeNode(tree), &project_path.to_path_buf(), &Mode::Complete).map_err(|e1| {
-            display_message("Error recreating files.", Color::RED);
-            DitError::UnexpectedComportement(format!("Details: {}.", e1))
-        })?;
-        Ok(())
+    /// Write the files of the commit in the working directory with their modes and the content
+    /// of the large files, replace the symlinks and empty directories of the HEAD commit by the
+    /// ones of the commit, and make the index hold its files
+    pub fn recreate_files(&self, repo: &Repository) -> Result<(), DitError> {
+        let project_path = repo.get_work_dir();
+        let modes = Modes::load(repo, &self.modes)?;
+        let head_hash = get_head_hash(repo)?;
+        if !repo.get_object_format().is_null_hash(&head_hash) {
+            let head = Commit::get_commit_from_file(repo, head_hash)?;
+            Modes::load(repo, &head.modes)?.remove_special_entries(repo, &modes)?;
+        }
+
+        let root = TreeNode(load_tree(repo, &self.tree)?);
+        write_tree_files(repo, &root, project_path)?;
+
+        let mut blobs: Vec<(PathBuf, String)> = vec![];
+        collect_blobs(&root, &mut blobs);
+        smudge_files(repo, &blobs)?;
+        for (path, _) in blobs.iter() {
+            repo.report(Event::FileCheckedOut(path.clone()));
+        }
+        modes.apply(repo, &blobs)?;
+
+        let (mut index, lock) = Index::lock(repo)?;
+        index.reset(&blobs);
+        index.save_to(repo, lock)
     }
 }
 
 impl Generator for Commit {
     fn generate_id(&mut self) -> String {
-        let content = self.tree.clone() + &*self.parent + &*self.description;
-        let hash = generate(content);
+        // Commits without mode object keep the id they had before modes were recorded, unsigned
+        // commits the id they had before signatures, commits without author the id they had
+        // before authors and commits which are not merges the id they had before merged parents
+        let mut content = self.tree.clone() + &*self.modes + &*self.parent + &*self.merged;
+        if !self.author.is_empty() {
+            content += &format!("{} {}", self.author, self.timestamp);
+        }
+        content += &*self.description;
+        content += &*self.signature;
+        let hash = self.object_format.hash(&content);
         self.set_hash(hash.clone());
         hash
     }
@@ -0,0 +1,183 @@
+use std::{env, fs};
+use std::path::Path;
+
+use crate::error::DitError;
+use crate::objects::hash::ObjectFormat;
+use crate::objects::lfs::Pointer;
+use crate::objects::lock::LockFile;
+use crate::repository::Repository;
+use crate::utils::{is_binary_content, matches_pattern};
+
+pub const RENAME_THRESHOLD: &str = "diff.renameThreshold";
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+pub const BINARY_PATTERNS: &str = "core.binary";
+pub const LFS_THRESHOLD: &str = "lfs.threshold";
+pub const LFS_PATTERNS: &str = "lfs.track";
+pub const USER_NAME: &str = "user.name";
+pub const USER_EMAIL: &str = "user.email";
+pub const REPOSITORY_FORMAT_VERSION: &str = "core.repositoryFormatVersion";
+pub const OBJECT_FORMAT: &str = "extensions.objectFormat";
+pub const CRYPT_CIPHER: &str = "crypt.cipher";
+pub const CRYPT_SALT: &str = "crypt.salt";
+pub const CRYPT_KEY: &str = "crypt.key";
+pub const CRYPT_PREVIOUS_KEYS: &str = "crypt.previousKeys";
+pub const CRYPT_KEY_FILE: &str = "crypt.keyFile";
+pub const SIGNING_KEY: &str = "user.signingKey";
+pub const COMMIT_SIGN: &str = "commit.sign";
+pub const TAG_SIGN: &str = "tag.sign";
+pub const ALLOWED_SIGNERS_FILE: &str = "signing.allowedSignersFile";
+/// Repository format written by `dit init`. Version 0 predates the settings and uses SHA-1,
+/// version 1 names its object format in `extensions.objectFormat`.
+pub const REPOSITORY_FORMAT: u32 = 1;
+
+/// Repository settings stored as `key = value` lines in `.dit/config`
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    pub fn load(repo: &Repository) -> Result<Config, DitError> {
+        let config_path = repo.get_config_path();
+        if !config_path.is_file() {
+            return Ok(Config::default());
+        }
+
+        let content = fs::read_to_string(config_path).map_err(DitError::IoError)?;
+        let mut entries: Vec<(String, String)> = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) => entries.push((key.trim().to_string(), value.trim().to_string())),
+                None => return Err(DitError::UnexpectedComportement(format!("Invalid config line: {}", line))),
+            }
+        }
+        Ok(Config { entries })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn set(&mut self, key: String, value: String) {
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key, value)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(k, _)| k != key);
+    }
+
+    pub fn save(&self, repo: &Repository) -> Result<(), DitError> {
+        let lock = LockFile::acquire(&repo.get_config_path())?;
+        let content: String = self.entries.iter().map(|(key, value)| format!("{} = {}\n", key, value)).collect();
+        lock.write_plain(content.as_bytes())?;
+        lock.commit()
+    }
+
+    /// Value of the boolean setting `key`, false when it is not set
+    pub fn get_bool(&self, key: &str) -> Result<bool, DitError> {
+        match self.get(key).map(|value| value.to_lowercase()) {
+            None => Ok(false),
+            Some(value) if value == "true" => Ok(true),
+            Some(value) if value == "false" => Ok(false),
+            Some(value) => Err(DitError::UnexpectedComportement(format!("{} must be true or false, found {}", key, value))),
+        }
+    }
+
+    /// Minimum similarity, in percent, for a deleted and an added file to be shown as a rename
+    pub fn get_rename_threshold(&self) -> Result<u8, DitError> {
+        match self.get(RENAME_THRESHOLD) {
+            Some(value) => match value.parse::<u8>() {
+                Ok(threshold) if threshold <= 100 => Ok(threshold),
+                _ => Err(DitError::UnexpectedComportement(format!("{} must be a percentage, found {}", RENAME_THRESHOLD, value))),
+            },
+            None => Ok(DEFAULT_RENAME_THRESHOLD),
+        }
+    }
+
+    /// Whether the file `path` of content `content` is binary: its path matches one of the comma
+    /// separated patterns of `core.binary`, or its content holds a NUL byte. The pointers of
+    /// the large files stand for binary files too.
+    pub fn is_binary(&self, path: &Path, content: &[u8]) -> bool {
+        self.matches_any(BINARY_PATTERNS, path) || is_binary_content(content) || Pointer::parse(content).is_some()
+    }
+
+    /// Minimum size in bytes of the files stored as large files, followed by `k`, `M` or `G`
+    /// for multiples of 1024, none when `lfs.threshold` is not set
+    pub fn get_lfs_threshold(&self) -> Result<Option<u64>, DitError> {
+        let Some(value) = self.get(LFS_THRESHOLD) else {
+            return Ok(None);
+        };
+        let (number, unit) = match value.chars().last() {
+            Some('k') | Some('K') => (&value[..value.len() - 1], 1 << 10),
+            Some('m') | Some('M') => (&value[..value.len() - 1], 1 << 20),
+            Some('g') | Some('G') => (&value[..value.len() - 1], 1 << 30),
+            _ => (value.as_str(), 1),
+        };
+        match number.trim().parse::<u64>() {
+            Ok(size) => Ok(Some(size.saturating_mul(unit))),
+            Err(_) => Err(DitError::UnexpectedComportement(format!("{} must be a size in bytes, found {}", LFS_THRESHOLD, value))),
+        }
+    }
+
+    /// Whether the file `path` of `size` bytes is stored as a large file: it reaches
+    /// `lfs.threshold` or matches one of the comma separated patterns of `lfs.track`
+    pub fn is_large_file(&self, path: &Path, size: u64) -> Result<bool, DitError> {
+        let exceeds_threshold = self.get_lfs_threshold()?.is_some_and(|threshold| size >= threshold);
+        Ok(exceeds_threshold || self.matches_any(LFS_PATTERNS, path))
+    }
+
+    /// Whether `path` matches one of the comma separated patterns of the setting `key`
+    fn matches_any(&self, key: &str, path: &Path) -> bool {
+        self.get(key).is_some_and(|patterns| {
+            patterns
+                .split(',')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .any(|pattern| matches_pattern(pattern, path))
+        })
+    }
+
+    /// Return `name <email>` from the config, falling back on the system user name
+    pub fn get_identity(&self) -> String {
+        let name = match self.get(USER_NAME) {
+            Some(name) => name.clone(),
+            None => env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_else(|_| String::from("unknown")),
+        };
+        match self.get(USER_EMAIL) {
+            Some(email) => format!("{} <{}>", name, email),
+            None => name,
+        }
+    }
+
+    /// Return the hash algorithm of the object ids, failing on a repository format or an
+    /// object format this version of dit does not know
+    pub fn get_object_format(&self) -> Result<ObjectFormat, DitError> {
+        let version = match self.get(REPOSITORY_FORMAT_VERSION) {
+            Some(value) => value.parse::<u32>().map_err(|_| {
+                DitError::UnexpectedComportement(format!("{} must be a number, found {}", REPOSITORY_FORMAT_VERSION, value))
+            })?,
+            None => 0,
+        };
+        if version > REPOSITORY_FORMAT {
+            return Err(DitError::UnsupportedRepositoryFormat(format!("repository format version {}", version)));
+        }
+        match self.get(OBJECT_FORMAT) {
+            Some(name) if version > 0 => ObjectFormat::from_name(name)
+                .ok_or_else(|| DitError::UnsupportedRepositoryFormat(format!("object format {}", name))),
+            _ => Ok(ObjectFormat::Sha1),
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::{env, fmt, fs};
+use std::path::PathBuf;
+
+use argon2::Argon2;
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::aead::rand_core::RngCore;
+
+use crate::error::DitError;
+use crate::objects::config::{Config, CRYPT_CIPHER, CRYPT_KEY, CRYPT_KEY_FILE, CRYPT_PREVIOUS_KEYS, CRYPT_SALT};
+
+pub const CIPHER_NAME: &str = "xchacha20poly1305";
+pub const PASSPHRASE_VARIABLE: &str = "DIT_PASSPHRASE";
+pub const KEY_FILE_VARIABLE: &str = "DIT_KEY_FILE";
+/// Passphrase replacing the current one on `dit key rotate`
+pub const NEW_PASSPHRASE_VARIABLE: &str = "DIT_NEW_PASSPHRASE";
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+const MAGIC: &[u8; 4] = b"DENC";
+const VERSION: u8 = 1;
+
+/// Secret protecting the key of an encrypted repository: a passphrase or the content of a
+/// key file, stretched with Argon2id
+#[derive(Clone)]
+pub enum KeySource {
+    Passphrase(String),
+    KeyFile(PathBuf),
+}
+
+impl KeySource {
+    /// Return the secret given by `DIT_PASSPHRASE`, `DIT_KEY_FILE` or the `crypt.keyFile`
+    /// setting of `config`, in this order
+    pub fn from_environment(config: &Config) -> Option<KeySource> {
+        if let Some(passphrase) = env::var(PASSPHRASE_VARIABLE).ok().filter(|value| !value.is_empty()) {
+            return Some(KeySource::Passphrase(passphrase));
+        }
+        env::var(KEY_FILE_VARIABLE)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .or_else(|| config.get(CRYPT_KEY_FILE).cloned())
+            .map(|path| KeySource::KeyFile(PathBuf::from(path)))
+    }
+
+    fn read_secret(&self) -> Result<Vec<u8>, DitError> {
+        let secret = match self {
+            KeySource::Passphrase(passphrase) => passphrase.as_bytes().to_vec(),
+            KeySource::KeyFile(path) => fs::read(path).map_err(|e| {
+                DitError::UnexpectedComportement(format!("Unable to read the key file {}: {}", path.display(), e))
+            })?,
+        };
+        if secret.is_empty() {
+            return Err(DitError::UnexpectedComportement(String::from("The key of the repository can not be empty")));
+        }
+        Ok(secret)
+    }
+
+    /// Return the key wrapping the data keys, derived from the secret and `salt`
+    fn derive_key(&self, salt: &[u8]) -> Result<[u8; KEY_SIZE], DitError> {
+        let mut key = [0u8; KEY_SIZE];
+        Argon2::default()
+            .hash_password_into(&self.read_secret()?, salt, &mut key)
+            .map_err(|e| DitError::UnexpectedComportement(format!("Unable to derive the key of the repository: {}", e)))?;
+        Ok(key)
+    }
+}
+
+/// Data keys of an encrypted repository, used with XChaCha20-Poly1305 so that altered or
+/// wrongly decrypted content is detected. Content is encrypted with the first key and
+/// decrypted with any of them, the others being the keys replaced by a rotation which did not
+/// finish yet.
+///
+/// The data keys are random. They are stored in the config wrapped with a key derived from a
+/// `KeySource`, so that changing the secret only rewrites the config. Encrypted content is
+/// `DENC`, a version byte, the 24 bytes nonce, then the ciphertext and its tag.
+#[derive(Clone)]
+pub struct Cipher {
+    keys: Vec<[u8; KEY_SIZE]>,
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cipher {{ {} keys }}", self.keys.len())
+    }
+}
+
+impl Cipher {
+    /// A cipher with a new random key
+    pub fn generate() -> Result<Cipher, DitError> {
+        let mut key = [0u8; KEY_SIZE];
+        fill_random(&mut key)?;
+        Ok(Cipher { keys: vec![key] })
+    }
+
+    /// Return the cipher of the repository configured by `config`, unlocked with the secret of
+    /// the environment, None when the repository is not encrypted
+    pub fn load(config: &Config) -> Result<Option<Cipher>, DitError> {
+        match config.get(CRYPT_CIPHER) {
+            None => Ok(None),
+            Some(name) if name != CIPHER_NAME => Err(DitError::UnsupportedRepositoryFormat(format!("cipher {}", name))),
+            Some(_) => {
+                let source = KeySource::from_environment(config).ok_or(DitError::MissingKey)?;
+                Self::unlock(config, &source).map(Some)
+            }
+        }
+    }
+
+    /// Return the cipher recorded in `config`, failing with `WrongKey` when `source` does not
+    /// unwrap its keys
+    pub fn unlock(config: &Config, source: &KeySource) -> Result<Cipher, DitError> {
+        let salt = decode_hex(config.get(CRYPT_SALT).ok_or_else(|| missing_setting(CRYPT_SALT))?, CRYPT_SALT)?;
+        let wrapping_key = source.derive_key(&salt)?;
+
+        let mut wrapped_keys = vec![config.get(CRYPT_KEY).ok_or_else(|| missing_setting(CRYPT_KEY))?.as_str()];
+        if let Some(previous_keys) = config.get(CRYPT_PREVIOUS_KEYS) {
+            wrapped_keys.extend(previous_keys.split(',').map(str::trim).filter(|key| !key.is_empty()));
+        }
+        let mut keys = Vec::new();
+        for wrapped_key in wrapped_keys {
+            let key = open(&wrapping_key, &decode_hex(wrapped_key, CRYPT_KEY)?).ok_or(DitError::WrongKey)?;
+            keys.push(key.try_into().map_err(|_| DitError::WrongKey)?);
+        }
+        Ok(Cipher { keys })
+    }
+
+    /// Record the keys in `config`, wrapped with a key derived from `source` and a new salt
+    pub fn save(&self, config: &mut Config, source: &KeySource) -> Result<(), DitError> {
+        let mut salt = [0u8; SALT_SIZE];
+        fill_random(&mut salt)?;
+        let wrapping_key = source.derive_key(&salt)?;
+        let mut wrapped_keys = Vec::new();
+        for key in self.keys.iter() {
+            wrapped_keys.push(encode_hex(&seal(&wrapping_key, key)?));
+        }
+
+        config.set(String::from(CRYPT_CIPHER), String::from(CIPHER_NAME));
+        config.set(String::from(CRYPT_SALT), encode_hex(&salt));
+        config.set(String::from(CRYPT_KEY), wrapped_keys.remove(0));
+        if wrapped_keys.is_empty() {
+            config.remove(CRYPT_PREVIOUS_KEYS);
+        } else {
+            config.set(String::from(CRYPT_PREVIOUS_KEYS), wrapped_keys.join(","));
+        }
+        match source {
+            KeySource::KeyFile(path) => {
+                let path = fs::canonicalize(path).map_err(DitError::IoError)?;
+                config.set(String::from(CRYPT_KEY_FILE), path.display().to_string());
+            }
+            KeySource::Passphrase(_) => config.remove(CRYPT_KEY_FILE),
+        }
+        Ok(())
+    }
+
+    /// Return a cipher encrypting with a new key and still decrypting with the current keys
+    pub fn rotate(&self) -> Result<Cipher, DitError> {
+        let mut cipher = Self::generate()?;
+        cipher.keys.extend(self.keys.iter().copied());
+        Ok(cipher)
+    }
+
+    /// Return the cipher without the keys replaced by a rotation
+    pub fn get_current(&self) -> Cipher {
+        Cipher { keys: self.keys[..1].to_vec() }
+    }
+
+    pub fn encrypt(&self, content: &[u8]) -> Result<Vec<u8>, DitError> {
+        seal(&self.keys[0], content)
+    }
+
+    /// Return the content encrypted in `data`, None when no key authenticates it
+    pub fn decrypt(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.keys.iter().find_map(|key| open(key, data))
+    }
+}
+
+fn seal(key: &[u8; KEY_SIZE], content: &[u8]) -> Result<Vec<u8>, DitError> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    fill_random(&mut nonce)?;
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(XNonce::from_slice(&nonce), content)
+        .map_err(|_| DitError::UnexpectedComportement(String::from("Encryption failed")))?;
+
+    let mut data = Vec::with_capacity(MAGIC.len() + 1 + NONCE_SIZE + ciphertext.len());
+    data.extend_from_slice(MAGIC);
+    data.push(VERSION);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(data)
+}
+
+fn open(key: &[u8; KEY_SIZE], data: &[u8]) -> Option<Vec<u8>> {
+    let header_size = MAGIC.len() + 1;
+    if data.len() < header_size + NONCE_SIZE || &data[..MAGIC.len()] != MAGIC || data[MAGIC.len()] != VERSION {
+        return None;
+    }
+    let (nonce, ciphertext) = data[header_size..].split_at(NONCE_SIZE);
+    XChaCha20Poly1305::new(key.into()).decrypt(XNonce::from_slice(nonce), ciphertext).ok()
+}
+
+fn fill_random(bytes: &mut [u8]) -> Result<(), DitError> {
+    OsRng
+        .try_fill_bytes(bytes)
+        .map_err(|e| DitError::UnexpectedComportement(format!("No random source: {}", e)))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(value: &str, key: &str) -> Result<Vec<u8>, DitError> {
+    let invalid = || DitError::UnexpectedComportement(format!("{} must be hexadecimal", key));
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|byte| byte.len() == 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+fn missing_setting(key: &str) -> DitError {
+    DitError::UnexpectedComportement(format!("The repository is encrypted but {} is missing from its config", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_content_round_trips() {
+        let cipher = Cipher::generate().unwrap();
+        let data = cipher.encrypt(b"secret content").unwrap();
+        assert_eq!(&data[..MAGIC.len()], MAGIC);
+        assert!(!data.windows(6).any(|window| window == b"secret"));
+        assert_eq!(cipher.decrypt(&data).unwrap(), b"secret content");
+
+        let mut altered = data.clone();
+        *altered.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt(&altered).is_none());
+        assert!(Cipher::generate().unwrap().decrypt(&data).is_none());
+    }
+
+    #[test]
+    fn saved_keys_are_unlocked_by_their_passphrase_only() {
+        let cipher = Cipher::generate().unwrap().rotate().unwrap();
+        let mut config = Config::default();
+        cipher.save(&mut config, &KeySource::Passphrase(String::from("right"))).unwrap();
+        assert!(config.get(CRYPT_PREVIOUS_KEYS).is_some());
+
+        let data = cipher.get_current().encrypt(b"content").unwrap();
+        let unlocked = Cipher::unlock(&config, &KeySource::Passphrase(String::from("right"))).unwrap();
+        assert_eq!(unlocked.decrypt(&data).unwrap(), b"content");
+        assert!(matches!(
+            Cipher::unlock(&config, &KeySource::Passphrase(String::from("wrong"))),
+            Err(DitError::WrongKey)
+        ));
+    }
+
+    #[test]
+    fn rotated_cipher_decrypts_content_of_the_previous_key() {
+        let cipher = Cipher::generate().unwrap();
+        let data = cipher.encrypt(b"content").unwrap();
+        let rotated = cipher.rotate().unwrap();
+        assert_eq!(rotated.decrypt(&data).unwrap(), b"content");
+        assert!(rotated.get_current().decrypt(&data).is_none());
+        assert!(cipher.decrypt(&rotated.encrypt(b"content").unwrap()).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Hash algorithm of the object ids of a repository, chosen by `dit init` and stored in the
+/// `extensions.objectFormat` setting
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ObjectFormat {
+    /// SHA-1, 40 hex characters, the format of the repositories created before the setting
+    /// existed
+    #[default]
+    Sha1,
+    /// SHA-256, 64 hex characters
+    Sha256,
+}
+
+impl ObjectFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ObjectFormat> {
+        match name {
+            "sha1" => Some(ObjectFormat::Sha1),
+            "sha256" => Some(ObjectFormat::Sha256),
+            _ => None,
+        }
+    }
+
+    /// Number of hex characters of an object id
+    pub fn get_hex_length(&self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 40,
+            ObjectFormat::Sha256 => 64,
+        }
+    }
+
+    /// Id standing for no object, such as the parent of a first commit
+    pub fn get_null_hash(&self) -> String {
+        "0".repeat(self.get_hex_length())
+    }
+
+    /// Return the id of `content`
+    pub fn hash(&self, content: &str) -> String {
+        self.hash_bytes(content.as_bytes())
+    }
+
+    /// Return the id of the bytes `content`, which need not be text
+    pub fn hash_bytes(&self, content: &[u8]) -> String {
+        let digest: Vec<u8> = match self {
+            ObjectFormat::Sha1 => Sha1::digest(content).to_vec(),
+            ObjectFormat::Sha256 => Sha256::digest(content).to_vec(),
+        };
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Whether `hash` is a full object id of this format
+    pub fn is_valid_hash(&self, hash: &str) -> bool {
+        hash.len() == self.get_hex_length() && hash.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Whether `hash` is the null id of this format
+    pub fn is_null_hash(&self, hash: &str) -> bool {
+        hash.len() == self.get_hex_length() && hash.bytes().all(|byte| byte == b'0')
+    }
+}
+
+impl fmt::Display for ObjectFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_the_digest_of_the_content() {
+        assert_eq!(ObjectFormat::Sha1.hash("abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(ObjectFormat::Sha1.hash("abc"), ObjectFormat::Sha1.hash_bytes(b"abc"));
+        assert_eq!(
+            ObjectFormat::Sha256.hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn null_ids_have_the_length_of_the_format() {
+        assert!(ObjectFormat::Sha1.is_null_hash(&ObjectFormat::Sha1.get_null_hash()));
+        assert!(ObjectFormat::Sha256.is_null_hash(&ObjectFormat::Sha256.get_null_hash()));
+        assert!(!ObjectFormat::Sha256.is_null_hash(&ObjectFormat::Sha1.get_null_hash()));
+        for hash in ["", "0", "00000", "a9993e364706816aba3e25717850c26c9cd0d89d"] {
+            assert!(!ObjectFormat::Sha1.is_null_hash(hash));
+        }
+    }
+}
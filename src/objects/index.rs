@@ -0,0 +1,418 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::DitError;
+use crate::features::status::get_head_blobs;
+use crate::objects::lock::LockFile;
+use crate::repository::Repository;
+
+const INDEX_MAGIC: &[u8; 4] = b"DIRC";
+const INDEX_VERSION: u32 = 1;
+
+/// Seconds and nanoseconds since the epoch
+type Timestamp = (i64, u32);
+
+/// A file of the next commit with the hash `hash`, and the stat data of its working file when
+/// its content had that hash, zero when it is not known
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    path: PathBuf,
+    mode: u32,
+    size: u64,
+    mtime: Timestamp,
+    ctime: Timestamp,
+    inode: u64,
+    hash: String,
+}
+
+impl IndexEntry {
+    pub fn new(path: PathBuf, hash: String, metadata: &Metadata) -> IndexEntry {
+        let (mode, ctime, inode) = get_unix_stat(metadata);
+        IndexEntry {
+            path,
+            mode,
+            size: metadata.len(),
+            mtime: metadata.modified().map(to_timestamp).unwrap_or_default(),
+            ctime,
+            inode,
+            hash,
+        }
+    }
+
+    /// Create an entry whose working file was not hashed yet, which never matches a file
+    pub fn without_stat(path: PathBuf, hash: String) -> IndexEntry {
+        IndexEntry {
+            path,
+            mode: 0,
+            size: 0,
+            mtime: (0, 0),
+            ctime: (0, 0),
+            inode: 0,
+            hash,
+        }
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_mode(&self) -> u32 {
+        self.mode
+    }
+
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn get_hash(&self) -> &String {
+        &self.hash
+    }
+
+    /// Whether the file still has the stat data recorded in the entry
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        let other = IndexEntry::new(self.path.clone(), self.hash.clone(), metadata);
+        *self == other
+    }
+}
+
+/// Files of the next commit, stored in `.dit/index`: the files of the HEAD commit updated by
+/// `add`, `rm`, `mv` and `restore --staged`, from which `commit` builds its tree. The stat data
+/// of the entries is a cache, so that a file whose size, times, inode and mode did not change
+/// is not read and hashed again by `add`, `status` and `commit -a`.
+///
+/// Without the file, the index holds the files of the HEAD commit.
+///
+/// The file contains `DIRC`, the version and the number of entries as big endian u32, the
+/// time it was written, then for every entry sorted by path the mode, the size, the mtime,
+/// the ctime and the inode, followed by the length and the bytes of the hash and of the path.
+/// Times are written as seconds, an i64, and nanoseconds, a u32. The file is encrypted as a
+/// whole in an encrypted repository.
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    entries: BTreeMap<PathBuf, IndexEntry>,
+    timestamp: Timestamp,
+    changed: bool,
+}
+
+impl Index {
+    /// Read the index of the repository, the files of the HEAD commit when it does not exist
+    pub fn load(repo: &Repository) -> Result<Index, DitError> {
+        let index_path = repo.get_index_path();
+        if !index_path.is_file() {
+            let mut index = Index::default();
+            index.reset(&get_head_blobs(repo)?);
+            index.changed = false;
+            return Ok(index);
+        }
+        let data = repo.decrypt(&index_path, &fs::read(&index_path).map_err(DitError::IoError)?)?;
+        parse_index(&data).map_err(|reason| {
+            DitError::UnexpectedComportement(format!(
+                "Index is corrupt: {}, remove {} to rebuild it from the HEAD commit, which unstages every change",
+                reason,
+                index_path.display()
+            ))
+        })
+    }
+
+    /// Lock the index and read it, so that no other process updates it before it is written
+    /// back by `save_to`
+    pub fn lock(repo: &Repository) -> Result<(Index, LockFile), DitError> {
+        let lock = LockFile::acquire(&repo.get_index_path())?;
+        Ok((Index::load(repo)?, lock))
+    }
+
+    /// Write the index if an entry was updated or removed since it was loaded
+    pub fn save(&mut self, repo: &Repository) -> Result<(), DitError> {
+        if !self.changed {
+            return Ok(());
+        }
+        self.save_to(repo, LockFile::acquire(&repo.get_index_path())?)
+    }
+
+    /// Write the index like `save` through `lock`, taken by `Index::lock`, and release it
+    pub fn save_to(&mut self, repo: &Repository, lock: LockFile) -> Result<(), DitError> {
+        if !self.changed {
+            return Ok(());
+        }
+        self.timestamp = to_timestamp(SystemTime::now());
+        lock.write_plain(&repo.encrypt(&encode_index(self))?)?;
+        lock.commit()?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Write the index like `save`, unless another process holds its lock: commands reading
+    /// the repository only refresh the stat data and must not fail because of it
+    pub fn try_save(&mut self, repo: &Repository) -> Result<(), DitError> {
+        match self.save(repo) {
+            Err(DitError::Locked { .. }) => Ok(()),
+            result => result,
+        }
+    }
+
+    pub fn get_entries(&self) -> Vec<&IndexEntry> {
+        self.entries.values().collect()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&IndexEntry> {
+        self.entries.get(path)
+    }
+
+    /// Return the path and hash of the files of the next commit, sorted by path
+    pub fn get_blobs(&self) -> Vec<(PathBuf, String)> {
+        self.entries.values().map(|entry| (entry.path.clone(), entry.hash.clone())).collect()
+    }
+
+    /// Return the hash of the file `path` of the working directory when its stat data shows it
+    /// did not change since it was hashed.
+    ///
+    /// A file modified in the same second as the index was written may have changed without
+    /// its stat data showing it, its hash is not trusted.
+    pub fn get_cached_hash(&self, repo: &Repository, path: &Path) -> Option<String> {
+        let entry = self.entries.get(path)?;
+        let metadata = fs::symlink_metadata(repo.get_work_dir().join(path)).ok()?;
+        if !entry.matches(&metadata) || self.is_racy(entry) {
+            return None;
+        }
+        Some(entry.hash.clone())
+    }
+
+    /// Stage the file `path` of the working directory, whose content has the hash `hash`
+    pub fn update(&mut self, repo: &Repository, path: PathBuf, hash: String) -> Result<(), DitError> {
+        let metadata = fs::symlink_metadata(repo.get_work_dir().join(&path)).map_err(DitError::IoError)?;
+        let entry = IndexEntry::new(path.clone(), hash, &metadata);
+        // Writing the index again makes a racy entry trusted once its second is over
+        if self.entries.get(&path) != Some(&entry) || self.is_racy(&entry) {
+            self.entries.insert(path, entry);
+            self.changed = true;
+        }
+        Ok(())
+    }
+
+    /// Record the stat data of the file `path` of the working directory, whose content has the
+    /// hash `hash`, if it is the staged version of the file
+    pub fn refresh(&mut self, repo: &Repository, path: &Path, hash: &str) -> Result<(), DitError> {
+        match self.entries.get(path) {
+            Some(entry) if entry.hash == hash => self.update(repo, path.to_path_buf(), hash.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Stage the version `hash` of the file `path`, without stat data unless it was already
+    /// staged
+    pub fn set(&mut self, path: PathBuf, hash: String) {
+        if self.entries.get(&path).is_some_and(|entry| entry.hash == hash) {
+            return;
+        }
+        self.entries.insert(path.clone(), IndexEntry::without_stat(path, hash));
+        self.changed = true;
+    }
+
+    /// Replace the files of the index by `blobs`, keeping the stat data of the files whose
+    /// hash did not change
+    pub fn reset(&mut self, blobs: &[(PathBuf, String)]) {
+        let paths: BTreeSet<&Path> = blobs.iter().map(|(path, _)| path.as_path()).collect();
+        self.retain(|path| paths.contains(path));
+        for (path, hash) in blobs {
+            self.set(path.clone(), hash.clone());
+        }
+    }
+
+    fn is_racy(&self, entry: &IndexEntry) -> bool {
+        entry.mtime.0 >= self.timestamp.0
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        if self.entries.remove(path).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// Remove the entries of the paths for which `keep` returns false
+    pub fn retain<F: Fn(&Path) -> bool>(&mut self, keep: F) {
+        let count = self.entries.len();
+        self.entries.retain(|path, _| keep(path));
+        self.changed |= self.entries.len() != count;
+    }
+}
+
+#[cfg(unix)]
+fn get_unix_stat(metadata: &Metadata) -> (u32, Timestamp, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.mode(), (metadata.ctime(), metadata.ctime_nsec() as u32), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn get_unix_stat(metadata: &Metadata) -> (u32, Timestamp, u64) {
+    let mode = if metadata.permissions().readonly() { 0o100444 } else { 0o100644 };
+    (mode, (0, 0), 0)
+}
+
+fn to_timestamp(time: SystemTime) -> Timestamp {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => (duration.as_secs() as i64, duration.subsec_nanos()),
+        Err(_) => (0, 0),
+    }
+}
+
+fn encode_index(index: &Index) -> Vec<u8> {
+    let mut data: Vec<u8> = Vec::new();
+    data.extend_from_slice(INDEX_MAGIC);
+    data.extend_from_slice(&INDEX_VERSION.to_be_bytes());
+    data.extend_from_slice(&(index.entries.len() as u32).to_be_bytes());
+    encode_timestamp(&mut data, index.timestamp);
+    for entry in index.entries.values() {
+        data.extend_from_slice(&entry.mode.to_be_bytes());
+        data.extend_from_slice(&entry.size.to_be_bytes());
+        encode_timestamp(&mut data, entry.mtime);
+        encode_timestamp(&mut data, entry.ctime);
+        data.extend_from_slice(&entry.inode.to_be_bytes());
+        data.push(entry.hash.len() as u8);
+        data.extend_from_slice(entry.hash.as_bytes());
+        let path = entry.path.to_string_lossy();
+        data.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        data.extend_from_slice(path.as_bytes());
+    }
+    data
+}
+
+fn encode_timestamp(data: &mut Vec<u8>, (seconds, nanoseconds): Timestamp) {
+    data.extend_from_slice(&seconds.to_be_bytes());
+    data.extend_from_slice(&nanoseconds.to_be_bytes());
+}
+
+fn parse_index(data: &[u8]) -> Result<Index, String> {
+    let mut reader = Reader { data, position: 0 };
+    if reader.take(4)? != INDEX_MAGIC {
+        return Err(String::from("invalid header"));
+    }
+    let version = reader.read_u32()?;
+    if version != INDEX_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+    let count = reader.read_u32()? as usize;
+    let timestamp = reader.read_timestamp()?;
+
+    let mut entries: BTreeMap<PathBuf, IndexEntry> = BTreeMap::new();
+    for _ in 0..count {
+        let mode = reader.read_u32()?;
+        let size = reader.read_u64()?;
+        let mtime = reader.read_timestamp()?;
+        let ctime = reader.read_timestamp()?;
+        let inode = reader.read_u64()?;
+        let length = reader.take(1)?[0] as usize;
+        let hash = reader.read_string(length)?;
+        let length = u16::from_be_bytes(reader.take(2)?.try_into().unwrap()) as usize;
+        let path = PathBuf::from(reader.read_string(length)?);
+        entries.insert(path.clone(), IndexEntry { path, mode, size, mtime, ctime, inode, hash });
+    }
+    if reader.position != data.len() {
+        return Err(String::from("unexpected data after the entries"));
+    }
+    Ok(Index {
+        entries,
+        timestamp,
+        changed: false,
+    })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + length).ok_or("truncated index")?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_timestamp(&mut self) -> Result<Timestamp, String> {
+        let seconds = i64::from_be_bytes(self.take(8)?.try_into().unwrap());
+        Ok((seconds, self.read_u32()?))
+    }
+
+    fn read_string(&mut self, length: usize) -> Result<String, String> {
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| String::from("invalid string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn sample_index() -> Index {
+        let dir = TempDir::new("index").unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, "one\n").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+
+        let mut index = Index::default();
+        for entry in [
+            IndexEntry::new(PathBuf::from("a.txt"), String::from("aa01"), &metadata),
+            IndexEntry::without_stat(PathBuf::from("dir/é.txt"), String::from("bb02")),
+        ] {
+            index.entries.insert(entry.path.clone(), entry);
+        }
+        index.timestamp = (1_792_000_000, 42);
+        index
+    }
+
+    #[test]
+    fn index_round_trips_through_its_encoding() {
+        let index = sample_index();
+        let parsed = parse_index(&encode_index(&index)).unwrap();
+        assert_eq!(parsed.entries, index.entries);
+        assert_eq!(parsed.timestamp, index.timestamp);
+        assert!(!parsed.changed);
+        assert_eq!(parsed.get(Path::new("a.txt")).unwrap().get_size(), 4);
+    }
+
+    #[test]
+    fn files_are_not_staged_while_the_index_is_locked() {
+        let dir = TempDir::new("index").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let path = dir.path().join("a.txt");
+        fs::write(&path, "one\n").unwrap();
+        let path = path.display().to_string();
+
+        let (mut index, lock) = Index::lock(&repo).unwrap();
+        assert!(matches!(repo.add(vec![&path]), Err(DitError::Locked { .. })));
+        index.set(PathBuf::from("b.txt"), String::from("bb02"));
+        index.save_to(&repo, lock).unwrap();
+        repo.add(vec![&path]).unwrap();
+
+        let index = Index::load(&repo).unwrap();
+        assert!(index.get(Path::new("a.txt")).is_some());
+        assert!(index.get(Path::new("b.txt")).is_some());
+    }
+
+    #[test]
+    fn corrupt_index_is_rejected() {
+        let data = encode_index(&sample_index());
+        assert_eq!(parse_index(b"DIRX").err().unwrap(), "invalid header");
+
+        let mut other_version = data.clone();
+        other_version[7] = 9;
+        assert_eq!(parse_index(&other_version).err().unwrap(), "unsupported version 9");
+
+        assert_eq!(parse_index(&data[..data.len() - 1]).err().unwrap(), "truncated index");
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert_eq!(parse_index(&trailing).err().unwrap(), "unexpected data after the entries");
+    }
+}
@@ -0,0 +1,168 @@
+use std::{fs, io, process};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::DitError;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+const POINTER_VERSION: &str = "version dit-lfs 1";
+/// Largest size of a pointer, bigger files are never read to look for one
+pub const MAX_POINTER_SIZE: u64 = 256;
+/// Number of bytes of a large file read at a time
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+static TEMPORARY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Blob stored in the trees in place of the content of a large file, whose content lives
+/// uncompressed in the content store `.dit/lfs/<2 first chars>/<other chars>` of the
+/// repository, addressed by its SHA-256.
+///
+/// Serialized as the lines `version dit-lfs 1`, `oid sha256:<hash>` and `size <bytes>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pointer {
+    oid: String,
+    size: u64,
+}
+
+impl Pointer {
+    /// Return the pointer serialized in `content`, None if it is not a pointer
+    pub fn parse(content: &[u8]) -> Option<Pointer> {
+        if content.len() as u64 > MAX_POINTER_SIZE {
+            return None;
+        }
+        let mut lines = std::str::from_utf8(content).ok()?.lines();
+        if lines.next()? != POINTER_VERSION {
+            return None;
+        }
+        let oid = lines.next()?.strip_prefix("oid sha256:")?;
+        let size = lines.next()?.strip_prefix("size ")?.parse::<u64>().ok()?;
+        if lines.next().is_some() || oid.len() != 64 || !oid.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Pointer { oid: oid.to_string(), size })
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}\noid sha256:{}\nsize {}\n", POINTER_VERSION, self.oid, self.size)
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    /// Size in bytes of the content
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Path of the content in the content store
+    pub fn get_content_path(&self, repo: &Repository) -> PathBuf {
+        repo.get_lfs_path().join(&self.oid[..2]).join(&self.oid[2..])
+    }
+
+    pub fn has_content(&self, repo: &Repository) -> bool {
+        self.get_content_path(repo).is_file()
+    }
+
+    /// Hash the stored content, return why it does not match the pointer or None
+    pub fn check_content(&self, repo: &Repository) -> Result<Option<String>, DitError> {
+        let file = File::open(self.get_content_path(repo)).map_err(DitError::IoError)?;
+        let (oid, size) = hash_content(file, None).map_err(DitError::IoError)?;
+        if size != self.size {
+            return Ok(Some(format!("content is {} bytes, expected {}", size, self.size)));
+        }
+        if oid != self.oid {
+            return Ok(Some(format!("content hashes to {}", oid)));
+        }
+        Ok(None)
+    }
+}
+
+/// Return the pointer of the file `path` of the working directory, copying its content to the
+/// content store with `store`. The file is read by chunks, never as a whole.
+pub fn clean(repo: &Repository, path: &Path, store: bool) -> Result<Pointer, DitError> {
+    let file = File::open(repo.get_work_dir().join(path)).map_err(DitError::IoError)?;
+    if !store {
+        let (oid, size) = hash_content(file, None).map_err(DitError::IoError)?;
+        return Ok(Pointer { oid, size });
+    }
+
+    fs::create_dir_all(repo.get_lfs_path()).map_err(DitError::IoError)?;
+    let count = TEMPORARY_COUNT.fetch_add(1, Ordering::Relaxed);
+    let temporary_path = repo.get_lfs_path().join(format!("tmp-{}-{}", process::id(), count));
+    let mut copy = File::create(&temporary_path).map_err(DitError::IoError)?;
+    let result = hash_content(file, Some(&mut copy)).and_then(|hashed| copy.sync_all().map(|_| hashed));
+    drop(copy);
+    let (oid, size) = match result {
+        Ok(hashed) => hashed,
+        Err(e) => {
+            let _ = fs::remove_file(&temporary_path);
+            return Err(DitError::IoError(e));
+        }
+    };
+
+    let pointer = Pointer { oid, size };
+    let content_path = pointer.get_content_path(repo);
+    if content_path.is_file() {
+        fs::remove_file(&temporary_path).map_err(DitError::IoError)?;
+    } else {
+        if let Some(parent) = content_path.parent() {
+            fs::create_dir_all(parent).map_err(DitError::IoError)?;
+        }
+        fs::rename(&temporary_path, &content_path).map_err(DitError::IoError)?;
+    }
+    Ok(pointer)
+}
+
+/// Replace the pointers written in the working directory for the files `blobs` by the content
+/// of the large files. A pointer whose content is not in the content store is left in place.
+pub fn smudge_files(repo: &Repository, blobs: &[(PathBuf, String)]) -> Result<(), DitError> {
+    for (path, _) in blobs {
+        let full_path = repo.get_work_dir().join(path);
+        let Some(pointer) = read_pointer_file(&full_path)? else {
+            continue;
+        };
+        if !pointer.has_content(repo) {
+            repo.report(Event::Warning(format!("Content of the large file {} is missing, its pointer is left in place", path.display())));
+            continue;
+        }
+        fs::copy(pointer.get_content_path(repo), &full_path).map_err(DitError::IoError)?;
+    }
+    Ok(())
+}
+
+/// Return the pointer held by the file `path`, None if it is not a pointer
+pub fn read_pointer_file(path: &Path) -> Result<Option<Pointer>, DitError> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(None);
+    };
+    if !metadata.is_file() || metadata.len() > MAX_POINTER_SIZE {
+        return Ok(None);
+    }
+    Ok(Pointer::parse(&fs::read(path).map_err(DitError::IoError)?))
+}
+
+/// Return the SHA-256 and the size of the content read from `reader`, copied to `copy`
+fn hash_content<R: Read>(mut reader: R, mut copy: Option<&mut File>) -> Result<(String, u64), io::Error> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut size: u64 = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        if let Some(copy) = copy.as_mut() {
+            copy.write_all(&buffer[..read])?;
+        }
+        size += read as u64;
+    }
+    let oid = hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok((oid, size))
+}
@@ -0,0 +1,326 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use dit_file_encryptor::CompressedFile;
+
+use crate::error::DitError;
+use crate::repository::Repository;
+
+pub const LOCK_EXTENSION: &str = "lock";
+
+/// Age after which a lock is considered left by a dit process which did not finish
+const STALE_LOCK_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Lock on a file of `.dit`. The lock file `<file>.lock` is created exclusively, so that two
+/// dit processes can not update the file together, receives the new content and replaces the
+/// file when the lock is committed. A lock dropped without being committed is removed and the
+/// file is left unchanged.
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    committed: bool,
+}
+
+impl LockFile {
+    /// Lock `path`, failing with `Locked` when another process holds the lock
+    pub fn acquire(path: &Path) -> Result<LockFile, DitError> {
+        let lock_path = get_lock_path(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(DitError::IoError)?;
+        }
+        match OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+            Ok(_) => Ok(LockFile {
+                path: path.to_path_buf(),
+                lock_path,
+                committed: false,
+            }),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let stale = is_stale(&lock_path);
+                Err(DitError::Locked { path: lock_path, stale })
+            }
+            Err(e) => Err(DitError::IoError(e)),
+        }
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn get_lock_path(&self) -> &PathBuf {
+        &self.lock_path
+    }
+
+    /// Write `content` to the lock file, encrypted when `repo` is encrypted and compressed
+    /// like the other files of `.dit`. Empty content leaves the lock file empty.
+    pub fn write(&self, repo: &Repository, content: &[u8]) -> Result<(), DitError> {
+        if content.is_empty() {
+            File::create(&self.lock_path).map_err(DitError::IoError)?;
+        } else {
+            let mut writer = CompressedFile::create_file(self.lock_path.clone())
+                .map_err(DitError::IoError)?
+                .open_for_write()
+                .map_err(DitError::IoError)?;
+            writer.write_all(&repo.encrypt(content)?).map_err(DitError::IoError)?;
+        }
+        self.sync()
+    }
+
+    /// Write `content` to the lock file as is, for the files read without decompression
+    pub fn write_plain(&self, content: &[u8]) -> Result<(), DitError> {
+        fs::write(&self.lock_path, content).map_err(DitError::IoError)?;
+        self.sync()
+    }
+
+    /// Replace the file by the lock file
+    pub fn commit(mut self) -> Result<(), DitError> {
+        self.replace()?;
+        sync_parent_dir(&self.path)
+    }
+
+    fn replace(&mut self) -> Result<(), DitError> {
+        fs::rename(&self.lock_path, &self.path).map_err(DitError::IoError)?;
+        self.committed = true;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), DitError> {
+        File::open(&self.lock_path)
+            .and_then(|file| file.sync_all())
+            .map_err(DitError::IoError)
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Update of several files of `.dit`, such as the ref, the info file, the staged file and the
+/// reflogs written by a commit, applied entirely or not at all.
+///
+/// Every file is locked and written to its lock file first. On commit, the list of the files
+/// is written to `.dit/transaction.lock`, held until the lock files have replaced them, so that
+/// a single transaction is committed at a time and a transaction interrupted by a crash is
+/// completed by `recover_transaction` when the repository is opened once its journal is stale.
+pub struct Transaction<'a> {
+    repo: &'a Repository,
+    locks: Vec<LockFile>,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn new(repo: &'a Repository) -> Transaction<'a> {
+        Transaction {
+            repo,
+            locks: Vec::new(),
+        }
+    }
+
+    pub fn get_repo(&self) -> &'a Repository {
+        self.repo
+    }
+
+    /// Lock `path` until the transaction ends, so that it can be read and checked before
+    /// being written
+    pub fn lock(&mut self, path: PathBuf) -> Result<(), DitError> {
+        if !self.locks.iter().any(|lock| *lock.get_path() == path) {
+            self.locks.push(LockFile::acquire(&path)?);
+        }
+        Ok(())
+    }
+
+    /// Lock `path` if needed and write its new content, compressed
+    pub fn write(&mut self, path: PathBuf, content: &[u8]) -> Result<(), DitError> {
+        self.lock(path.clone())?;
+        match self.locks.iter().find(|lock| *lock.get_path() == path) {
+            Some(lock) => lock.write(self.repo, content),
+            None => Err(DitError::UnexpectedComportement(format!("{} is not locked", path.display()))),
+        }
+    }
+
+    /// Replace every written file by its new content
+    pub fn commit(mut self) -> Result<(), DitError> {
+        if self.locks.len() <= 1 {
+            return match self.locks.pop() {
+                Some(lock) => lock.commit(),
+                None => Ok(()),
+            };
+        }
+
+        let dit_path = self.repo.get_dit_path();
+        let mut journal = String::new();
+        for lock in self.locks.iter() {
+            let path = lock.get_path().strip_prefix(dit_path).unwrap_or(lock.get_path());
+            journal.push_str(&format!("{}\n", path.display()));
+        }
+        let mut journal_lock = LockFile::acquire(&self.repo.get_transaction_path())?;
+        journal_lock.write_plain(journal.as_bytes())?;
+        sync_parent_dir(journal_lock.get_lock_path())?;
+
+        for i in 0..self.locks.len() {
+            if let Err(e) = self.locks[i].replace() {
+                // The journal and the remaining lock files are kept for recover_transaction
+                self.locks.iter_mut().for_each(|lock| lock.committed = true);
+                journal_lock.committed = true;
+                return Err(e);
+            }
+        }
+        for lock in self.locks.iter() {
+            sync_parent_dir(lock.get_path())?;
+        }
+        journal_lock.committed = true;
+        fs::remove_file(journal_lock.get_lock_path()).map_err(DitError::IoError)?;
+        sync_parent_dir(journal_lock.get_lock_path())
+    }
+}
+
+/// Complete the transaction interrupted by a crash, if any, by moving its remaining lock files
+/// in place of the files they update. A journal which is not stale yet belongs to the dit
+/// process committing the transaction, which is left to it.
+pub fn recover_transaction(repo: &Repository) -> Result<(), DitError> {
+    let journal_path = get_lock_path(&repo.get_transaction_path());
+    if !journal_path.is_file() || !is_stale(&journal_path) {
+        return Ok(());
+    }
+    let journal = match fs::read_to_string(&journal_path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(DitError::IoError(e)),
+    };
+
+    // Another process recovering the transaction at the same time may have moved a lock file
+    // already
+    for line in journal.lines().filter(|line| !line.is_empty()) {
+        let path = repo.get_dit_path().join(line);
+        ignore_not_found(fs::rename(get_lock_path(&path), &path))?;
+    }
+    ignore_not_found(fs::remove_file(&journal_path))?;
+    sync_parent_dir(&journal_path)
+}
+
+fn ignore_not_found(result: std::io::Result<()>) -> Result<(), DitError> {
+    match result {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(DitError::IoError(e)),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `path` is the lock file of another file, to be skipped when listing refs and logs
+pub fn is_lock_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == LOCK_EXTENSION)
+}
+
+fn get_lock_path(path: &Path) -> PathBuf {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(format!(".{}", LOCK_EXTENSION));
+    PathBuf::from(lock_path)
+}
+
+fn is_stale(lock_path: &Path) -> bool {
+    fs::metadata(lock_path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > STALE_LOCK_AGE)
+}
+
+/// Make a rename in the directory of `path` durable
+fn sync_parent_dir(path: &Path) -> Result<(), DitError> {
+    if let Some(parent) = path.parent() {
+        File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .map_err(DitError::IoError)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::utils::read_dit_file;
+
+    /// Leave the state of a transaction interrupted after its journal was written: `a` and `b`
+    /// locked with their new content, their lock files `age` old
+    fn interrupt_transaction(repo: &Repository, age: Duration) {
+        for name in ["a", "b"] {
+            let path = repo.get_dit_path().join(name);
+            fs::write(&path, "old").unwrap();
+            let lock_path = get_lock_path(&path);
+            fs::write(&lock_path, "new").unwrap();
+            File::options().write(true).open(&lock_path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+        }
+        let journal_path = get_lock_path(&repo.get_transaction_path());
+        fs::write(&journal_path, "a\nb\n").unwrap();
+        File::options().write(true).open(&journal_path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn read(repo: &Repository, name: &str) -> String {
+        fs::read_to_string(repo.get_dit_path().join(name)).unwrap()
+    }
+
+    #[test]
+    fn transaction_replaces_every_file() {
+        let dir = TempDir::new("lock").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut transaction = Transaction::new(&repo);
+        transaction.write(repo.get_dit_path().join("a"), b"first").unwrap();
+        transaction.write(repo.get_dit_path().join("b"), b"second").unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(read_dit_file(&repo, &repo.get_dit_path().join("a")).unwrap(), b"first");
+        assert_eq!(read_dit_file(&repo, &repo.get_dit_path().join("b")).unwrap(), b"second");
+        assert!(!get_lock_path(&repo.get_transaction_path()).exists());
+        assert!(!get_lock_path(&repo.get_dit_path().join("a")).exists());
+    }
+
+    #[test]
+    fn transactions_are_committed_one_at_a_time() {
+        let dir = TempDir::new("lock").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let journal_lock = LockFile::acquire(&repo.get_transaction_path()).unwrap();
+        let mut transaction = Transaction::new(&repo);
+        transaction.write(repo.get_dit_path().join("a"), b"first").unwrap();
+        transaction.write(repo.get_dit_path().join("b"), b"second").unwrap();
+        assert!(matches!(transaction.commit(), Err(DitError::Locked { .. })));
+        drop(journal_lock);
+
+        assert!(!repo.get_dit_path().join("a").exists());
+        assert!(!get_lock_path(&repo.get_dit_path().join("a")).exists());
+    }
+
+    #[test]
+    fn stale_transaction_is_recovered() {
+        let dir = TempDir::new("lock").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        interrupt_transaction(&repo, STALE_LOCK_AGE * 2);
+        recover_transaction(&repo).unwrap();
+
+        assert_eq!((read(&repo, "a"), read(&repo, "b")), (String::from("new"), String::from("new")));
+        assert!(!get_lock_path(&repo.get_transaction_path()).exists());
+    }
+
+    #[test]
+    fn running_transaction_is_left_to_its_process() {
+        let dir = TempDir::new("lock").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        interrupt_transaction(&repo, Duration::ZERO);
+        recover_transaction(&repo).unwrap();
+        assert_eq!(read(&repo, "a"), "old");
+        assert!(get_lock_path(&repo.get_transaction_path()).exists());
+
+        File::options()
+            .write(true)
+            .open(get_lock_path(&repo.get_transaction_path()))
+            .unwrap()
+            .set_modified(SystemTime::now() - STALE_LOCK_AGE * 2)
+            .unwrap();
+        Repository::open(dir.path()).unwrap();
+        assert_eq!(read(&repo, "a"), "new");
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+
+use crate::error::DitError;
+use crate::objects::lock::LockFile;
+use crate::objects::object::{Object, ObjectKind};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::read_dit_file;
+
+/// Kind and permissions of an entry of the working directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FileMode {
+    Regular,
+    Executable,
+    /// Symbolic link to the path it holds
+    Symlink(String),
+    /// Empty directory
+    Directory,
+}
+
+impl FileMode {
+    /// Return the mode of the entry `path` of the working directory, without following symlinks
+    pub fn from_path(path: &Path) -> Result<FileMode, DitError> {
+        let metadata = fs::symlink_metadata(path).map_err(DitError::IoError)?;
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path).map_err(DitError::IoError)?;
+            return Ok(FileMode::Symlink(target.to_string_lossy().to_string()));
+        }
+        if metadata.is_dir() {
+            return Ok(FileMode::Directory);
+        }
+        Ok(if is_executable(&metadata) { FileMode::Executable } else { FileMode::Regular })
+    }
+
+    /// Octal mode written in the mode objects, like git tree entries
+    pub fn as_octal(&self) -> &'static str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink(_) => "120000",
+            FileMode::Directory => "040000",
+        }
+    }
+
+    /// Whether the entry is stored in the mode object only, not in the tree
+    pub fn is_special(&self) -> bool {
+        matches!(self, FileMode::Symlink(_) | FileMode::Directory)
+    }
+}
+
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileMode::Symlink(target) => write!(f, "{} -> {}", self.as_octal(), target),
+            mode => write!(f, "{}", mode.as_octal()),
+        }
+    }
+}
+
+/// Modes of the entries of a commit which are not regular files: executable files, symlinks
+/// and empty directories. Symlinks and empty directories only exist here, the trees only hold
+/// the content of files.
+///
+/// Stored as a `modes` object of lines `<octal mode> <path>`, followed by a tab and the target
+/// for a symlink, and referenced by the `mode` header of the commit. The staged modes, which
+/// can also hold regular files to clear an executable bit, are stored in `.dit/modes`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Modes {
+    entries: BTreeMap<PathBuf, FileMode>,
+}
+
+impl Modes {
+    /// Read the mode object `hash`, an empty hash meaning no entry
+    pub fn load(repo: &Repository, hash: &str) -> Result<Modes, DitError> {
+        if hash.is_empty() {
+            return Ok(Modes::default());
+        }
+        let object = Object::read(repo, hash)?;
+        if object.get_kind() != ObjectKind::Modes {
+            return Err(DitError::CorruptObject {
+                hash: hash.to_string(),
+                reason: format!("expected a modes object, found a {}", object.get_kind()),
+            });
+        }
+        let content = String::from_utf8_lossy(object.get_content());
+        parse_modes(&content).map_err(|reason| DitError::CorruptObject { hash: hash.to_string(), reason })
+    }
+
+    /// Write the entries which are not regular files as a mode object and return its hash,
+    /// empty when there is none
+    pub fn save(&self, repo: &Repository) -> Result<String, DitError> {
+        let content = encode_modes(self, false);
+        if content.is_empty() {
+            return Ok(String::new());
+        }
+        let hash = repo.get_object_format().hash(&content);
+        if Object::new(ObjectKind::Modes, content.into_bytes()).write(repo, &hash)? {
+            repo.report(Event::ObjectWritten(hash.clone()));
+        }
+        Ok(hash)
+    }
+
+    /// Read the modes of the entries staged since the last commit
+    pub fn load_staged(repo: &Repository) -> Result<Modes, DitError> {
+        let modes_path = repo.get_modes_path();
+        if !modes_path.is_file() || fs::metadata(&modes_path).map_err(DitError::IoError)?.len() == 0 {
+            return Ok(Modes::default());
+        }
+        let content = read_dit_file(repo, &modes_path)?;
+        parse_modes(&String::from_utf8_lossy(&content)).map_err(|reason| {
+            DitError::UnexpectedComportement(format!("Invalid staged modes in {}: {}", modes_path.display(), reason))
+        })
+    }
+
+    pub fn save_staged(&self, repo: &Repository) -> Result<(), DitError> {
+        let lock = LockFile::acquire(&repo.get_modes_path())?;
+        lock.write(repo, encode_modes(self, true).as_bytes())?;
+        lock.commit()
+    }
+
+    /// Read the modes of the entries `paths` of the working directory
+    pub fn from_working(repo: &Repository, paths: &[PathBuf]) -> Result<Modes, DitError> {
+        let mut modes = Modes::default();
+        for path in paths {
+            modes.set(path.clone(), FileMode::from_path(&repo.get_work_dir().join(path))?);
+        }
+        Ok(modes)
+    }
+
+    /// Return the mode of `path`, regular when it has no entry
+    pub fn get(&self, path: &Path) -> FileMode {
+        self.entries.get(path).cloned().unwrap_or(FileMode::Regular)
+    }
+
+    pub fn get_entries(&self) -> &BTreeMap<PathBuf, FileMode> {
+        &self.entries
+    }
+
+    pub fn set(&mut self, path: PathBuf, mode: FileMode) {
+        self.entries.insert(path, mode);
+    }
+
+    /// Replace the entries by the ones of `other`
+    pub fn overlay(&mut self, other: &Modes) {
+        for (path, mode) in other.entries.iter() {
+            self.entries.insert(path.clone(), mode.clone());
+        }
+    }
+
+    /// Remove the entries of the paths for which `keep` returns false
+    pub fn retain<F: Fn(&Path, &FileMode) -> bool>(&mut self, keep: F) {
+        self.entries.retain(|path, mode| keep(path, mode));
+    }
+
+    /// Keep the symlinks and the directories still empty, and the modes of the files of `blobs`
+    pub fn retain_tracked(&mut self, blobs: &[(PathBuf, String)]) {
+        let files: HashSet<&Path> = blobs.iter().map(|(path, _)| path.as_path()).collect();
+        let ancestors: HashSet<&Path> = blobs.iter().flat_map(|(path, _)| path.ancestors()).collect();
+        self.entries.retain(|path, mode| match mode {
+            FileMode::Symlink(_) => !files.contains(path.as_path()),
+            FileMode::Directory => !ancestors.contains(path.as_path()),
+            _ => files.contains(path.as_path()),
+        });
+    }
+
+    /// Return the paths of the symlinks and empty directories
+    pub fn get_special_paths(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|(_, mode)| mode.is_special())
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Give the entries of the working directory the modes recorded for `blobs` and create
+    /// the symlinks and empty directories
+    pub fn apply(&self, repo: &Repository, blobs: &[(PathBuf, String)]) -> Result<(), DitError> {
+        let project_path = repo.get_work_dir();
+        for (path, _) in blobs {
+            set_executable(&project_path.join(path), self.get(path) == FileMode::Executable)?;
+        }
+        for (path, mode) in self.entries.iter() {
+            let full_path = project_path.join(path);
+            match mode {
+                FileMode::Symlink(target) => {
+                    if fs::symlink_metadata(&full_path).is_ok() {
+                        remove_entry(&full_path)?;
+                    }
+                    if let Some(parent) = full_path.parent() {
+                        fs::create_dir_all(parent).map_err(DitError::IoError)?;
+                    }
+                    create_symlink(target, &full_path)?;
+                    repo.report(Event::FileCheckedOut(path.clone()));
+                }
+                FileMode::Directory => fs::create_dir_all(&full_path).map_err(DitError::IoError)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove from the working directory the symlinks and the empty directories which are
+    /// not entries of `next`, before checking out the version with the modes `next`
+    pub fn remove_special_entries(&self, repo: &Repository, next: &Modes) -> Result<(), DitError> {
+        for path in self.get_special_paths() {
+            let full_path = repo.get_work_dir().join(&path);
+            if next.entries.contains_key(&path) || FileMode::from_path(&full_path).ok() != Some(self.get(&path)) {
+                continue;
+            }
+            match self.get(&path) {
+                FileMode::Directory => {
+                    if fs::read_dir(&full_path).map_err(DitError::IoError)?.next().is_none() {
+                        fs::remove_dir(&full_path).map_err(DitError::IoError)?;
+                    }
+                }
+                _ => fs::remove_file(&full_path).map_err(DitError::IoError)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_modes(modes: &Modes, with_regular: bool) -> String {
+    let mut content = String::new();
+    for (path, mode) in modes.entries.iter() {
+        match mode {
+            FileMode::Regular if !with_regular => (),
+            FileMode::Symlink(target) => content.push_str(&format!("{} {}\t{}\n", mode.as_octal(), path.display(), target)),
+            mode => content.push_str(&format!("{} {}\n", mode.as_octal(), path.display())),
+        }
+    }
+    content
+}
+
+fn parse_modes(content: &str) -> Result<Modes, String> {
+    let mut modes = Modes::default();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let (octal, rest) = line.split_once(' ').ok_or_else(|| format!("malformed line '{}'", line))?;
+        let (path, mode) = match octal {
+            "100644" => (rest, FileMode::Regular),
+            "100755" => (rest, FileMode::Executable),
+            "040000" => (rest, FileMode::Directory),
+            "120000" => {
+                let (path, target) = rest.split_once('\t').ok_or_else(|| format!("symlink without target '{}'", line))?;
+                (path, FileMode::Symlink(target.to_string()))
+            }
+            _ => return Err(format!("unknown mode {}", octal)),
+        };
+        modes.set(PathBuf::from(path), mode);
+    }
+    Ok(modes)
+}
+
+fn remove_entry(path: &Path) -> Result<(), DitError> {
+    let metadata = fs::symlink_metadata(path).map_err(DitError::IoError)?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path).map_err(DitError::IoError)
+    } else {
+        fs::remove_file(path).map_err(DitError::IoError)
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Add or remove the executable bits of the file `path`, allowed to whoever can read it
+#[cfg(unix)]
+fn set_executable(path: &Path, executable: bool) -> Result<(), DitError> {
+    use std::os::unix::fs::PermissionsExt;
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    if !metadata.is_file() {
+        return Ok(());
+    }
+    let mode = metadata.permissions().mode();
+    let new_mode = if executable { mode | (mode & 0o444) >> 2 } else { mode & !0o111 };
+    if new_mode != mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(new_mode)).map_err(DitError::IoError)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path, _executable: bool) -> Result<(), DitError> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &str, path: &Path) -> Result<(), DitError> {
+    std::os::unix::fs::symlink(target, path).map_err(DitError::IoError)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(target: &str, path: &Path) -> Result<(), DitError> {
+    Err(DitError::UnexpectedComportement(format!(
+        "Unable to create the symlink {} to {} on this platform",
+        path.display(),
+        target
+    )))
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::DitError;
+use crate::features::init::get_head_hash;
+use crate::objects::branch::Branch;
+use crate::objects::commit::Commit;
+use crate::objects::hash::ObjectFormat;
+use crate::objects::index::Index;
+use crate::objects::reflog::ReflogEntry;
+use crate::objects::store::load_tree;
+use crate::objects::tag::Tag;
+use crate::objects::tree::Node;
+use crate::objects::tree::Node::TreeNode;
+use crate::repository::Repository;
+
+/// Type of an object, written at the start of the objects serialized by dit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+    Modes,
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Blob => "blob",
+            ObjectKind::Tag => "tag",
+            ObjectKind::Modes => "modes",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ObjectKind> {
+        match name {
+            "commit" => Some(ObjectKind::Commit),
+            "tree" => Some(ObjectKind::Tree),
+            "blob" => Some(ObjectKind::Blob),
+            "tag" => Some(ObjectKind::Tag),
+            "modes" => Some(ObjectKind::Modes),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Content of an object with its type.
+///
+/// Serialized as `<kind> <size>\0<content>` where `size` is the length of `content` in bytes.
+/// The trees and blobs written by `repository_tree_creator` before layout 3 have no header,
+/// `dit upgrade` writes them again with one. The commits of the first versions of dit, which
+/// start with their `tree <id>` line, are read as commits without header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Object {
+    kind: ObjectKind,
+    content: Vec<u8>,
+}
+
+impl Object {
+    pub fn new(kind: ObjectKind, content: Vec<u8>) -> Object {
+        Object { kind, content }
+    }
+
+    pub fn get_kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    pub fn get_size(&self) -> usize {
+        self.content.len()
+    }
+
+    pub fn get_content(&self) -> &Vec<u8> {
+        &self.content
+    }
+
+    pub fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
+    /// Return the id of a tree or a blob, the hash of the serialized object with `format`
+    pub fn get_id(&self, format: ObjectFormat) -> String {
+        format.hash_bytes(&self.encode())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = format!("{} {}\0", self.kind, self.content.len()).into_bytes();
+        data.extend_from_slice(&self.content);
+        data
+    }
+
+    /// Parse `data`, the serialized object `hash`, checking its header
+    pub fn decode(hash: &str, data: &[u8]) -> Result<Object, DitError> {
+        let corrupt = |reason: String| DitError::CorruptObject { hash: hash.to_string(), reason };
+        if is_legacy_commit(data) {
+            return Ok(Object::new(ObjectKind::Commit, data.to_vec()));
+        }
+
+        let end = data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| corrupt(String::from("missing type header")))?;
+        let header = std::str::from_utf8(&data[..end]).map_err(|_| corrupt(String::from("type header is not valid UTF-8")))?;
+        let (kind, size) = header
+            .split_once(' ')
+            .ok_or_else(|| corrupt(format!("malformed type header '{}'", header)))?;
+        let kind = ObjectKind::from_name(kind).ok_or_else(|| corrupt(format!("unknown object type '{}'", kind)))?;
+        let size: usize = size.parse().map_err(|_| corrupt(format!("invalid object size '{}'", size)))?;
+
+        let content = &data[end + 1..];
+        if content.len() != size {
+            return Err(corrupt(format!("header announces {} bytes but content has {}", size, content.len())));
+        }
+        Ok(Object::new(kind, content.to_vec()))
+    }
+
+    /// Read and parse the object `hash`
+    pub fn read(repo: &Repository, hash: &str) -> Result<Object, DitError> {
+        Self::decode(hash, &repo.get_store().read(hash)?)
+    }
+
+    /// Write the object as `hash`, return false if it already exists
+    pub fn write(&self, repo: &Repository, hash: &str) -> Result<bool, DitError> {
+        repo.get_store().write(hash, &self.encode())
+    }
+}
+
+/// Whether `data` is a commit written without header, whose first line is `tree <id>` where a
+/// typed tree has its size followed by a NUL byte
+fn is_legacy_commit(data: &[u8]) -> bool {
+    let line = data.split(|byte| *byte == b'\n').next().unwrap_or_default();
+    match line.strip_prefix(b"tree ") {
+        Some(id) => data.len() > line.len() && [ObjectFormat::Sha1, ObjectFormat::Sha256].iter().any(|format| {
+            std::str::from_utf8(id).is_ok_and(|id| format.is_valid_hash(id))
+        }),
+        None => false,
+    }
+}
+
+/// Return the kind of every object reachable from the branches, the tags, the reflogs and
+/// the index, the tag objects included
+pub fn get_reachable_objects(repo: &Repository) -> Result<BTreeMap<String, ObjectKind>, DitError> {
+    let mut kinds: BTreeMap<String, ObjectKind> = BTreeMap::new();
+    let mut pending = get_referenced_commits(repo)?;
+    while let Some(hash) = pending.pop() {
+        if repo.get_object_format().is_null_hash(&hash) || kinds.contains_key(&hash) {
+            continue;
+        }
+        let commit = Commit::get_commit_from_file(repo, hash.clone())?;
+        kinds.insert(hash, ObjectKind::Commit);
+        add_tree_objects(repo, commit.get_tree(), &mut kinds)?;
+        if !commit.get_modes().is_empty() {
+            kinds.insert(commit.get_modes().clone(), ObjectKind::Modes);
+        }
+        pending.push(commit.get_parent().clone());
+        if commit.is_merge() {
+            pending.push(commit.get_merged().clone());
+        }
+    }
+
+    for tag in Tag::get_tags(repo)? {
+        if let Some(object) = tag.get_object() {
+            kinds.insert(object.clone(), ObjectKind::Tag);
+        }
+    }
+
+    for (_, hash) in Index::load(repo)?.get_blobs() {
+        kinds.insert(hash, ObjectKind::Blob);
+    }
+    Ok(kinds)
+}
+
+/// Return the commits named by the branches, HEAD, the tags and the reflogs, without reading them
+pub fn get_referenced_commits(repo: &Repository) -> Result<Vec<String>, DitError> {
+    let mut hashes: Vec<String> = Branch::get_branches(repo)?
+        .into_iter()
+        .map(|branch| branch.get_head().clone())
+        .collect();
+    hashes.push(get_head_hash(repo)?);
+    hashes.extend(Tag::get_tags(repo)?.into_iter().map(|tag| tag.get_target().clone()));
+    for name in ReflogEntry::get_log_names(repo)? {
+        for entry in ReflogEntry::get_entries(repo, &name)? {
+            hashes.push(entry.get_old().clone());
+            hashes.push(entry.get_new().clone());
+        }
+    }
+    hashes.retain(|hash| !repo.get_object_format().is_null_hash(hash));
+    hashes.sort();
+    hashes.dedup();
+    Ok(hashes)
+}
+
+fn add_tree_objects(repo: &Repository, tree_hash: &String, kinds: &mut BTreeMap<String, ObjectKind>) -> Result<(), DitError> {
+    if kinds.contains_key(tree_hash) {
+        return Ok(());
+    }
+    let tree = load_tree(repo, tree_hash)?;
+    add_node_objects(&TreeNode(tree), kinds);
+    Ok(())
+}
+
+fn add_node_objects(node: &Node, kinds: &mut BTreeMap<String, ObjectKind>) {
+    match node {
+        TreeNode(tree) => {
+            kinds.insert(tree.get_id(), ObjectKind::Tree);
+            for child in tree.get_children() {
+                add_node_objects(child, kinds);
+            }
+        }
+        blob => {
+            kinds.insert(blob.get_id(), ObjectKind::Blob);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_are_decoded_as_they_were_encoded() {
+        for kind in [ObjectKind::Commit, ObjectKind::Tree, ObjectKind::Blob, ObjectKind::Tag, ObjectKind::Modes] {
+            let object = Object::new(kind, b"some\0content".to_vec());
+            let data = object.encode();
+            assert!(data.starts_with(format!("{} 12\0", kind).as_bytes()));
+            assert_eq!(Object::decode("hash", &data).unwrap(), object);
+        }
+    }
+
+    #[test]
+    fn objects_without_a_valid_header_are_corrupt() {
+        for data in [&b"no header"[..], b"blob\0content", b"file 7\0content", b"blob x\0content", b"blob 3\0content"] {
+            assert!(matches!(Object::decode("hash", data), Err(DitError::CorruptObject { .. })));
+        }
+    }
+
+    #[test]
+    fn commits_without_header_are_read_as_commits() {
+        let data = format!("tree {}\npare {}\nfirst commit", "a".repeat(40), "0".repeat(40)).into_bytes();
+        assert_eq!(Object::decode("hash", &data).unwrap(), Object::new(ObjectKind::Commit, data.clone()));
+        assert!(Object::decode("hash", format!("tree {}", "a".repeat(40)).as_bytes()).is_err());
+        assert!(Object::decode("hash", b"tree 1234\n").is_err());
+    }
+
+    #[test]
+    fn ids_hash_the_header_and_the_content() {
+        let object = Object::new(ObjectKind::Blob, Vec::new());
+        assert_eq!(object.get_id(ObjectFormat::Sha1), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        assert_eq!(
+            object.get_id(ObjectFormat::Sha256),
+            "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+        );
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::error::DitError;
+use crate::objects::hash::ObjectFormat;
+
+pub const PACK_DIR: &str = "pack";
+
+const PACK_MAGIC: &[u8; 4] = b"DPCK";
+const INDEX_MAGIC: &[u8; 4] = b"DIDX";
+const PACK_VERSION: u32 = 2;
+const HEADER_SIZE: usize = 12;
+
+const WHOLE_ENTRY: u8 = 0;
+const DELTA_ENTRY: u8 = 1;
+
+const INSERT_INSTRUCTION: u8 = 0;
+const COPY_INSTRUCTION: u8 = 1;
+
+/// Number of previous objects, sorted by size, tried as delta base
+const DELTA_WINDOW: usize = 10;
+/// Longest chain of deltas to apply to read an object
+const MAX_DELTA_DEPTH: usize = 50;
+/// Size of the blocks of the base matched in the target when computing a delta
+const DELTA_BLOCK_SIZE: usize = 16;
+
+/// Location of an object in the data of a pack
+#[derive(Clone, Copy, Debug)]
+struct PackEntry {
+    offset: usize,
+    length: usize,
+}
+
+/// Objects stored together in `.dit/objects/pack/pack-<id>.pack`, with their offsets in
+/// `pack-<id>.idx`.
+///
+/// The pack file contains `DPCK`, the version and the number of objects as big endian u32,
+/// then the objects. An object is a type byte, `0` for a whole object or `1` for a delta,
+/// followed for a delta by the length and the hash of its base, an object of the same pack,
+/// and then by the content or the delta compressed with zlib. An object is read by seeking
+/// to its offset, without reading the rest of the pack.
+///
+/// The index is not compressed and contains `DIDX`, the version and the number of objects,
+/// then for every object sorted by hash the length and the hash, the offset and the length
+/// of the object in the pack, as big endian u64.
+#[derive(Debug)]
+pub struct Pack {
+    pack_path: PathBuf,
+    version: u32,
+    entries: BTreeMap<String, PackEntry>,
+}
+
+impl Pack {
+    /// Open the pack `pack_path`, reading its index. The objects are only read when needed.
+    pub fn open(pack_path: PathBuf) -> Result<Pack, DitError> {
+        let index = fs::read(pack_path.with_extension("idx")).map_err(DitError::IoError)?;
+        let (version, entries) = parse_index(&index).map_err(|reason| corrupt_pack(&pack_path, reason))?;
+        Ok(Pack {
+            pack_path,
+            version,
+            entries,
+        })
+    }
+
+    /// Return the packs of `pack_dir`, sorted by name
+    pub fn open_all(pack_dir: &Path) -> Result<Vec<Pack>, DitError> {
+        if !pack_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for file in fs::read_dir(pack_dir).map_err(DitError::IoError)? {
+            let path = file.map_err(DitError::IoError)?.path();
+            if path.extension().is_some_and(|extension| extension == "idx") {
+                paths.push(path.with_extension("pack"));
+            }
+        }
+        paths.sort();
+        paths.into_iter().map(Pack::open).collect()
+    }
+
+    pub fn get_pack_path(&self) -> &PathBuf {
+        &self.pack_path
+    }
+
+    pub fn get_index_path(&self) -> PathBuf {
+        self.pack_path.with_extension("idx")
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    pub fn get_hashes(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Number of bytes of the object `hash` in the pack, the length of its compressed delta
+    /// when it is stored as one
+    pub fn get_entry_size(&self, hash: &str) -> Option<u64> {
+        self.entries.get(hash).map(|entry| entry.length as u64)
+    }
+
+    /// Return the content of the object `hash`, or None if it is not in the pack
+    pub fn read(&self, hash: &str) -> Result<Option<Vec<u8>>, DitError> {
+        if !self.contains(hash) {
+            return Ok(None);
+        }
+        let mut file = File::open(&self.pack_path).map_err(DitError::IoError)?;
+
+        let mut deltas: Vec<Vec<u8>> = Vec::new();
+        let mut current = hash.to_string();
+        let mut content = loop {
+            let bytes = self.read_entry(&mut file, &current)?;
+            match parse_entry(&bytes) {
+                Some((None, content)) => break self.inflate(&current, content)?,
+                Some((Some(base), delta)) if self.contains(base) && deltas.len() < MAX_DELTA_DEPTH => {
+                    deltas.push(self.inflate(&current, delta)?);
+                    current = base.to_string();
+                }
+                _ => return Err(corrupt_pack(&self.pack_path, format!("invalid entry for object {}", current))),
+            }
+        };
+        for delta in deltas.iter().rev() {
+            content = apply_delta(&content, delta).map_err(|reason| DitError::CorruptObject {
+                hash: hash.to_string(),
+                reason,
+            })?;
+        }
+        Ok(Some(content))
+    }
+
+    /// Return the bytes of the entry `hash`, read at its offset in `file`
+    fn read_entry(&self, file: &mut File, hash: &str) -> Result<Vec<u8>, DitError> {
+        let entry = self.entries[hash];
+        let out_of_pack = || corrupt_pack(&self.pack_path, format!("object {} is out of the pack", hash));
+        if entry.offset < HEADER_SIZE {
+            return Err(out_of_pack());
+        }
+        if file.stream_position().map_err(DitError::IoError)? == 0 {
+            let mut header = [0; HEADER_SIZE];
+            file.read_exact(&mut header).map_err(|_| out_of_pack())?;
+            check_header(&header, PACK_MAGIC, self.version, self.entries.len())
+                .map_err(|reason| corrupt_pack(&self.pack_path, reason))?;
+        }
+        file.seek(SeekFrom::Start(entry.offset as u64)).map_err(DitError::IoError)?;
+        let mut bytes: Vec<u8> = vec![0; entry.length];
+        file.read_exact(&mut bytes).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => out_of_pack(),
+            _ => DitError::IoError(e),
+        })?;
+        Ok(bytes)
+    }
+
+    /// Return the content or the delta of an entry
+    fn inflate(&self, hash: &str, bytes: &[u8]) -> Result<Vec<u8>, DitError> {
+        let mut content: Vec<u8> = Vec::new();
+        ZlibDecoder::new(bytes)
+            .read_to_end(&mut content)
+            .map_err(|e| corrupt_pack(&self.pack_path, format!("object {} can not be decompressed: {}", hash, e)))?;
+        Ok(content)
+    }
+
+    /// Write `objects` in a new pack of `pack_dir`. With `with_deltas`, each object is stored
+    /// as a delta against a similar one when it is much smaller; encrypted objects, which do
+    /// not look alike, are stored whole.
+    pub fn write(pack_dir: &Path, objects: &[(String, Vec<u8>)], with_deltas: bool) -> Result<(Pack, PackStats), DitError> {
+        fs::create_dir_all(pack_dir).map_err(DitError::IoError)?;
+
+        // Larger objects first, so the newer versions of a file, which usually grow, are
+        // stored whole and the older ones as deltas against them
+        let mut order: Vec<usize> = (0..objects.len()).collect();
+        order.sort_by(|a, b| objects[*b].1.len().cmp(&objects[*a].1.len()).then(objects[*a].0.cmp(&objects[*b].0)));
+
+        let mut data: Vec<u8> = Vec::new();
+        data.extend_from_slice(PACK_MAGIC);
+        data.extend_from_slice(&PACK_VERSION.to_be_bytes());
+        data.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+        let mut entries: BTreeMap<String, PackEntry> = BTreeMap::new();
+        let mut depths: Vec<usize> = vec![0; objects.len()];
+        let mut deltas = 0;
+        let window = if with_deltas { DELTA_WINDOW } else { 0 };
+        for (position, index) in order.iter().enumerate() {
+            let (hash, content) = &objects[*index];
+            let mut best: Option<(usize, Vec<u8>)> = None;
+            for base in order[position.saturating_sub(window)..position].iter() {
+                if depths[*base] >= MAX_DELTA_DEPTH {
+                    continue;
+                }
+                let delta = create_delta(&objects[*base].1, content);
+                let limit = best.as_ref().map(|(_, best)| best.len()).unwrap_or(content.len() / 2);
+                if delta.len() < limit {
+                    best = Some((*base, delta));
+                }
+            }
+
+            let offset = data.len();
+            match best {
+                Some((base, delta)) => {
+                    let base_hash = &objects[base].0;
+                    data.push(DELTA_ENTRY);
+                    data.push(base_hash.len() as u8);
+                    data.extend_from_slice(base_hash.as_bytes());
+                    data.extend_from_slice(&deflate(&delta)?);
+                    depths[*index] = depths[base] + 1;
+                    deltas += 1;
+                }
+                None => {
+                    data.push(WHOLE_ENTRY);
+                    data.extend_from_slice(&deflate(content)?);
+                }
+            }
+            entries.insert(hash.clone(), PackEntry { offset, length: data.len() - offset });
+        }
+
+        let name = format!("pack-{}", ObjectFormat::Sha1.hash(&entries.keys().cloned().collect::<Vec<String>>().join("\n")));
+        let pack_path = pack_dir.join(format!("{}.pack", name));
+        let index_path = pack_path.with_extension("idx");
+
+        // The index makes the pack visible, it is written once the pack is complete
+        let temporary_path = pack_dir.join(format!("{}.pack.tmp", name));
+        fs::write(&temporary_path, &data).map_err(DitError::IoError)?;
+        fs::rename(&temporary_path, &pack_path).map_err(DitError::IoError)?;
+
+        let temporary_path = pack_dir.join(format!("{}.idx.tmp", name));
+        fs::write(&temporary_path, encode_index(&entries)).map_err(DitError::IoError)?;
+        fs::rename(&temporary_path, &index_path).map_err(DitError::IoError)?;
+
+        let size = file_size(&pack_path)? + file_size(&index_path)?;
+        let pack = Pack {
+            pack_path,
+            version: PACK_VERSION,
+            entries,
+        };
+        Ok((pack, PackStats::new(objects.len(), deltas, size)))
+    }
+}
+
+/// What a repack wrote
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PackStats {
+    objects: usize,
+    deltas: usize,
+    size: u64,
+    previous_size: u64,
+}
+
+impl PackStats {
+    pub fn new(objects: usize, deltas: usize, size: u64) -> PackStats {
+        PackStats { objects, deltas, size, previous_size: 0 }
+    }
+
+    /// Number of objects in the pack
+    pub fn get_objects(&self) -> usize {
+        self.objects
+    }
+
+    /// Number of objects stored as deltas
+    pub fn get_deltas(&self) -> usize {
+        self.deltas
+    }
+
+    /// Size of the pack and of its index on disk
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Size of the loose objects and of the packs replaced by the pack
+    pub fn get_previous_size(&self) -> u64 {
+        self.previous_size
+    }
+
+    pub fn set_previous_size(&mut self, previous_size: u64) {
+        self.previous_size = previous_size;
+    }
+}
+
+/// Return the instructions rebuilding `target` from `base`: both sizes, then a list of
+/// `0 <length> <bytes>` to insert bytes and `1 <offset> <length>` to copy bytes of `base`
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta: Vec<u8> = Vec::new();
+    write_varint(&mut delta, base.len() as u64);
+    write_varint(&mut delta, target.len() as u64);
+
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    let mut offset = 0;
+    while offset + DELTA_BLOCK_SIZE <= base.len() {
+        blocks.entry(&base[offset..offset + DELTA_BLOCK_SIZE]).or_insert(offset);
+        offset += DELTA_BLOCK_SIZE;
+    }
+
+    let mut insert_start = 0;
+    let mut position = 0;
+    while position + DELTA_BLOCK_SIZE <= target.len() {
+        let Some(&start) = blocks.get(&target[position..position + DELTA_BLOCK_SIZE]) else {
+            position += 1;
+            continue;
+        };
+        let mut length = DELTA_BLOCK_SIZE;
+        while start + length < base.len() && position + length < target.len() && base[start + length] == target[position + length] {
+            length += 1;
+        }
+        let (mut start, mut position_start) = (start, position);
+        while start > 0 && position_start > insert_start && base[start - 1] == target[position_start - 1] {
+            start -= 1;
+            position_start -= 1;
+            length += 1;
+        }
+
+        push_insert(&mut delta, &target[insert_start..position_start]);
+        delta.push(COPY_INSTRUCTION);
+        write_varint(&mut delta, start as u64);
+        write_varint(&mut delta, length as u64);
+        position = position_start + length;
+        insert_start = position;
+    }
+    push_insert(&mut delta, &target[insert_start..]);
+    delta
+}
+
+/// Rebuild the target of `delta` from `base`
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, String> {
+    let mut position = 0;
+    let base_size = read_varint(delta, &mut position).ok_or("truncated delta")? as usize;
+    let target_size = read_varint(delta, &mut position).ok_or("truncated delta")? as usize;
+    if base_size != base.len() {
+        return Err(format!("delta expects a base of {} bytes but it has {}", base_size, base.len()));
+    }
+
+    let mut target: Vec<u8> = Vec::with_capacity(target_size);
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+        let first = read_varint(delta, &mut position).ok_or("truncated delta")? as usize;
+        match instruction {
+            INSERT_INSTRUCTION => {
+                let bytes = position
+                    .checked_add(first)
+                    .and_then(|end| delta.get(position..end))
+                    .ok_or("truncated delta")?;
+                target.extend_from_slice(bytes);
+                position += first;
+            }
+            COPY_INSTRUCTION => {
+                let length = read_varint(delta, &mut position).ok_or("truncated delta")? as usize;
+                let bytes = first
+                    .checked_add(length)
+                    .and_then(|end| base.get(first..end))
+                    .ok_or("delta copies bytes out of its base")?;
+                target.extend_from_slice(bytes);
+            }
+            _ => return Err(format!("unknown delta instruction {}", instruction)),
+        }
+    }
+    if target.len() != target_size {
+        return Err(format!("delta announces {} bytes but produces {}", target_size, target.len()));
+    }
+    Ok(target)
+}
+
+fn deflate(bytes: &[u8]) -> Result<Vec<u8>, DitError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).map_err(DitError::IoError)?;
+    encoder.finish().map_err(DitError::IoError)
+}
+
+fn push_insert(delta: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    delta.push(INSERT_INSTRUCTION);
+    write_varint(delta, bytes.len() as u64);
+    delta.extend_from_slice(bytes);
+}
+
+/// Write `value` 7 bits at a time, the high bit set on every byte but the last
+fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *input.get(*position)?;
+        *position += 1;
+        if shift > 63 {
+            return None;
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Split an entry of the pack into the hash of its base, for a delta, and its content
+fn parse_entry(bytes: &[u8]) -> Option<(Option<&str>, &[u8])> {
+    match *bytes.first()? {
+        WHOLE_ENTRY => Some((None, &bytes[1..])),
+        DELTA_ENTRY => {
+            let length = *bytes.get(1)? as usize;
+            let base = std::str::from_utf8(bytes.get(2..2 + length)?).ok()?;
+            Some((Some(base), &bytes[2 + length..]))
+        }
+        _ => None,
+    }
+}
+
+fn check_header(data: &[u8], magic: &[u8; 4], version: u32, count: usize) -> Result<(), String> {
+    if data.len() < HEADER_SIZE || &data[..4] != magic {
+        return Err(String::from("invalid header"));
+    }
+    let found = u32::from_be_bytes(data[4..8].try_into().unwrap());
+    if found != version {
+        return Err(format!("header announces version {} but the index has {}", found, version));
+    }
+    let found = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+    if found != count {
+        return Err(format!("header announces {} objects but the index has {}", found, count));
+    }
+    Ok(())
+}
+
+fn encode_index(entries: &BTreeMap<String, PackEntry>) -> Vec<u8> {
+    let mut index: Vec<u8> = Vec::new();
+    index.extend_from_slice(INDEX_MAGIC);
+    index.extend_from_slice(&PACK_VERSION.to_be_bytes());
+    index.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for (hash, entry) in entries.iter() {
+        index.push(hash.len() as u8);
+        index.extend_from_slice(hash.as_bytes());
+        index.extend_from_slice(&(entry.offset as u64).to_be_bytes());
+        index.extend_from_slice(&(entry.length as u64).to_be_bytes());
+    }
+    index
+}
+
+/// Return the version of the pack and its entries
+fn parse_index(index: &[u8]) -> Result<(u32, BTreeMap<String, PackEntry>), String> {
+    if index.len() < HEADER_SIZE {
+        return Err(String::from("truncated index"));
+    }
+    let version = u32::from_be_bytes(index[4..8].try_into().unwrap());
+    if version != PACK_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+    let count = u32::from_be_bytes(index[8..12].try_into().unwrap()) as usize;
+    check_header(index, INDEX_MAGIC, version, count)?;
+
+    let mut entries: BTreeMap<String, PackEntry> = BTreeMap::new();
+    let mut position = 12;
+    for _ in 0..count {
+        let length = *index.get(position).ok_or("truncated index")? as usize;
+        let hash = index.get(position + 1..position + 1 + length).ok_or("truncated index")?;
+        let hash = std::str::from_utf8(hash).map_err(|_| String::from("invalid hash in index"))?;
+        position += 1 + length;
+        let numbers = index.get(position..position + 16).ok_or("truncated index")?;
+        let offset = u64::from_be_bytes(numbers[..8].try_into().unwrap()) as usize;
+        let length = u64::from_be_bytes(numbers[8..].try_into().unwrap()) as usize;
+        position += 16;
+        entries.insert(hash.to_string(), PackEntry { offset, length });
+    }
+    Ok((version, entries))
+}
+
+fn file_size(path: &Path) -> Result<u64, DitError> {
+    Ok(fs::metadata(path).map_err(DitError::IoError)?.len())
+}
+
+fn corrupt_pack(path: &Path, reason: String) -> DitError {
+    DitError::CorruptPack { path: path.to_path_buf(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn versions() -> Vec<(String, Vec<u8>)> {
+        let base: String = (0..200).map(|line| format!("line {}\n", line)).collect();
+        let changed = base.replace("line 100\n", "line one hundred\n");
+        vec![
+            (String::from("aa01"), base.into_bytes()),
+            (String::from("aa02"), changed.into_bytes()),
+            (String::from("bb01"), b"small".to_vec()),
+        ]
+    }
+
+    #[test]
+    fn delta_rebuilds_target() {
+        let (_, base) = &versions()[0];
+        let (_, target) = &versions()[1];
+        let delta = create_delta(base, target);
+        assert!(delta.len() < target.len() / 4);
+        assert_eq!(&apply_delta(base, &delta).unwrap(), target);
+    }
+
+    #[test]
+    fn pack_reads_objects_through_its_index() {
+        let dir = TempDir::new("pack").unwrap();
+        let objects = versions();
+        let (pack, stats) = Pack::write(dir.path(), &objects, true).unwrap();
+        assert_eq!(stats.get_objects(), 3);
+        assert_eq!(stats.get_deltas(), 1);
+
+        let pack = Pack::open(pack.get_pack_path().clone()).unwrap();
+        for (hash, content) in objects.iter() {
+            assert_eq!(pack.read(hash).unwrap().as_ref(), Some(content));
+        }
+        assert_eq!(pack.read("cc01").unwrap(), None);
+    }
+
+    #[test]
+    fn pack_without_deltas_stores_objects_whole() {
+        let dir = TempDir::new("pack").unwrap();
+        let objects = versions();
+        let (pack, stats) = Pack::write(dir.path(), &objects, false).unwrap();
+        assert_eq!(stats.get_deltas(), 0);
+        assert_eq!(pack.read("aa02").unwrap().as_ref(), Some(&objects[1].1));
+    }
+
+    #[test]
+    fn pack_stores_entries_compressed() {
+        let dir = TempDir::new("pack").unwrap();
+        let objects = versions();
+        let (pack, _) = Pack::write(dir.path(), &objects, true).unwrap();
+        let data = fs::read(pack.get_pack_path()).unwrap();
+        assert_eq!(&data[..4], PACK_MAGIC);
+        assert!(data.len() < objects[0].1.len());
+    }
+
+    #[test]
+    fn truncated_pack_is_corrupt() {
+        let dir = TempDir::new("pack").unwrap();
+        let (pack, _) = Pack::write(dir.path(), &versions(), true).unwrap();
+        let data = fs::read(pack.get_pack_path()).unwrap();
+        fs::write(pack.get_pack_path(), &data[..data.len() / 2]).unwrap();
+
+        let pack = Pack::open(pack.get_pack_path().clone()).unwrap();
+        let errors = ["aa01", "aa02"].iter().filter(|hash| pack.read(hash).is_err()).count();
+        assert!(errors > 0);
+    }
+}
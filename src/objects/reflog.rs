@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::DitError;
+use crate::objects::config::Config;
+use crate::objects::lock::{is_lock_file, Transaction};
+use crate::repository::Repository;
+use crate::utils::read_dit_file;
+
+pub const HEAD_LOG: &str = "HEAD";
+
+/// A movement of HEAD or of a branch tip.
+///
+/// Entries are stored one per line, oldest first, in `.dit/logs/HEAD` and
+/// `.dit/logs/refs/<branch>` as `<old> <new> <timestamp>\t<identity>\t<reason>`.
+#[derive(Clone, Debug)]
+pub struct ReflogEntry {
+    old: String,
+    new: String,
+    identity: String,
+    timestamp: u64,
+    reason: String,
+}
+
+impl ReflogEntry {
+    /// Create an entry made now by the identity of the repository config
+    pub fn new(repo: &Repository, old: String, new: String, reason: String) -> ReflogEntry {
+        let identity = Config::load(repo).unwrap_or_default().get_identity();
+        ReflogEntry {
+            old,
+            new,
+            identity,
+            timestamp: now(),
+            reason,
+        }
+    }
+
+    pub fn get_old(&self) -> &String {
+        &self.old
+    }
+
+    pub fn get_new(&self) -> &String {
+        &self.new
+    }
+
+    pub fn set_old(&mut self, old: String) {
+        self.old = old;
+    }
+
+    pub fn set_new(&mut self, new: String) {
+        self.new = new;
+    }
+
+    pub fn get_identity(&self) -> &String {
+        &self.identity
+    }
+
+    pub fn get_timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn get_reason(&self) -> &String {
+        &self.reason
+    }
+
+    /// Append the entry to the log of `name`, `HEAD` or a branch name
+    pub fn append(&self, repo: &Repository, name: &String) -> Result<(), DitError> {
+        let mut transaction = Transaction::new(repo);
+        self.append_to(&mut transaction, name)?;
+        transaction.commit()
+    }
+
+    /// Append the entry to the log of `name` as part of `transaction`
+    pub fn append_to(&self, transaction: &mut Transaction, name: &String) -> Result<(), DitError> {
+        let repo = transaction.get_repo();
+        transaction.lock(get_log_path(repo, name))?;
+        let mut entries = Self::get_entries(repo, name)?;
+        entries.push(self.clone());
+        Self::write_entries_to(transaction, name, &entries)
+    }
+
+    /// Return the entries of the log of `name`, oldest first
+    pub fn get_entries(repo: &Repository, name: &String) -> Result<Vec<ReflogEntry>, DitError> {
+        let log_path = get_log_path(repo, name);
+        if !log_path.is_file() || log_path.metadata().map_err(DitError::IoError)?.len() == 0 {
+            return Ok(vec![]);
+        }
+
+        let content = read_dit_file(repo, &log_path)?;
+
+        let mut entries: Vec<ReflogEntry> = Vec::new();
+        for line in String::from_utf8_lossy(&content).lines() {
+            if !line.is_empty() {
+                entries.push(Self::from_line(line)?);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Replace the log of `name` by `entries`
+    pub fn write_entries(repo: &Repository, name: &String, entries: &[ReflogEntry]) -> Result<(), DitError> {
+        let mut transaction = Transaction::new(repo);
+        Self::write_entries_to(&mut transaction, name, entries)?;
+        transaction.commit()
+    }
+
+    fn write_entries_to(transaction: &mut Transaction, name: &String, entries: &[ReflogEntry]) -> Result<(), DitError> {
+        let content: String = entries.iter().map(|entry| format!("{}\n", entry.to_line())).collect();
+        let path = get_log_path(transaction.get_repo(), name);
+        transaction.write(path, content.as_bytes())
+    }
+
+    /// Return the names of every existing log, `HEAD` first
+    pub fn get_log_names(repo: &Repository) -> Result<Vec<String>, DitError> {
+        let mut names: Vec<String> = Vec::new();
+        if get_log_path(repo, &HEAD_LOG.to_string()).is_file() {
+            names.push(HEAD_LOG.to_string());
+        }
+
+        let refs_logs = repo.get_logs_path().join("refs");
+        if refs_logs.is_dir() {
+            for entry in fs::read_dir(refs_logs).map_err(DitError::IoError)? {
+                let entry = entry.map_err(DitError::IoError)?;
+                if !is_lock_file(&entry.path()) {
+                    names.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    fn to_line(&self) -> String {
+        format!("{} {} {}\t{}\t{}", self.old, self.new, self.timestamp, self.identity, self.reason)
+    }
+
+    fn from_line(line: &str) -> Result<ReflogEntry, DitError> {
+        let invalid = || DitError::UnexpectedComportement(format!("Invalid reflog line: {}", line));
+
+        let mut fields = line.splitn(3, '\t');
+        let (Some(header), Some(identity), Some(reason)) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(invalid());
+        };
+        let header: Vec<&str> = header.split_whitespace().collect();
+        if header.len() != 3 {
+            return Err(invalid());
+        }
+        let timestamp = header[2].parse::<u64>().map_err(|_| invalid())?;
+
+        Ok(ReflogEntry {
+            old: header[0].to_string(),
+            new: header[1].to_string(),
+            identity: identity.to_string(),
+            timestamp,
+            reason: reason.to_string(),
+        })
+    }
+}
+
+/// Record a movement of the tip of `branch`, which is also a movement of HEAD
+pub fn record_branch_movement(transaction: &mut Transaction, branch: &String, old: &str, new: &str, reason: &str) -> Result<(), DitError> {
+    let entry = ReflogEntry::new(transaction.get_repo(), old.to_string(), new.to_string(), reason.to_string());
+    entry.append_to(transaction, branch)?;
+    entry.append_to(transaction, &HEAD_LOG.to_string())?;
+    Ok(())
+}
+
+/// Record a movement of HEAD which does not change any branch tip
+pub fn record_head_movement(transaction: &mut Transaction, old: &str, new: &str, reason: &str) -> Result<(), DitError> {
+    ReflogEntry::new(transaction.get_repo(), old.to_string(), new.to_string(), reason.to_string())
+        .append_to(transaction, &HEAD_LOG.to_string())
+}
+
+fn get_log_path(repo: &Repository, name: &String) -> PathBuf {
+    let logs_path = repo.get_logs_path();
+    if name == HEAD_LOG {
+        logs_path.join(HEAD_LOG)
+    } else {
+        logs_path.join("refs").join(name)
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
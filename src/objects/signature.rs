@@ -0,0 +1,160 @@
+use std::fs;
+use std::path::PathBuf;
+
+use ssh_key::{HashAlg, LineEnding, PrivateKey, PublicKey, SshSig};
+
+use crate::error::DitError;
+use crate::objects::config::{Config, ALLOWED_SIGNERS_FILE, SIGNING_KEY};
+use crate::repository::Repository;
+
+/// Namespace of the SSH signatures made by dit, so that they can not be mistaken for
+/// signatures of other tools
+pub const SIGNATURE_NAMESPACE: &str = "dit";
+/// Allowed signers file used when `signing.allowedSignersFile` is not set, in `.dit`
+pub const DEFAULT_ALLOWED_SIGNERS: &str = "allowed_signers";
+/// Prefix of the lines of an object holding its signature
+const SIGNATURE_PREFIX: &str = "sig ";
+
+/// Result of the verification of the signature of a commit or a tag
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verification {
+    /// Valid signature by a key of the allowed signers file, listed for `principals`
+    Good { principals: String, fingerprint: String },
+    /// Valid signature by a key missing from the allowed signers file
+    UnknownKey { fingerprint: String },
+    Bad { reason: String },
+    Unsigned,
+}
+
+impl Verification {
+    pub fn is_good(&self) -> bool {
+        matches!(self, Verification::Good { .. })
+    }
+
+    /// Name of the result in the porcelain and JSON outputs
+    pub fn get_status(&self) -> &'static str {
+        match self {
+            Verification::Good { .. } => "good",
+            Verification::UnknownKey { .. } => "unknown",
+            Verification::Bad { .. } => "bad",
+            Verification::Unsigned => "none",
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Verification::Good { principals, fingerprint } => format!("Good signature from {} with key {}", principals, fingerprint),
+            Verification::UnknownKey { fingerprint } => format!("Signature made with key {}, which is not an allowed signer", fingerprint),
+            Verification::Bad { reason } => format!("Bad signature: {}", reason),
+            Verification::Unsigned => String::from("No signature"),
+        }
+    }
+}
+
+/// Sign `payload` with the OpenSSH private key of `user.signingKey`, return the armored
+/// SSH signature
+pub fn sign(repo: &Repository, payload: &[u8]) -> Result<String, DitError> {
+    let config = Config::load(repo)?;
+    let path = config
+        .get(SIGNING_KEY)
+        .map(PathBuf::from)
+        .ok_or_else(|| DitError::UnexpectedComportement(format!("Set {} to the path of an SSH private key to sign", SIGNING_KEY)))?;
+    let key = PrivateKey::read_openssh_file(&path)
+        .map_err(|e| DitError::UnexpectedComportement(format!("Unable to read the signing key {}: {}", path.display(), e)))?;
+    if key.is_encrypted() {
+        return Err(DitError::UnexpectedComportement(format!("The signing key {} is protected by a passphrase, which dit does not support", path.display())));
+    }
+    key.sign(SIGNATURE_NAMESPACE, HashAlg::Sha512, payload)
+        .and_then(|signature| signature.to_pem(LineEnding::LF))
+        .map_err(|e| DitError::UnexpectedComportement(format!("Unable to sign with {}: {}", path.display(), e)))
+}
+
+/// Check the armored `signature` of `payload` against the keys of the allowed signers file
+pub fn verify(repo: &Repository, payload: &[u8], signature: &str) -> Result<Verification, DitError> {
+    if signature.is_empty() {
+        return Ok(Verification::Unsigned);
+    }
+    let signature = match SshSig::from_pem(signature) {
+        Ok(signature) => signature,
+        Err(e) => return Ok(Verification::Bad { reason: e.to_string() }),
+    };
+    let fingerprint = signature.public_key().fingerprint(HashAlg::Sha256).to_string();
+    let Some((principals, key)) = find_allowed_signer(repo, &signature)? else {
+        return Ok(Verification::UnknownKey { fingerprint });
+    };
+    match key.verify(SIGNATURE_NAMESPACE, payload, &signature) {
+        Ok(()) => Ok(Verification::Good { principals, fingerprint }),
+        Err(e) => Ok(Verification::Bad { reason: e.to_string() }),
+    }
+}
+
+/// Return the principals and the key of the allowed signers file which made `signature`.
+///
+/// Each line of the file holds comma separated principals, optional options of which only
+/// `namespaces="..."` is taken into account, and a public key as written in OpenSSH
+/// `.pub` files, like the `allowed_signers` files of `ssh-keygen -Y verify`.
+fn find_allowed_signer(repo: &Repository, signature: &SshSig) -> Result<Option<(String, PublicKey)>, DitError> {
+    let path = get_allowed_signers_path(repo)?;
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path).map_err(DitError::IoError)?;
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let mut fields = line.split_whitespace();
+        let Some(principals) = fields.next() else {
+            continue;
+        };
+        let mut fields: Vec<&str> = fields.collect();
+        let options = match fields.first() {
+            Some(field) if field.contains('=') => fields.remove(0),
+            _ => "",
+        };
+        if !allows_namespace(options) {
+            continue;
+        }
+        let Ok(key) = PublicKey::from_openssh(&fields.join(" ")) else {
+            continue;
+        };
+        if key.key_data() == signature.public_key() {
+            return Ok(Some((principals.to_string(), key)));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the options of an allowed signer do not restrict its namespaces or include dit's
+fn allows_namespace(options: &str) -> bool {
+    let Some((_, namespaces)) = options.split_once("namespaces=\"") else {
+        return true;
+    };
+    let namespaces = namespaces.split('"').next().unwrap_or_default();
+    namespaces.split(',').any(|namespace| namespace.trim() == SIGNATURE_NAMESPACE)
+}
+
+/// Path of the allowed signers file, `signing.allowedSignersFile` relative to the working
+/// directory or `.dit/allowed_signers`
+pub fn get_allowed_signers_path(repo: &Repository) -> Result<PathBuf, DitError> {
+    Ok(match Config::load(repo)?.get(ALLOWED_SIGNERS_FILE) {
+        Some(path) => repo.get_work_dir().join(path),
+        None => repo.get_dit_path().join(DEFAULT_ALLOWED_SIGNERS),
+    })
+}
+
+/// Return `signature` as the `sig ` lines embedded in a commit or a tag
+pub fn encode_signature(signature: &str) -> String {
+    signature.lines().map(|line| format!("{}{}\n", SIGNATURE_PREFIX, line)).collect()
+}
+
+/// Split `content` into the `sig ` lines which start it, returned as the armored signature,
+/// and the rest
+pub fn decode_signature(content: &str) -> (String, &str) {
+    let mut signature = String::new();
+    let mut rest = content;
+    while let Some(line) = rest.strip_prefix(SIGNATURE_PREFIX) {
+        let (value, next) = line.split_once('\n').unwrap_or((line, ""));
+        signature.push_str(value);
+        signature.push('\n');
+        rest = next;
+    }
+    (signature, rest)
+}
@@ -0,0 +1,671 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::UNIX_EPOCH;
+
+use dit_file_encryptor::CompressedFile;
+
+use crate::error::DitError;
+use crate::objects::crypt::Cipher;
+use crate::objects::hash::ObjectFormat;
+use crate::objects::object::{Object, ObjectKind};
+use crate::objects::pack::{Pack, PACK_DIR, PackStats};
+use crate::objects::reflog::now;
+use crate::objects::tree::{decode_tree, encode_tree, Node, Tree};
+use crate::repository::Repository;
+
+static TEMPORARY_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Storage of the commits, trees and blobs of a repository, addressed by hash
+pub trait ObjectStore: Send + Sync {
+    fn has(&self, hash: &str) -> Result<bool, DitError>;
+
+    /// Return the content of the object `hash`, or `ObjectNotFound`
+    fn read(&self, hash: &str) -> Result<Vec<u8>, DitError>;
+
+    /// Store `content` as the object `hash`, return false if it already exists
+    fn write(&self, hash: &str, content: &[u8]) -> Result<bool, DitError>;
+
+    /// Return the hash of every object, sorted
+    fn iterate(&self) -> Result<Vec<String>, DitError>;
+
+    /// Return the stored size and the modification date of the object `hash`
+    fn stat(&self, hash: &str) -> Result<ObjectStat, DitError>;
+
+    /// Delete the loose object `hash`, return false if it does not exist or is packed
+    fn remove(&self, hash: &str) -> Result<bool, DitError>;
+
+    /// Write the objects `hashes` in a new pack, then delete their loose files and the
+    /// previous packs: the packed objects missing from `hashes` are dropped. Return None
+    /// when the store has no packs.
+    fn repack(&self, hashes: &[String]) -> Result<Option<PackStats>, DitError> {
+        let _ = hashes;
+        Ok(None)
+    }
+
+    /// Read the object `hash` and parse its type header
+    fn read_object(&self, hash: &str) -> Result<Object, DitError> {
+        Object::decode(hash, &self.read(hash)?)
+    }
+
+    /// Return the content of the blob `hash`
+    fn read_blob(&self, hash: &str) -> Result<Vec<u8>, DitError> {
+        let object = self.read_object(hash)?;
+        check_kind(hash, &object, ObjectKind::Blob)?;
+        Ok(object.into_content())
+    }
+
+    /// Store `content` as a blob whose id is computed with `format`, return the id and whether
+    /// the blob was written
+    fn write_blob(&self, format: ObjectFormat, content: &[u8]) -> Result<(String, bool), DitError> {
+        let object = Object::new(ObjectKind::Blob, content.to_vec());
+        let hash = object.get_id(format);
+        let written = self.write(&hash, &object.encode())?;
+        Ok((hash, written))
+    }
+
+    /// Read the tree `hash` and its subtrees
+    fn read_tree(&self, hash: &str) -> Result<Tree, DitError> {
+        read_tree_at(self, hash, Path::new(""))
+    }
+
+    /// Write the trees of `root` whose id is unknown, computing it with `format`, and return
+    /// the id of `root`. The blobs of the tree must already be stored.
+    fn write_tree(&self, format: ObjectFormat, root: &mut Node) -> Result<String, DitError> {
+        if let Node::TreeNode(tree) = root {
+            if tree.get_id().is_empty() {
+                for child in tree.get_mut_children().iter_mut() {
+                    self.write_tree(format, child)?;
+                }
+                let object = Object::new(ObjectKind::Tree, encode_tree(tree));
+                let hash = object.get_id(format);
+                self.write(&hash, &object.encode())?;
+                tree.set_id(hash);
+            }
+        }
+        Ok(root.get_id())
+    }
+}
+
+/// Storage information on an object
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjectStat {
+    size: u64,
+    modified: u64,
+    packed: bool,
+}
+
+impl ObjectStat {
+    pub fn new(size: u64, modified: u64, packed: bool) -> ObjectStat {
+        ObjectStat { size, modified, packed }
+    }
+
+    /// Number of bytes used by the object in the store
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Unix timestamp of the last write of the object, or of its pack
+    pub fn get_modified(&self) -> u64 {
+        self.modified
+    }
+
+    /// Whether the object is only stored in a pack
+    pub fn is_packed(&self) -> bool {
+        self.packed
+    }
+}
+
+/// Objects stored one per compressed file in `.dit/objects/<2 first chars>/<other chars>`
+#[derive(Clone, Debug)]
+pub struct LooseObjectStore {
+    objects_path: PathBuf,
+}
+
+impl LooseObjectStore {
+    pub fn new(objects_path: PathBuf) -> LooseObjectStore {
+        LooseObjectStore { objects_path }
+    }
+
+    pub fn get_objects_path(&self) -> &PathBuf {
+        &self.objects_path
+    }
+
+    fn get_object_file(&self, hash: &str) -> Option<PathBuf> {
+        if hash.len() <= 2 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(self.objects_path.join(&hash[..2]).join(&hash[2..]))
+    }
+}
+
+impl ObjectStore for LooseObjectStore {
+    fn has(&self, hash: &str) -> Result<bool, DitError> {
+        Ok(self.get_object_file(hash).is_some_and(|path| path.is_file()))
+    }
+
+    fn read(&self, hash: &str) -> Result<Vec<u8>, DitError> {
+        match self.get_object_file(hash) {
+            Some(path) if path.is_file() => read_loose_object(&path),
+            _ => Err(DitError::ObjectNotFound { hash: hash.to_string() }),
+        }
+    }
+
+    fn write(&self, hash: &str, content: &[u8]) -> Result<bool, DitError> {
+        let path = self.get_object_file(hash).ok_or_else(|| DitError::CorruptObject {
+            hash: hash.to_string(),
+            reason: String::from("invalid hash"),
+        })?;
+        if path.is_file() {
+            return Ok(false);
+        }
+        write_loose_object(&path, content)?;
+        Ok(true)
+    }
+
+    fn iterate(&self) -> Result<Vec<String>, DitError> {
+        list_loose_objects(&self.objects_path)
+    }
+
+    fn stat(&self, hash: &str) -> Result<ObjectStat, DitError> {
+        let path = match self.get_object_file(hash) {
+            Some(path) if path.is_file() => path,
+            _ => return Err(DitError::ObjectNotFound { hash: hash.to_string() }),
+        };
+        let (size, modified) = file_stat(&path)?;
+        Ok(ObjectStat::new(size, modified, false))
+    }
+
+    fn remove(&self, hash: &str) -> Result<bool, DitError> {
+        match self.get_object_file(hash) {
+            Some(path) if path.is_file() => {
+                fs::remove_file(path).map_err(DitError::IoError)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Loose objects of `.dit/objects` and packs of `.dit/objects/pack`, the default store.
+/// New objects are written loose, `repack` moves them to a pack.
+#[derive(Debug)]
+pub struct PackedObjectStore {
+    loose: LooseObjectStore,
+    packs: RwLock<Option<Vec<Arc<Pack>>>>,
+}
+
+impl PackedObjectStore {
+    pub fn new(objects_path: PathBuf) -> PackedObjectStore {
+        PackedObjectStore {
+            loose: LooseObjectStore::new(objects_path),
+            packs: RwLock::new(None),
+        }
+    }
+
+    pub fn get_objects_path(&self) -> &PathBuf {
+        self.loose.get_objects_path()
+    }
+
+    pub fn get_pack_path(&self) -> PathBuf {
+        self.loose.get_objects_path().join(PACK_DIR)
+    }
+
+    /// Return the packs, read from the pack directory on first use
+    pub fn get_packs(&self) -> Result<Vec<Arc<Pack>>, DitError> {
+        if let Some(packs) = self.packs.read().map_err(|_| lock_error())?.as_ref() {
+            return Ok(packs.clone());
+        }
+        let packs: Vec<Arc<Pack>> = Pack::open_all(&self.get_pack_path())?.into_iter().map(Arc::new).collect();
+        *self.packs.write().map_err(|_| lock_error())? = Some(packs.clone());
+        Ok(packs)
+    }
+
+    fn find_pack(&self, hash: &str) -> Result<Option<Arc<Pack>>, DitError> {
+        Ok(self.get_packs()?.into_iter().find(|pack| pack.contains(hash)))
+    }
+
+    /// Write `objects` as they are in a new pack, as deltas when `with_deltas`, then delete
+    /// their loose files and the previous packs, see `repack`
+    pub fn replace_objects(&self, objects: &[(String, Vec<u8>)], with_deltas: bool) -> Result<PackStats, DitError> {
+        let mut previous_size: u64 = 0;
+        for (hash, _) in objects {
+            if self.loose.has(hash)? {
+                previous_size += self.loose.stat(hash)?.get_size();
+            }
+        }
+        let previous_packs = self.get_packs()?;
+        for pack in previous_packs.iter() {
+            previous_size += file_stat(pack.get_pack_path())?.0 + file_stat(&pack.get_index_path())?.0;
+        }
+
+        let (pack, mut stats) = Pack::write(&self.get_pack_path(), objects, with_deltas)?;
+        stats.set_previous_size(previous_size);
+
+        // The new pack is complete, the objects it replaces can go
+        for previous in previous_packs.iter() {
+            if previous.get_pack_path() != pack.get_pack_path() {
+                fs::remove_file(previous.get_index_path()).map_err(DitError::IoError)?;
+                fs::remove_file(previous.get_pack_path()).map_err(DitError::IoError)?;
+            }
+        }
+        for (hash, _) in objects {
+            self.loose.remove(hash)?;
+        }
+        self.reset()?;
+        Ok(stats)
+    }
+
+    /// Forget the packs after a repack
+    fn reset(&self) -> Result<(), DitError> {
+        *self.packs.write().map_err(|_| lock_error())? = None;
+        Ok(())
+    }
+}
+
+impl ObjectStore for PackedObjectStore {
+    fn has(&self, hash: &str) -> Result<bool, DitError> {
+        Ok(self.loose.has(hash)? || self.find_pack(hash)?.is_some())
+    }
+
+    fn read(&self, hash: &str) -> Result<Vec<u8>, DitError> {
+        if self.loose.has(hash)? {
+            return self.loose.read(hash);
+        }
+        match self.find_pack(hash)? {
+            Some(pack) => pack.read(hash)?.ok_or_else(|| DitError::ObjectNotFound { hash: hash.to_string() }),
+            None => Err(DitError::ObjectNotFound { hash: hash.to_string() }),
+        }
+    }
+
+    fn write(&self, hash: &str, content: &[u8]) -> Result<bool, DitError> {
+        if self.find_pack(hash)?.is_some() {
+            return Ok(false);
+        }
+        self.loose.write(hash, content)
+    }
+
+    fn iterate(&self) -> Result<Vec<String>, DitError> {
+        let mut hashes = self.loose.iterate()?;
+        for pack in self.get_packs()? {
+            hashes.extend(pack.get_hashes());
+        }
+        hashes.sort();
+        hashes.dedup();
+        Ok(hashes)
+    }
+
+    fn stat(&self, hash: &str) -> Result<ObjectStat, DitError> {
+        if self.loose.has(hash)? {
+            return self.loose.stat(hash);
+        }
+        let pack = self.find_pack(hash)?.ok_or_else(|| DitError::ObjectNotFound { hash: hash.to_string() })?;
+        let (_, modified) = file_stat(pack.get_pack_path())?;
+        Ok(ObjectStat::new(pack.get_entry_size(hash).unwrap_or(0), modified, true))
+    }
+
+    fn remove(&self, hash: &str) -> Result<bool, DitError> {
+        self.loose.remove(hash)
+    }
+
+    fn repack(&self, hashes: &[String]) -> Result<Option<PackStats>, DitError> {
+        let mut objects: Vec<(String, Vec<u8>)> = Vec::new();
+        for hash in hashes {
+            objects.push((hash.clone(), self.read(hash)?));
+        }
+        self.replace_objects(&objects, true).map(Some)
+    }
+}
+
+/// Objects of a `PackedObjectStore` encrypted with the cipher of an encrypted repository. The
+/// ids of the objects are the hashes of their decrypted content, which remain visible in the
+/// names of the loose objects and in the pack indexes, and `repack` encrypts every object again
+/// with the current key of the cipher, without deltas.
+#[derive(Debug)]
+pub struct EncryptedObjectStore {
+    store: PackedObjectStore,
+    cipher: Arc<Cipher>,
+}
+
+impl EncryptedObjectStore {
+    pub fn new(store: PackedObjectStore, cipher: Arc<Cipher>) -> EncryptedObjectStore {
+        EncryptedObjectStore {
+            store,
+            cipher,
+        }
+    }
+}
+
+impl ObjectStore for EncryptedObjectStore {
+    fn has(&self, hash: &str) -> Result<bool, DitError> {
+        self.store.has(hash)
+    }
+
+    fn read(&self, hash: &str) -> Result<Vec<u8>, DitError> {
+        let data = self.store.read(hash)?;
+        self.cipher.decrypt(&data).ok_or_else(|| DitError::CorruptObject {
+            hash: hash.to_string(),
+            reason: String::from("it can not be decrypted with the key of the repository"),
+        })
+    }
+
+    fn write(&self, hash: &str, content: &[u8]) -> Result<bool, DitError> {
+        if self.store.has(hash)? {
+            return Ok(false);
+        }
+        self.store.write(hash, &self.cipher.encrypt(content)?)
+    }
+
+    fn iterate(&self) -> Result<Vec<String>, DitError> {
+        self.store.iterate()
+    }
+
+    fn stat(&self, hash: &str) -> Result<ObjectStat, DitError> {
+        self.store.stat(hash)
+    }
+
+    fn remove(&self, hash: &str) -> Result<bool, DitError> {
+        self.store.remove(hash)
+    }
+
+    fn repack(&self, hashes: &[String]) -> Result<Option<PackStats>, DitError> {
+        let mut objects: Vec<(String, Vec<u8>)> = Vec::new();
+        for hash in hashes {
+            objects.push((hash.clone(), self.cipher.encrypt(&self.read(hash)?)?));
+        }
+        // Deltas between ciphertexts save nothing
+        self.store.replace_objects(&objects, false).map(Some)
+    }
+}
+
+/// Objects kept in memory, for tests and embedders which do not need them on disk
+#[derive(Debug, Default)]
+pub struct MemoryObjectStore {
+    objects: RwLock<BTreeMap<String, (Vec<u8>, u64)>>,
+}
+
+impl MemoryObjectStore {
+    pub fn new() -> MemoryObjectStore {
+        MemoryObjectStore::default()
+    }
+}
+
+impl ObjectStore for MemoryObjectStore {
+    fn has(&self, hash: &str) -> Result<bool, DitError> {
+        let objects = self.objects.read().map_err(|_| lock_error())?;
+        Ok(objects.contains_key(hash))
+    }
+
+    fn read(&self, hash: &str) -> Result<Vec<u8>, DitError> {
+        let objects = self.objects.read().map_err(|_| lock_error())?;
+        objects
+            .get(hash)
+            .map(|(content, _)| content.clone())
+            .ok_or_else(|| DitError::ObjectNotFound { hash: hash.to_string() })
+    }
+
+    fn write(&self, hash: &str, content: &[u8]) -> Result<bool, DitError> {
+        let mut objects = self.objects.write().map_err(|_| lock_error())?;
+        if objects.contains_key(hash) {
+            return Ok(false);
+        }
+        objects.insert(hash.to_string(), (content.to_vec(), now()));
+        Ok(true)
+    }
+
+    fn iterate(&self) -> Result<Vec<String>, DitError> {
+        let objects = self.objects.read().map_err(|_| lock_error())?;
+        Ok(objects.keys().cloned().collect())
+    }
+
+    fn stat(&self, hash: &str) -> Result<ObjectStat, DitError> {
+        let objects = self.objects.read().map_err(|_| lock_error())?;
+        objects
+            .get(hash)
+            .map(|(content, modified)| ObjectStat::new(content.len() as u64, *modified, false))
+            .ok_or_else(|| DitError::ObjectNotFound { hash: hash.to_string() })
+    }
+
+    fn remove(&self, hash: &str) -> Result<bool, DitError> {
+        let mut objects = self.objects.write().map_err(|_| lock_error())?;
+        Ok(objects.remove(hash).is_some())
+    }
+}
+
+/// Load the tree `hash` and its children from the object store of `repo`
+pub fn load_tree(repo: &Repository, hash: &str) -> Result<Tree, DitError> {
+    repo.get_store().read_tree(hash)
+}
+
+/// Save the trees of `root` changed since they were read in the object store of `repo` and
+/// return the id of `root`
+pub fn save_tree(repo: &Repository, root: &mut Node) -> Result<String, DitError> {
+    repo.get_store().write_tree(repo.get_object_format(), root)
+}
+
+/// Read the tree `hash` of `store` whose entries are in the directory `path`, and its subtrees
+fn read_tree_at<S: ObjectStore + ?Sized>(store: &S, hash: &str, path: &Path) -> Result<Tree, DitError> {
+    let object = store.read_object(hash)?;
+    check_kind(hash, &object, ObjectKind::Tree)?;
+    let mut children = decode_tree(hash, object.get_content(), path)?;
+    for child in children.iter_mut() {
+        let child_path = child.get_path();
+        if let Node::TreeNode(subtree) = child {
+            *subtree = read_tree_at(store, &subtree.get_id(), &child_path)?;
+        }
+    }
+    let mut tree = Tree::new(String::new(), path.to_path_buf());
+    tree.set_children(children);
+    tree.set_id(hash.to_string());
+    Ok(tree)
+}
+
+fn check_kind(hash: &str, object: &Object, kind: ObjectKind) -> Result<(), DitError> {
+    if object.get_kind() != kind {
+        return Err(DitError::CorruptObject {
+            hash: hash.to_string(),
+            reason: format!("expected a {} but found a {}", kind, object.get_kind()),
+        });
+    }
+    Ok(())
+}
+
+fn read_loose_object(path: &Path) -> Result<Vec<u8>, DitError> {
+    let mut reader = CompressedFile::new(path.to_path_buf())
+        .open_for_read()
+        .map_err(DitError::IoError)?;
+    let mut content: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut content).map_err(DitError::IoError)?;
+    Ok(content)
+}
+
+/// Write the object to a temporary file renamed once complete, so that an interrupted write
+/// never leaves a truncated object behind
+fn write_loose_object(path: &Path, content: &[u8]) -> Result<(), DitError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(DitError::IoError)?;
+    }
+    let mut temporary_path = path.as_os_str().to_owned();
+    let count = TEMPORARY_FILE_COUNT.fetch_add(1, Ordering::Relaxed);
+    temporary_path.push(format!(".tmp-{}-{}", process::id(), count));
+    let temporary_path = PathBuf::from(temporary_path);
+
+    let mut writer = CompressedFile::create_file(temporary_path.clone())
+        .map_err(DitError::IoError)?
+        .open_for_write()
+        .map_err(DitError::IoError)?;
+    writer.write_all(content).map_err(DitError::IoError)?;
+    drop(writer);
+    fs::rename(&temporary_path, path).map_err(DitError::IoError)
+}
+
+fn list_loose_objects(objects_path: &Path) -> Result<Vec<String>, DitError> {
+    let mut hashes: Vec<String> = Vec::new();
+    for dir in fs::read_dir(objects_path).map_err(DitError::IoError)? {
+        let dir = dir.map_err(DitError::IoError)?;
+        let prefix = dir.file_name().to_string_lossy().to_string();
+        if !dir.path().is_dir() || prefix.len() != 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            continue;
+        }
+        for file in fs::read_dir(dir.path()).map_err(DitError::IoError)? {
+            let file = file.map_err(DitError::IoError)?;
+            let is_temporary = file.file_name().to_string_lossy().contains('.');
+            if file.path().is_file() && !is_temporary {
+                hashes.push(format!("{}{}", prefix, file.file_name().to_string_lossy()));
+            }
+        }
+    }
+    hashes.sort();
+    Ok(hashes)
+}
+
+/// Create the 256 directories `00` to `ff` of the loose objects in `objects_path`
+pub fn create_object_dirs(objects_path: &Path) -> Result<(), std::io::Error> {
+    for i in 0..=255 {
+        fs::create_dir_all(objects_path.join(format!("{:02x}", i)))?;
+    }
+    Ok(())
+}
+
+/// Return the size and the modification date of `path`
+fn file_stat(path: &Path) -> Result<(u64, u64), DitError> {
+    let metadata = fs::metadata(path).map_err(DitError::IoError)?;
+    let modified = metadata
+        .modified()
+        .map_err(DitError::IoError)?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    Ok((metadata.len(), modified))
+}
+
+fn lock_error() -> DitError {
+    DitError::UnexpectedComportement(String::from("Object store lock is poisoned"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::objects::tree::create_tree;
+
+    fn sample_tree<S: ObjectStore>(store: &S) -> (Node, Vec<(PathBuf, String)>) {
+        let mut blobs: Vec<(PathBuf, String)> = Vec::new();
+        for (path, content) in [("a/b/f.txt", "one\n"), ("a/g.txt", "two\n"), ("h.txt", "one\n")] {
+            let (hash, _) = store.write_blob(ObjectFormat::Sha1, content.as_bytes()).unwrap();
+            blobs.push((PathBuf::from(path), hash));
+        }
+        (create_tree(Tree::default(), &blobs), blobs)
+    }
+
+    fn collect(node: &Node, blobs: &mut Vec<(PathBuf, String)>) {
+        match node {
+            Node::TreeNode(tree) => tree.get_children().iter().for_each(|child| collect(child, blobs)),
+            blob => blobs.push((blob.get_path(), blob.get_id())),
+        }
+    }
+
+    #[test]
+    fn memory_store_reads_what_it_writes() {
+        let store = MemoryObjectStore::new();
+        assert!(store.write("abcd", b"content").unwrap());
+        assert!(!store.write("abcd", b"other").unwrap());
+        assert!(store.has("abcd").unwrap());
+        assert_eq!(store.read("abcd").unwrap(), b"content");
+        assert_eq!(store.stat("abcd").unwrap().get_size(), 7);
+        assert_eq!(store.iterate().unwrap(), vec![String::from("abcd")]);
+
+        assert!(store.remove("abcd").unwrap());
+        assert!(!store.has("abcd").unwrap());
+        assert!(matches!(store.read("abcd"), Err(DitError::ObjectNotFound { .. })));
+    }
+
+    #[test]
+    fn blobs_are_typed_and_hashed_like_git() {
+        let store = MemoryObjectStore::new();
+        let (hash, written) = store.write_blob(ObjectFormat::Sha1, b"one\n").unwrap();
+        assert!(written);
+        assert_eq!(hash, "5626abf0f72e58d7a153368ba57db4c673c0e171");
+        assert_eq!(store.read(&hash).unwrap(), b"blob 4\0one\n");
+        assert_eq!(store.read_blob(&hash).unwrap(), b"one\n");
+        assert!(!store.write_blob(ObjectFormat::Sha1, b"one\n").unwrap().1);
+
+        let (hash, _) = store.write_blob(ObjectFormat::Sha256, b"one\n").unwrap();
+        assert_eq!(hash.len(), 64);
+    }
+
+    #[test]
+    fn trees_are_read_as_they_were_written() {
+        let store = MemoryObjectStore::new();
+        let (mut root, blobs) = sample_tree(&store);
+        let hash = store.write_tree(ObjectFormat::Sha1, &mut root).unwrap();
+        assert_eq!(root.get_id(), hash);
+
+        let tree = store.read_tree(&hash).unwrap();
+        assert_eq!(tree.get_id(), hash);
+        let mut read_blobs: Vec<(PathBuf, String)> = Vec::new();
+        collect(&Node::TreeNode(tree), &mut read_blobs);
+        read_blobs.sort();
+        let mut expected = blobs.clone();
+        expected.sort();
+        assert_eq!(read_blobs, expected);
+
+        // The same entries always give the same tree, whatever their order
+        let reversed_blobs: Vec<(PathBuf, String)> = blobs.into_iter().rev().collect();
+        let mut reversed = create_tree(Tree::default(), &reversed_blobs);
+        assert_eq!(store.write_tree(ObjectFormat::Sha1, &mut reversed).unwrap(), hash);
+    }
+
+    #[test]
+    fn empty_tree_has_the_id_of_git() {
+        let store = MemoryObjectStore::new();
+        let hash = store.write_tree(ObjectFormat::Sha1, &mut Node::TreeNode(Tree::default())).unwrap();
+        assert_eq!(hash, "4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+    }
+
+    #[test]
+    fn reading_an_object_of_another_kind_fails() {
+        let store = MemoryObjectStore::new();
+        let (hash, _) = store.write_blob(ObjectFormat::Sha1, b"one\n").unwrap();
+        assert!(matches!(store.read_tree(&hash), Err(DitError::CorruptObject { .. })));
+
+        store.write("0123", b"no header").unwrap();
+        assert!(matches!(store.read_blob("0123"), Err(DitError::CorruptObject { .. })));
+    }
+
+    #[test]
+    fn loose_store_keeps_objects_in_files() {
+        let dir = TempDir::new("dit-store").unwrap();
+        create_object_dirs(dir.path()).unwrap();
+        let store = LooseObjectStore::new(dir.path().to_path_buf());
+        let (mut root, _) = sample_tree(&store);
+        let hash = store.write_tree(ObjectFormat::Sha1, &mut root).unwrap();
+
+        let reopened = LooseObjectStore::new(dir.path().to_path_buf());
+        assert_eq!(reopened.read_tree(&hash).unwrap(), store.read_tree(&hash).unwrap());
+        assert_eq!(reopened.iterate().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn packed_store_serves_objects_after_repack() {
+        let dir = TempDir::new("dit-store").unwrap();
+        create_object_dirs(dir.path()).unwrap();
+        let store = PackedObjectStore::new(dir.path().to_path_buf());
+        let (mut root, blobs) = sample_tree(&store);
+        let hash = store.write_tree(ObjectFormat::Sha1, &mut root).unwrap();
+        let tree = store.read_tree(&hash).unwrap();
+
+        store.repack(&store.iterate().unwrap()).unwrap();
+        assert!(store.loose.iterate().unwrap().is_empty());
+        assert!(store.stat(&hash).unwrap().is_packed());
+        assert_eq!(store.read_tree(&hash).unwrap(), tree);
+        assert_eq!(store.read_blob(&blobs[1].1).unwrap(), b"two\n");
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs;
+
+use crate::error::DitError;
+use crate::objects::branch::Branch;
+use crate::objects::config::Config;
+use crate::objects::lock::{is_lock_file, LockFile};
+use crate::objects::object::{Object, ObjectKind};
+use crate::objects::reflog::now;
+use crate::objects::signature::{decode_signature, encode_signature, sign};
+use crate::reporter::Event;
+use crate::repository::Repository;
+use crate::utils::read_dit_file;
+
+/// Name given to a commit, stored in `.dit/tags/<name>`.
+///
+/// A lightweight tag stores the hash of the commit. A signed tag stores the hash of a tag
+/// object, `object <commit>\ntag <name>\ntagger <identity> <timestamp>\n` followed by the
+/// signature as `sig ` lines.
+pub struct Tag {
+    name: String,
+    target: String,
+    /// Hash of the tag object, None for a lightweight tag
+    object: Option<String>,
+    tagger: String,
+    signature: String,
+}
+
+impl Tag {
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    /// Hash of the tagged commit
+    pub fn get_target(&self) -> &String {
+        &self.target
+    }
+
+    pub fn get_object(&self) -> Option<&String> {
+        self.object.as_ref()
+    }
+
+    pub fn get_signature(&self) -> &String {
+        &self.signature
+    }
+
+    /// Return the content of the tag object without its signature, which is what is signed
+    pub fn get_payload(&self) -> Vec<u8> {
+        format!("object {}\ntag {}\ntagger {}\n", self.target, self.name, self.tagger).into_bytes()
+    }
+
+    pub fn new_tag(repo: &Repository, name: String, target: String) -> Result<Tag, DitError> {
+        Self::write_tag_file(repo, &name, &target)?;
+        Ok(Tag { name, target, object: None, tagger: String::new(), signature: String::new() })
+    }
+
+    /// Create a tag object signed with the signing key of `repo` and name it `name`
+    pub fn new_signed_tag(repo: &Repository, name: String, target: String) -> Result<Tag, DitError> {
+        if !Branch::is_name_ok(&name) {
+            return Err(DitError::InvalidRefName(name));
+        }
+        if Self::exist(repo, &name) {
+            return Err(DitError::TagExists(name));
+        }
+        let tagger = format!("{} {}", Config::load(repo)?.get_identity(), now());
+        let mut tag = Tag { name, target, object: None, tagger, signature: String::new() };
+        tag.signature = sign(repo, &tag.get_payload())?;
+
+        let content = String::from_utf8_lossy(&tag.get_payload()).to_string() + &encode_signature(&tag.signature);
+        let hash = repo.get_object_format().hash(&content);
+        if Object::new(ObjectKind::Tag, content.into_bytes()).write(repo, &hash)? {
+            repo.report(Event::ObjectWritten(hash.clone()));
+        }
+        Self::write_tag_file(repo, &tag.name, &hash)?;
+        tag.object = Some(hash);
+        Ok(tag)
+    }
+
+    fn write_tag_file(repo: &Repository, name: &String, value: &str) -> Result<(), DitError> {
+        if !Branch::is_name_ok(name) {
+            return Err(DitError::InvalidRefName(name.clone()));
+        }
+        let lock = LockFile::acquire(&repo.get_tags_path().join(name))?;
+        if Self::exist(repo, name) {
+            return Err(DitError::TagExists(name.clone()));
+        }
+        lock.write(repo, value.as_bytes())?;
+        lock.commit()
+    }
+
+    pub fn exist(repo: &Repository, name: &String) -> bool {
+        repo.get_tags_path().join(name).is_file()
+    }
+
+    pub fn get_tag(repo: &Repository, name: &String) -> Result<Tag, DitError> {
+        if !Self::exist(repo, name) {
+            return Err(DitError::UnknownRevision(name.clone()));
+        }
+        let target = read_dit_file(repo, &repo.get_tags_path().join(name))?;
+        let target = String::from_utf8_lossy(&target).trim().to_string();
+
+        match Object::read(repo, &target) {
+            Ok(object) if object.get_kind() == ObjectKind::Tag => Self::parse(&target, object.get_content()),
+            _ => Ok(Tag { name: name.clone(), target, object: None, tagger: String::new(), signature: String::new() }),
+        }
+    }
+
+    /// Parse the content of the tag object `hash`
+    fn parse(hash: &str, content: &[u8]) -> Result<Tag, DitError> {
+        let corrupt = |reason: &str| DitError::CorruptObject { hash: hash.to_string(), reason: reason.to_string() };
+        let content = std::str::from_utf8(content).map_err(|_| corrupt("content is not valid UTF-8"))?;
+        let mut lines = content.splitn(4, '\n');
+        let mut header = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .map(str::to_string)
+                .ok_or_else(|| corrupt(&format!("missing {}header", name)))
+        };
+        let target = header("object ")?;
+        let name = header("tag ")?;
+        let tagger = header("tagger ")?;
+        let (signature, _) = decode_signature(lines.next().unwrap_or_default());
+
+        Ok(Tag { name, target, object: Some(hash.to_string()), tagger, signature })
+    }
+
+    /// Return every tag sorted by name
+    pub fn get_tags(repo: &Repository) -> Result<Vec<Tag>, DitError> {
+        let tags_path = repo.get_tags_path();
+        if !tags_path.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut tags: Vec<Tag> = Vec::new();
+        for entry in fs::read_dir(tags_path).map_err(DitError::IoError)? {
+            let entry = entry.map_err(DitError::IoError)?;
+            if is_lock_file(&entry.path()) {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            tags.push(Self::get_tag(repo, &name)?);
+        }
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+}
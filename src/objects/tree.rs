@@ -0,0 +1,196 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::DitError;
+use crate::objects::object::ObjectKind;
+
+/// Entry of a tree, a directory or a file
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    TreeNode(Tree),
+    BlobNode(Blob),
+}
+
+impl Node {
+    pub fn get_id(&self) -> String {
+        match self {
+            Node::TreeNode(tree) => tree.id.clone(),
+            Node::BlobNode(blob) => blob.id.clone(),
+        }
+    }
+
+    pub fn get_name(&self) -> String {
+        match self {
+            Node::TreeNode(tree) => tree.name.clone(),
+            Node::BlobNode(blob) => blob.name.clone(),
+        }
+    }
+
+    /// Path of the entry relative to the repository root
+    pub fn get_path(&self) -> PathBuf {
+        match self {
+            Node::TreeNode(tree) => tree.path.clone(),
+            Node::BlobNode(blob) => blob.path.clone(),
+        }
+    }
+
+    pub fn get_kind(&self) -> ObjectKind {
+        match self {
+            Node::TreeNode(_) => ObjectKind::Tree,
+            Node::BlobNode(_) => ObjectKind::Blob,
+        }
+    }
+
+    pub fn is_same_type(a: &Node, b: &Node) -> bool {
+        a.get_kind() == b.get_kind()
+    }
+}
+
+/// Directory of a commit, the root tree having an empty name and path.
+///
+/// The id of a tree is only known once it is written, see `ObjectStore::write_tree`, the trees
+/// changed since they were read or written have an empty id.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tree {
+    id: String,
+    name: String,
+    path: PathBuf,
+    children: Vec<Node>,
+}
+
+impl Tree {
+    pub fn new(id: String, path: PathBuf) -> Tree {
+        Tree {
+            id,
+            name: get_file_name(&path),
+            path,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn get_id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+
+    pub fn get_children(&self) -> &Vec<Node> {
+        &self.children
+    }
+
+    /// Return the children, whose changes make the id of the tree unknown
+    pub fn get_mut_children(&mut self) -> &mut Vec<Node> {
+        self.id.clear();
+        &mut self.children
+    }
+
+    pub fn set_children(&mut self, children: Vec<Node>) {
+        self.id.clear();
+        self.children = children;
+    }
+}
+
+/// File of a tree, whose content is the blob `id` of the object store
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blob {
+    id: String,
+    name: String,
+    path: PathBuf,
+}
+
+impl Blob {
+    pub fn new(id: String, path: PathBuf) -> Blob {
+        Blob {
+            id,
+            name: get_file_name(&path),
+            path,
+        }
+    }
+}
+
+/// Serialize the children of `tree`, sorted by name, as `<kind> <id> <name>\0` entries. The ids
+/// of the children must be known.
+pub fn encode_tree(tree: &Tree) -> Vec<u8> {
+    let mut children: Vec<&Node> = tree.children.iter().collect();
+    children.sort_by_key(|child| child.get_name());
+
+    let mut content: Vec<u8> = Vec::new();
+    for child in children {
+        content.extend_from_slice(format!("{} {} {}\0", child.get_kind(), child.get_id(), child.get_name()).as_bytes());
+    }
+    content
+}
+
+/// Parse the content of the tree object `hash` whose entries are in the directory `path`. The
+/// subtrees are returned without their children.
+pub fn decode_tree(hash: &str, content: &[u8], path: &Path) -> Result<Vec<Node>, DitError> {
+    let corrupt = |reason: String| DitError::CorruptObject { hash: hash.to_string(), reason };
+
+    let mut children: Vec<Node> = Vec::new();
+    for entry in content.split(|byte| *byte == 0).filter(|entry| !entry.is_empty()) {
+        let entry = std::str::from_utf8(entry).map_err(|_| corrupt(String::from("tree entry is not valid UTF-8")))?;
+        let mut fields = entry.splitn(3, ' ');
+        let (Some(kind), Some(id), Some(name)) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(corrupt(format!("malformed tree entry '{}'", entry)));
+        };
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(corrupt(format!("invalid file name '{}'", name)));
+        }
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(corrupt(format!("invalid id '{}' for {}", id, name)));
+        }
+        let child_path = path.join(name);
+        match ObjectKind::from_name(kind) {
+            Some(ObjectKind::Tree) => children.push(Node::TreeNode(Tree::new(id.to_string(), child_path))),
+            Some(ObjectKind::Blob) => children.push(Node::BlobNode(Blob::new(id.to_string(), child_path))),
+            _ => return Err(corrupt(format!("unexpected entry type '{}'", kind))),
+        }
+    }
+    if !content.is_empty() && content.last() != Some(&0) {
+        return Err(corrupt(String::from("truncated tree entry")));
+    }
+    Ok(children)
+}
+
+/// Add the files `blobs`, given by their path relative to the repository root and their hash,
+/// to `root`, replacing the entries already at their paths
+pub fn create_tree(mut root: Tree, blobs: &[(PathBuf, String)]) -> Node {
+    for (path, hash) in blobs {
+        let components: Vec<String> = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect();
+        insert_blob(&mut root, &components, Blob::new(hash.clone(), path.clone()));
+    }
+    Node::TreeNode(root)
+}
+
+fn insert_blob(tree: &mut Tree, components: &[String], blob: Blob) {
+    let Some((name, rest)) = components.split_first() else {
+        return;
+    };
+    let children = tree.get_mut_children();
+    if rest.is_empty() {
+        children.retain(|child| child.get_name() != *name);
+        children.push(Node::BlobNode(blob));
+        return;
+    }
+
+    let index = match children.iter().position(|child| child.get_name() == *name && matches!(child, Node::TreeNode(_))) {
+        Some(index) => index,
+        None => {
+            children.retain(|child| child.get_name() != *name);
+            let path = blob.path.ancestors().nth(rest.len()).unwrap_or(Path::new("")).to_path_buf();
+            children.push(Node::TreeNode(Tree::new(String::new(), path)));
+            children.len() - 1
+        }
+    };
+    if let Node::TreeNode(subtree) = &mut children[index] {
+        insert_blob(subtree, rest, blob);
+    }
+}
+
+fn get_file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::error::DitError;
+use crate::reporter::Event;
+use crate::repository::Repository;
+
+/// Largest number of threads hashing or writing files
+pub const MAX_WORKERS: usize = 8;
+
+/// Number of threads of `parallel_map`: the available parallelism, at most `MAX_WORKERS`
+pub fn get_worker_count() -> usize {
+    thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(MAX_WORKERS)
+}
+
+/// Apply `f` to every item on a bounded pool of threads and return the results in the order
+/// of `items`. The workers stop taking items after the first error, which is returned.
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Result<Vec<R>, DitError>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> Result<R, DitError> + Sync,
+{
+    let workers = get_worker_count().min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+    let error: Mutex<Option<DitError>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while !failed.load(Ordering::Relaxed) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    match f(item) {
+                        Ok(result) => {
+                            if let Ok(mut results) = results.lock() {
+                                results[i] = Some(result);
+                            }
+                        }
+                        Err(e) => {
+                            failed.store(true, Ordering::Relaxed);
+                            if let Ok(mut error) = error.lock() {
+                                error.get_or_insert(e);
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(e) = error.into_inner().ok().flatten() {
+        return Err(e);
+    }
+    results
+        .into_inner()
+        .map_err(|_| DitError::UnexpectedComportement(String::from("A worker thread panicked")))?
+        .into_iter()
+        .map(|result| result.ok_or_else(|| DitError::UnexpectedComportement(String::from("A worker thread panicked"))))
+        .collect()
+}
+
+/// Progress of a step done by several threads, reported to the repository as `Progress`
+/// events at most once per percent
+pub struct Progress<'a> {
+    repo: &'a Repository,
+    step: String,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(repo: &'a Repository, step: &str, total: usize) -> Progress<'a> {
+        Progress {
+            repo,
+            step: step.to_string(),
+            total,
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Record that `count` more items are done
+    pub fn add(&self, count: usize) {
+        let before = self.done.fetch_add(count, Ordering::Relaxed);
+        let after = (before + count).min(self.total);
+        if self.total > 0 && (after == self.total || before * 100 / self.total != after * 100 / self.total) {
+            self.repo.report(Event::Progress {
+                step: self.step.clone(),
+                done: after,
+                total: self.total,
+            });
+        }
+    }
+}
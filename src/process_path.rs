@@ -2,23 +2,36 @@ use std::path::PathBuf;
 use std::fs;
 use std::io;
 
+/// Return the files of `dir` and of its subdirectories, or `dir` itself when it is not a
+/// directory. Symlinks are returned without being followed, and empty directories are
+/// returned as entries since dit records them.
 pub fn get_all_files_in_directory(dir: &PathBuf) -> Result<Vec<PathBuf>, io::Error> {
-    if !dir.is_dir() {
+    if !is_real_dir(dir) {
         return Ok(vec![dir.clone()]);
     } 
     
     let mut files: Vec<PathBuf> = Vec::new();
+    let mut is_empty = true;
     
     for entry in fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
+        is_empty = false;
 
-        if path.is_dir() {
+        if is_real_dir(&path) {
             files.extend(get_all_files_in_directory(&path)?)
-        } else if path.is_file() {
+        } else if path.is_symlink() || path.is_file() {
             files.push(path);
         }
     }
 
+    if is_empty {
+        files.push(dir.clone());
+    }
     return Ok(files);
 }
+
+/// Whether `path` is a directory and not a symlink to one
+fn is_real_dir(path: &PathBuf) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+}
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::features::display_message::Color;
+
+/// Something the library has to tell its user
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A step of a long operation, such as the creation of the objects directory, started
+    StepStarted(String),
+    /// A step previously started is done
+    StepFinished(String),
+    /// An object was stored in the objects directory
+    ObjectWritten(String),
+    /// An unreachable object was deleted from the objects directory
+    ObjectRemoved(String),
+    /// A file of the working directory was written from the objects directory
+    FileCheckedOut(PathBuf),
+    /// `done` of the `total` items of a long step, such as hashing the files to add, are done
+    Progress { step: String, done: usize, total: usize },
+    /// Feedback on what a command did, such as the creation of a branch
+    Message(String, Color),
+    /// Something went wrong without making the command fail
+    Warning(String),
+    /// Result of a command, such as a status or a log line
+    Output(String, Color),
+}
+
+/// Receiver of the events emitted by a `Repository`
+pub trait Reporter: Send + Sync {
+    fn report(&self, event: &Event);
+}
+
+/// Reporter dropping every event, used by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {
+    fn report(&self, _event: &Event) {}
+}
+
+/// Reporter keeping every event in memory, for tests and embedders
+#[derive(Debug, Default)]
+pub struct CollectingReporter {
+    events: Mutex<Vec<Event>>,
+}
+
+impl CollectingReporter {
+    pub fn new() -> CollectingReporter {
+        CollectingReporter::default()
+    }
+
+    /// Return the events received so far, oldest first
+    pub fn get_events(&self) -> Vec<Event> {
+        self.events.lock().map(|events| events.clone()).unwrap_or_default()
+    }
+
+    /// Return the text of the `Output` events received so far
+    pub fn get_output(&self) -> Vec<String> {
+        self.get_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Output(line, _) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Reporter for CollectingReporter {
+    fn report(&self, event: &Event) {
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event.clone());
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collecting_reporter_keeps_events_in_order() {
+        let reporter = CollectingReporter::new();
+        reporter.report(&Event::StepStarted(String::from("hash")));
+        reporter.report(&Event::Output(String::from("line"), Color::DEFAULT));
+        reporter.report(&Event::Warning(String::from("careful")));
+
+        assert_eq!(reporter.get_events().len(), 3);
+        assert_eq!(reporter.get_events()[0], Event::StepStarted(String::from("hash")));
+        assert_eq!(reporter.get_output(), vec![String::from("line")]);
+    }
+}
@@ -0,0 +1,574 @@
+use std::{env, fmt, fs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::error::DitError;
+use crate::features::{add, checkout, commit, create_branch, merge, message, mv, restore, revert, rm, tag, verify};
+use crate::features::init::init_repository;
+use crate::features::upgrade::check_layout_version;
+use crate::objects::config::Config;
+use crate::objects::crypt::{Cipher, KeySource};
+use crate::objects::hash::ObjectFormat;
+use crate::objects::lock::recover_transaction;
+use crate::objects::store::{EncryptedObjectStore, ObjectStore, PackedObjectStore};
+use crate::reporter::{Event, Reporter, SilentReporter};
+use crate::utils::normalize_path;
+
+pub const DIT_DIR: &str = ".dit";
+
+/// Handle on a dit repository, owning the paths of its working directory and of its
+/// `.dit` directory. Every operation of the library goes through it, reads and writes
+/// objects through its object store, loose files and packs in `.dit/objects` by default, and reports
+/// what it does to the reporter of the repository, silent by default. In an encrypted repository,
+/// the objects and the files of `.dit` but the config are encrypted with its cipher.
+#[derive(Clone)]
+pub struct Repository {
+    work_dir: PathBuf,
+    dit_path: PathBuf,
+    store: Arc<dyn ObjectStore>,
+    reporter: Arc<dyn Reporter>,
+    object_format: ObjectFormat,
+    cipher: Option<Arc<Cipher>>,
+}
+
+impl Repository {
+    /// Open the repository whose working directory is `path`, failing with `OutdatedRepository`
+    /// when its layout must first be migrated by `dit upgrade`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Repository, DitError> {
+        let repository = Self::open_unchecked(path)?;
+        check_layout_version(&repository)?;
+        Ok(repository)
+    }
+
+    /// Open the repository whose working directory is `path` whatever its layout version,
+    /// completing the update of its files interrupted by a crash if any
+    pub fn open_unchecked<P: AsRef<Path>>(path: P) -> Result<Repository, DitError> {
+        let work_dir = absolute_path(path.as_ref())?;
+        let dit_path = work_dir.join(DIT_DIR);
+        if !dit_path.is_dir() {
+            return Err(DitError::NotInitialized);
+        }
+        let mut repository = Repository {
+            store: Arc::new(PackedObjectStore::new(dit_path.join("objects"))),
+            work_dir,
+            dit_path,
+            reporter: Arc::new(SilentReporter),
+            object_format: ObjectFormat::default(),
+            cipher: None,
+        };
+        recover_transaction(&repository)?;
+        let config = Config::load(&repository)?;
+        repository.object_format = config.get_object_format()?;
+        match Cipher::load(&config)? {
+            Some(cipher) => Ok(repository.with_cipher(Arc::new(cipher))),
+            None => Ok(repository),
+        }
+    }
+
+    /// Open the repository containing `path`, looking for a `.dit` directory in `path`
+    /// and then in its parents
+    pub fn discover<P: AsRef<Path>>(path: P) -> Result<Repository, DitError> {
+        let repository = Self::discover_unchecked(path)?;
+        check_layout_version(&repository)?;
+        Ok(repository)
+    }
+
+    /// Look for the repository containing `path` like `discover`, whatever its layout version
+    pub fn discover_unchecked<P: AsRef<Path>>(path: P) -> Result<Repository, DitError> {
+        let mut current_path = absolute_path(path.as_ref())?;
+
+        loop {
+            if current_path.join(DIT_DIR).is_dir() {
+                return Self::open_unchecked(current_path);
+            }
+            current_path = match current_path.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => return Err(DitError::NotInitialized),
+            };
+        }
+    }
+
+    /// Create a repository in `path`, replacing any existing one
+    pub fn init<P: AsRef<Path>>(path: P) -> Result<Repository, DitError> {
+        Self::init_with_reporter(path, Arc::new(SilentReporter))
+    }
+
+    /// Create a repository in `path` like `init`, reporting the creation to `reporter`
+    pub fn init_with_reporter<P: AsRef<Path>>(path: P, reporter: Arc<dyn Reporter>) -> Result<Repository, DitError> {
+        Self::init_with_format(path, ObjectFormat::default(), reporter)
+    }
+
+    /// Create a repository in `path` whose object ids are computed with `object_format`
+    pub fn init_with_format<P: AsRef<Path>>(path: P, object_format: ObjectFormat, reporter: Arc<dyn Reporter>) -> Result<Repository, DitError> {
+        Self::create(path.as_ref(), object_format, None, reporter)
+    }
+
+    /// Create a repository in `path` like `init_with_format`, whose objects and files are
+    /// encrypted with a new key protected by `source`
+    pub fn init_encrypted<P: AsRef<Path>>(path: P, object_format: ObjectFormat, source: &KeySource, reporter: Arc<dyn Reporter>) -> Result<Repository, DitError> {
+        Self::create(path.as_ref(), object_format, Some(source), reporter)
+    }
+
+    fn create(path: &Path, object_format: ObjectFormat, encryption: Option<&KeySource>, reporter: Arc<dyn Reporter>) -> Result<Repository, DitError> {
+        let work_dir = absolute_path(path)?;
+        fs::create_dir_all(&work_dir).map_err(DitError::IoError)?;
+
+        let dit_path = work_dir.join(DIT_DIR);
+        let mut repository = Repository {
+            store: Arc::new(PackedObjectStore::new(dit_path.join("objects"))),
+            dit_path,
+            work_dir,
+            reporter,
+            object_format,
+            cipher: None,
+        };
+        if encryption.is_some() {
+            repository = repository.with_cipher(Arc::new(Cipher::generate()?));
+        }
+
+        if let Err(e) = init_repository(&repository, encryption) {
+            if repository.dit_path.is_dir() {
+                let _ = fs::remove_dir_all(&repository.dit_path);
+            }
+            return Err(e);
+        }
+        Ok(repository)
+    }
+
+    /// Replace the reporter receiving the events of the repository
+    pub fn with_reporter(mut self, reporter: Arc<dyn Reporter>) -> Repository {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Replace the store holding the objects of the repository
+    pub fn with_store(mut self, store: Arc<dyn ObjectStore>) -> Repository {
+        self.store = store;
+        self
+    }
+
+    /// Encrypt the objects and the files of the repository with `cipher`, replacing its store
+    /// by an `EncryptedObjectStore` over `.dit/objects`
+    pub fn with_cipher(mut self, cipher: Arc<Cipher>) -> Repository {
+        let store = PackedObjectStore::new(self.get_objects_path());
+        self.store = Arc::new(EncryptedObjectStore::new(store, cipher.clone()));
+        self.cipher = Some(cipher);
+        self
+    }
+
+    pub fn get_cipher(&self) -> Option<&Cipher> {
+        self.cipher.as_deref()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Return `content` encrypted with the cipher of the repository, unchanged when it is not
+    /// encrypted
+    pub fn encrypt(&self, content: &[u8]) -> Result<Vec<u8>, DitError> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(content),
+            None => Ok(content.to_vec()),
+        }
+    }
+
+    /// Return the content of the file `path` decrypted from `data`, unchanged when the
+    /// repository is not encrypted
+    pub fn decrypt(&self, path: &Path, data: &[u8]) -> Result<Vec<u8>, DitError> {
+        match &self.cipher {
+            Some(cipher) if !data.is_empty() => cipher.decrypt(data).ok_or_else(|| DitError::DecryptionFailed(path.to_path_buf())),
+            _ => Ok(data.to_vec()),
+        }
+    }
+
+    pub fn get_store(&self) -> &dyn ObjectStore {
+        self.store.as_ref()
+    }
+
+    pub fn get_object_format(&self) -> ObjectFormat {
+        self.object_format
+    }
+
+    /// Id standing for no object in the object format of the repository
+    pub fn get_null_hash(&self) -> String {
+        self.object_format.get_null_hash()
+    }
+
+    pub fn report(&self, event: Event) {
+        self.reporter.report(&event);
+    }
+
+    pub fn get_work_dir(&self) -> &PathBuf {
+        &self.work_dir
+    }
+
+    pub fn get_dit_path(&self) -> &PathBuf {
+        &self.dit_path
+    }
+
+    pub fn get_objects_path(&self) -> PathBuf {
+        self.dit_path.join("objects")
+    }
+
+    pub fn get_refs_path(&self) -> PathBuf {
+        self.dit_path.join("refs")
+    }
+
+    pub fn get_tags_path(&self) -> PathBuf {
+        self.dit_path.join("tags")
+    }
+
+    pub fn get_logs_path(&self) -> PathBuf {
+        self.dit_path.join("logs")
+    }
+
+    pub fn get_info_path(&self) -> PathBuf {
+        self.dit_path.join("info")
+    }
+
+    /// File holding the staged tree before layout 3, read by `dit upgrade`
+    pub fn get_staged_path(&self) -> PathBuf {
+        self.dit_path.join("staged")
+    }
+
+    pub fn get_index_path(&self) -> PathBuf {
+        self.dit_path.join("index")
+    }
+
+    /// Modes of the staged entries, see `Modes`
+    pub fn get_modes_path(&self) -> PathBuf {
+        self.dit_path.join("modes")
+    }
+
+    /// Content store of the large files, see `objects::lfs`
+    pub fn get_lfs_path(&self) -> PathBuf {
+        self.dit_path.join("lfs")
+    }
+
+    pub fn get_deleted_path(&self) -> PathBuf {
+        self.dit_path.join("deleted")
+    }
+
+    pub fn get_commit_path(&self) -> PathBuf {
+        self.dit_path.join("commit")
+    }
+
+    pub fn get_config_path(&self) -> PathBuf {
+        self.dit_path.join("config")
+    }
+
+    pub fn get_format_path(&self) -> PathBuf {
+        self.dit_path.join("format")
+    }
+
+    /// File whose lock holds the journal of the files being replaced by a `Transaction`
+    pub fn get_transaction_path(&self) -> PathBuf {
+        self.dit_path.join("transaction")
+    }
+
+    pub fn add(&self, elements: Vec<&String>) -> Result<(), DitError> {
+        add::add(self, elements)
+    }
+
+    pub fn rm(&self, elements: Vec<&String>, cached: bool) -> Result<(), DitError> {
+        rm::rm(self, elements, cached)
+    }
+
+    pub fn restore_staged(&self, elements: Vec<&String>) -> Result<(), DitError> {
+        restore::restore_staged(self, elements)
+    }
+
+    pub fn mv(&self, source: &String, destination: &String) -> Result<(), DitError> {
+        mv::mv(self, source, destination)
+    }
+
+    /// Commit the staged files with `description`, without opening an editor
+    pub fn commit(&self, description: &str) -> Result<(), DitError> {
+        message::message(self, description.to_string())?;
+        commit::commit(self, true)
+    }
+
+    /// Replace the HEAD commit, keeping its description when `description` is `None`
+    pub fn amend(&self, description: Option<&String>) -> Result<(), DitError> {
+        match description {
+            Some(description) => {
+                message::message(self, description.clone())?;
+                commit::amend(self, true, false)
+            }
+            None => commit::amend(self, true, true),
+        }
+    }
+
+    pub fn new_branch(&self, name: &String) -> Result<(), DitError> {
+        create_branch::new_branch(self, name)
+    }
+
+    /// Tag the commit designated by `revision`, HEAD by default
+    pub fn new_tag(&self, name: &String, revision: Option<&String>) -> Result<(), DitError> {
+        tag::new_tag(self, name, revision, false)
+    }
+
+    /// Tag the commit designated by `revision` with a tag object signed with `user.signingKey`
+    pub fn new_signed_tag(&self, name: &String, revision: Option<&String>) -> Result<(), DitError> {
+        tag::new_tag(self, name, revision, true)
+    }
+
+    /// Check the signature of the commit designated by `revision` against the allowed signers
+    pub fn verify_commit(&self, revision: &str) -> Result<(), DitError> {
+        verify::verify_commit(self, revision)
+    }
+
+    /// Check the signature of the tag `name` against the allowed signers
+    pub fn verify_tag(&self, name: &String) -> Result<(), DitError> {
+        verify::verify_tag(self, name)
+    }
+
+    pub fn checkout(&self, name: &String) -> Result<(), DitError> {
+        checkout::checkout(self, name)
+    }
+
+    pub fn merge(&self, name: &String) -> Result<(), DitError> {
+        merge::merge(self, name)
+    }
+
+    pub fn revert(&self, revision: String) -> Result<(), DitError> {
+        revert::revert(self, revision)
+    }
+}
+
+impl fmt::Debug for Repository {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Repository")
+            .field("work_dir", &self.work_dir)
+            .field("dit_path", &self.dit_path)
+            .field("object_format", &self.object_format)
+            .finish()
+    }
+}
+
+fn absolute_path(path: &Path) -> Result<PathBuf, DitError> {
+    if path.is_absolute() {
+        return Ok(normalize_path(path.to_path_buf()));
+    }
+    let current_dir = env::current_dir().map_err(DitError::IoError)?;
+    Ok(normalize_path(current_dir.join(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::features::key::rotate_key;
+    use crate::features::output::OutputFormat;
+    use crate::features::show::{show, ShowMode};
+    use crate::features::status::{get_head_modes, status};
+    use crate::objects::branch::Branch;
+    use crate::objects::config::LFS_THRESHOLD;
+    use crate::objects::mode::FileMode;
+    use crate::reporter::CollectingReporter;
+    use crate::utils::read_dit_file;
+
+    fn write_and_add(repo: &Repository, path: &str, content: &str) {
+        let path = repo.get_work_dir().join(path);
+        fs::write(&path, content).unwrap();
+        repo.add(vec![&path.display().to_string()]).unwrap();
+    }
+
+    /// Repository whose branch `other` changed `a.txt` and whose current branch changed `b.txt`,
+    /// and `a.txt` when `main_content` differs from its first version
+    fn diverged_repository(dir: &TempDir, other_content: &str, main_content: &str) -> Repository {
+        let repo = Repository::init(dir.path()).unwrap();
+        write_and_add(&repo, "a.txt", "1\n2\n3\n");
+        write_and_add(&repo, "b.txt", "b\n");
+        repo.commit("first").unwrap();
+        repo.new_branch(&String::from("other")).unwrap();
+        repo.checkout(&String::from("other")).unwrap();
+        write_and_add(&repo, "a.txt", other_content);
+        repo.commit("other").unwrap();
+        repo.checkout(&String::from("main")).unwrap();
+        write_and_add(&repo, "a.txt", main_content);
+        write_and_add(&repo, "b.txt", "b on main\n");
+        repo.commit("main").unwrap();
+        repo
+    }
+
+    #[test]
+    fn library_events_go_to_the_reporter() {
+        let dir = TempDir::new("repository").unwrap();
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = Repository::init_with_reporter(dir.path(), reporter.clone()).unwrap();
+        write_and_add(&repo, "a.txt", "one\n");
+        repo.commit("first").unwrap();
+        let written = reporter.get_events().iter().filter(|event| matches!(event, Event::ObjectWritten(_))).count();
+        assert!(written >= 3, "blob, tree and commit written, got {}", written);
+
+        repo.new_branch(&String::from("other")).unwrap();
+        repo.checkout(&String::from("other")).unwrap();
+        assert!(reporter.get_events().contains(&Event::FileCheckedOut(PathBuf::from("a.txt"))));
+        assert!(reporter.get_output().is_empty());
+    }
+
+    #[test]
+    fn show_reports_its_output() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = repo.with_reporter(reporter.clone());
+        write_and_add(&repo, "a.txt", "one\n");
+        repo.commit("first").unwrap();
+
+        show(&repo, "HEAD", ShowMode::NameStatus, None, OutputFormat::Porcelain).unwrap();
+        let output = reporter.get_output();
+        assert_eq!(output[0], "version\t1");
+        assert!(output.contains(&String::from("message\tfirst")));
+        assert!(output.contains(&String::from("change\tA\ta.txt")));
+    }
+
+    #[test]
+    fn status_reports_each_file_once() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        write_and_add(&repo, "a.txt", "one\n");
+        write_and_add(&repo, "b.txt", "b\n");
+        repo.commit("first").unwrap();
+        write_and_add(&repo, "c.txt", "c\n");
+        fs::write(dir.path().join("a.txt"), "two\n").unwrap();
+        fs::write(dir.path().join("d.txt"), "d\n").unwrap();
+
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = repo.with_reporter(reporter.clone());
+        status(&repo, None, OutputFormat::Porcelain).unwrap();
+        let output = reporter.get_output();
+        assert_eq!(&output[2..], ["staged\tA\tc.txt", "unstaged\tM\ta.txt", "untracked\td.txt"]);
+    }
+
+    #[test]
+    fn commit_empties_the_description_of_the_next_commit() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        write_and_add(&repo, "a.txt", "one\n");
+        repo.commit("first").unwrap();
+        assert_eq!(fs::metadata(repo.get_commit_path()).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn key_rotation_encrypts_the_description_of_the_next_commit() {
+        let dir = TempDir::new("repository").unwrap();
+        let source = KeySource::Passphrase(String::from("first"));
+        let repo = Repository::init_encrypted(dir.path(), ObjectFormat::default(), &source, Arc::new(SilentReporter)).unwrap();
+        message::message(&repo, String::from("next commit")).unwrap();
+        let new_source = KeySource::Passphrase(String::from("second"));
+        rotate_key(&repo, &new_source).unwrap();
+
+        // Only the new key decrypts the files once the rotation is complete
+        let cipher = Cipher::unlock(&Config::load(&repo).unwrap(), &new_source).unwrap().get_current();
+        let repo = repo.with_cipher(Arc::new(cipher));
+        assert_eq!(read_dit_file(&repo, &repo.get_commit_path()).unwrap(), b"next commit");
+    }
+
+    #[test]
+    fn large_files_of_an_encrypted_repository_are_reported() {
+        let dir = TempDir::new("repository").unwrap();
+        let source = KeySource::Passphrase(String::from("secret"));
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = Repository::init_encrypted(dir.path(), ObjectFormat::default(), &source, reporter.clone()).unwrap();
+        let mut config = Config::load(&repo).unwrap();
+        config.set(String::from(LFS_THRESHOLD), String::from("4"));
+        config.save(&repo).unwrap();
+        write_and_add(&repo, "large.bin", "large content\n");
+
+        let warned = reporter.get_events().iter().any(|event| matches!(event, Event::Warning(warning) if warning.starts_with("large.bin")));
+        assert!(warned);
+    }
+
+    #[test]
+    fn merge_takes_the_modes_changed_on_one_branch() {
+        let set_mode = |repo: &Repository, path: &str, mode: u32| {
+            let path = repo.get_work_dir().join(path);
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            repo.add(vec![&path.display().to_string()]).unwrap();
+        };
+        let dir = TempDir::new("repository").unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        write_and_add(&repo, "a.sh", "a\n");
+        write_and_add(&repo, "b.sh", "b\n");
+        write_and_add(&repo, "c.txt", "c\n");
+        set_mode(&repo, "a.sh", 0o755);
+        set_mode(&repo, "b.sh", 0o755);
+        repo.commit("first").unwrap();
+        repo.new_branch(&String::from("other")).unwrap();
+        repo.checkout(&String::from("other")).unwrap();
+        set_mode(&repo, "a.sh", 0o644);
+        repo.commit("other").unwrap();
+        repo.checkout(&String::from("main")).unwrap();
+        set_mode(&repo, "b.sh", 0o644);
+        write_and_add(&repo, "c.txt", "c on main\n");
+        repo.commit("main").unwrap();
+        repo.merge(&String::from("other")).unwrap();
+
+        let modes = get_head_modes(&repo).unwrap();
+        assert_eq!(modes.get(Path::new("a.sh")), FileMode::Regular);
+        assert_eq!(modes.get(Path::new("b.sh")), FileMode::Regular);
+    }
+
+    #[test]
+    fn merge_takes_the_files_changed_on_one_branch() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = diverged_repository(&dir, "1\n2\n3\n4\n", "1\n2\n3\n");
+        repo.merge(&String::from("other")).unwrap();
+
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = repo.with_reporter(reporter.clone());
+        show(&repo, "HEAD", ShowMode::Patch, None, OutputFormat::Json).unwrap();
+        let output = reporter.get_output();
+        assert!(output[0].contains("\"merged\""));
+        assert!(output[0].contains("\"combined\":[]"));
+        show(&repo, "HEAD:a.txt", ShowMode::Patch, None, OutputFormat::Human).unwrap();
+        show(&repo, "HEAD:b.txt", ShowMode::Patch, None, OutputFormat::Human).unwrap();
+        assert_eq!(reporter.get_output()[1..], ["1", "2", "3", "4", "b on main"]);
+    }
+
+    #[test]
+    fn merge_reports_files_changed_on_both_branches() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = diverged_repository(&dir, "1\ntwo\n3\n", "1\n2\nthree\n");
+        match repo.merge(&String::from("other")) {
+            Err(DitError::Conflict { paths }) => assert_eq!(paths, vec![PathBuf::from("a.txt")]),
+            result => panic!("expected a conflict, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn merging_a_merged_branch_does_nothing() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = diverged_repository(&dir, "1\n2\n3\n4\n", "1\n2\n3\n");
+        repo.merge(&String::from("other")).unwrap();
+        let head = Branch::get_current_branch(&repo).unwrap().get_head().clone();
+
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = repo.with_reporter(reporter.clone());
+        repo.merge(&String::from("other")).unwrap();
+        assert_eq!(*Branch::get_current_branch(&repo).unwrap().get_head(), head);
+        assert!(reporter.get_events().iter().any(|event| matches!(event, Event::Message(message, _) if message == "Already up to date")));
+    }
+
+    #[test]
+    fn merge_changed_after_merging_shows_a_combined_diff() {
+        let dir = TempDir::new("repository").unwrap();
+        let repo = diverged_repository(&dir, "1\n2\n3\n4\n", "1\n2\n3\n");
+        repo.merge(&String::from("other")).unwrap();
+        write_and_add(&repo, "a.txt", "1\ntwo\n3\n4\n");
+        repo.amend(None).unwrap();
+
+        let reporter = Arc::new(CollectingReporter::new());
+        let repo = repo.with_reporter(reporter.clone());
+        show(&repo, "HEAD", ShowMode::Patch, None, OutputFormat::Human).unwrap();
+        let output = reporter.get_output();
+        let diff = &output[output.iter().position(|line| line == "diff --dit --cc a.txt").unwrap()..];
+        assert_eq!(diff[3..], ["@@@ -1,3 -1,4 +1,4 @@@", "  1", "- 2", " -2", "++two", "  3", "+ 4"].map(String::from));
+    }
+}
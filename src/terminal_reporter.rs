@@ -0,0 +1,58 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+
+use dit::features::display_message::{Color, display_message};
+use dit::reporter::{Event, Reporter};
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+/// Reporter printing events to the terminal: results are always printed, feedback and
+/// warnings unless quiet, steps and written objects or files only when verbose.
+pub struct TerminalReporter {
+    verbosity: Verbosity,
+}
+
+impl TerminalReporter {
+    /// Create the reporter, disabling colors when `NO_COLOR` is set
+    pub fn new(verbosity: Verbosity) -> TerminalReporter {
+        if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            colored::control::set_override(false);
+        }
+        TerminalReporter { verbosity }
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn report(&self, event: &Event) {
+        match event {
+            Event::Output(line, color) => display_message(line, *color),
+            Event::Message(message, color) if self.verbosity >= Verbosity::Normal => display_message(message, *color),
+            Event::Warning(message) if self.verbosity >= Verbosity::Normal => display_message(message, Color::BLUE),
+            Event::Progress { step, done, total } if self.verbosity >= Verbosity::Normal => display_progress(step, *done, *total),
+            _ if self.verbosity < Verbosity::Verbose => (),
+            Event::StepStarted(step) | Event::StepFinished(step) => display_message(step, Color::DEFAULT),
+            Event::ObjectWritten(hash) => display_message(format!("object {} written", hash).as_str(), Color::DEFAULT),
+            Event::ObjectRemoved(hash) => display_message(format!("object {} removed", hash).as_str(), Color::DEFAULT),
+            Event::FileCheckedOut(path) => display_message(format!("checked out {}", path.display()).as_str(), Color::DEFAULT),
+            Event::Message(..) | Event::Warning(_) | Event::Progress { .. } => (),
+        }
+    }
+}
+
+/// Rewrite the progress line on stderr, only when it is a terminal so that redirected output
+/// stays clean
+fn display_progress(step: &str, done: usize, total: usize) {
+    let mut stderr = io::stderr();
+    if !stderr.is_terminal() {
+        return;
+    }
+    let _ = write!(stderr, "\r{}: {}% ({}/{})", step, done * 100 / total.max(1), done, total);
+    if done >= total {
+        let _ = writeln!(stderr);
+    }
+}
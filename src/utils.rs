@@ -1,39 +1,27 @@
-use std::{env, io};
-use std::fs::{File, OpenOptions};
+use std::{env, fs, io, process};
+use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::{Component, Path, PathBuf};
 
 use dit_file_encryptor::CompressedFile;
-use dit_file_encryptor::write_string_file_gz;
 
 use crate::error::DitError;
+use crate::objects::config::Config;
+use crate::objects::index::Index;
+use crate::objects::lfs::clean;
+use crate::objects::object::{Object, ObjectKind};
+use crate::objects::store::load_tree;
+use crate::objects::tree::Node;
+use crate::objects::tree::Node::TreeNode;
+use crate::parallel::{parallel_map, Progress};
 use crate::process_path::get_all_files_in_directory;
+use crate::reporter::Event;
+use crate::repository::Repository;
 
-pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+/// Number of bytes searched for a NUL byte to tell a binary file from a text file
+const BINARY_CHECK_SIZE: usize = 8000;
 
-pub fn relative_path_to_dit() -> Option<PathBuf> {
-    let initial_dir = env::current_dir()
-        .expect("Failed to get current directory");
-
-    let mut current_dir = initial_dir.clone();
-
-    loop {
-        let dit_path = current_dir.join(".dit");
-        if dit_path.exists() && dit_path.is_dir() {
-            return match initial_dir.strip_prefix(&current_dir) {
-                Ok(path) => Some(PathBuf::from(path)),
-                _ => None
-            };
-        }
-
-        match current_dir.parent() {
-            Some(parent) => current_dir = parent.to_path_buf(),
-            None => break,
-        }
-    }
-
-    None
-}
+pub const SCRATCH_DIR_PREFIX: &str = "scratch-";
 
 pub fn normalize_path(path: PathBuf) -> PathBuf {
     let mut components = vec![];
@@ -55,66 +43,79 @@ pub fn normalize_path(path: PathBuf) -> PathBuf {
     components.iter().collect()
 }
 
-/// Return a result with normalize path from .dit to the specify path
-pub fn path_from_dit(path: &PathBuf) -> Result<PathBuf, DitError> {
-    let rel_to_dit = match relative_path_to_dit() {
-        Some(rel_to_dit) => rel_to_dit,
-        None => {
-            return Err(DitError::NotInitialized)
-        }
-    };
-    let path_file = rel_to_dit.join(path);
-    let norm_path = normalize_path(path_file);
-    Ok(norm_path)
+/// Return a result with normalize path from the repository root to the specify path
+pub fn path_from_dit(repo: &Repository, path: &PathBuf) -> Result<PathBuf, DitError> {
+    let absolute_path = resolve_path(repo, path)?;
+    match absolute_path.strip_prefix(repo.get_work_dir()) {
+        Ok(path) => Ok(path.to_path_buf()),
+        Err(_) => Err(DitError::OutsideRepository(path.clone())),
+    }
 }
 
-pub fn read_hash_file(file_path: PathBuf, pos: usize) -> Result<String, DitError> {
-    let mut reader = CompressedFile::new(file_path)
-        .open_for_read()
-        .map_err(|e| DitError::IoError(e))?;
-
-    let mut buf = String::new();
+/// Return the absolute path of `path`. A relative path is taken from the current directory
+/// when it is inside the repository, from the repository root otherwise.
+pub fn resolve_path(repo: &Repository, path: &PathBuf) -> Result<PathBuf, DitError> {
+    if path.is_absolute() {
+        return Ok(normalize_path(path.clone()));
+    }
+    let current_dir = env::current_dir().map_err(DitError::IoError)?;
+    let base = if current_dir.starts_with(repo.get_work_dir()) {
+        current_dir
+    } else {
+        repo.get_work_dir().clone()
+    };
+    Ok(normalize_path(base.join(path)))
+}
 
-    reader.read_to_string(&mut buf)
-        .map_err(|e1| DitError::IoError(e1))?;
+pub fn read_hash_file(repo: &Repository, file_path: PathBuf, pos: usize) -> Result<String, DitError> {
+    let buf = read_dit_file(repo, &file_path)?;
 
-    let infos: Vec<&str> = buf
-        .split_whitespace()
+    let infos: Vec<&[u8]> = buf
+        .split(|byte| byte.is_ascii_whitespace())
+        .filter(|info| !info.is_empty())
         .collect();
 
-    Ok(String::from(infos[pos]))
+    match infos.get(pos).map(|hash| std::str::from_utf8(hash)) {
+        Some(Ok(hash)) => Ok(String::from(hash)),
+        Some(Err(_)) => Err(DitError::UnexpectedComportement(format!("Invalid hash at position {} in {}", pos, file_path.display()))),
+        None => Err(DitError::UnexpectedComportement(format!("No hash at position {} in {}", pos, file_path.display()))),
+    }
 }
 
-pub fn write_hash_file(hash: String, path: PathBuf, pos: u64) -> Result<(), io::Error> {
-    let mut file = OpenOptions::new()
-        .write(true)
-        .read(true)
-        .create(true)
-        .open(path)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-    write_string_file_gz(hash, &mut file, pos)?;
-    Ok(())
+/// Return the content of the compressed file `path` of `.dit`, decrypted when the repository
+/// is encrypted. An empty file has no content.
+pub fn read_dit_file(repo: &Repository, path: &Path) -> Result<Vec<u8>, DitError> {
+    if fs::metadata(path).map_err(DitError::IoError)?.len() == 0 {
+        return Ok(vec![]);
+    }
+    let mut reader = CompressedFile::new(path.to_path_buf())
+        .open_for_read()
+        .map_err(DitError::IoError)?;
+    let mut content: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut content).map_err(DitError::IoError)?;
+    repo.decrypt(path, &content)
 }
 
+/// Return the content of the file `path`, the bytes which are not valid UTF-8 being replaced
 pub fn read_content_from_non_encrypted_file(path: &&Path) -> Result<String, io::Error> {
     let file = File::open(path)?;
     let mut buf_reader = BufReader::new(file);
-    let mut contents = String::new();
-    buf_reader.read_to_string(&mut contents)?;
-    Ok(contents)
+    let mut contents: Vec<u8> = Vec::new();
+    buf_reader.read_to_end(&mut contents)?;
+    Ok(String::from_utf8_lossy(&contents).into_owned())
 }
 
-pub fn clean_path(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, DitError> {
+pub fn clean_path(repo: &Repository, paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, DitError> {
     let mut all_files_path: Vec<PathBuf> = vec![];
 
     for element in paths {
-        let total_files = get_all_files_in_directory(&element).map_err(|e| {
+        let total_files = get_all_files_in_directory(&resolve_path(repo, &element)?).map_err(|e| {
             DitError::UnexpectedComportement(format!("Details: {}", e))
         })?;
         let clean_files: Result<Vec<PathBuf>, DitError> = total_files
             .into_iter()
-            .map(|file| path_from_dit(&file))
+            .filter(|file| !file.starts_with(repo.get_dit_path()))
+            .map(|file| path_from_dit(repo, &file))
             .collect();
 
         let clean_files = clean_files?;
@@ -123,4 +124,195 @@ pub fn clean_path(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>, DitError> {
     }
 
     Ok(all_files_path)
-}
\ No newline at end of file
+}
+
+/// Return the path and hash of every file stored in the tree `tree_hash`
+pub fn get_tree_blobs(repo: &Repository, tree_hash: &str) -> Result<Vec<(PathBuf, String)>, DitError> {
+    let tree = load_tree(repo, tree_hash)?;
+
+    let mut blobs: Vec<(PathBuf, String)> = vec![];
+    collect_blobs(&TreeNode(tree), &mut blobs);
+    Ok(blobs)
+}
+
+pub fn collect_blobs(node: &Node, blobs: &mut Vec<(PathBuf, String)>) {
+    match node {
+        TreeNode(tree) => {
+            for child in tree.get_children() {
+                collect_blobs(child, blobs);
+            }
+        }
+        blob => blobs.push((blob.get_path(), blob.get_id())),
+    }
+}
+
+/// Return the path and hash of files of the working directory, paths are relative to the repository root
+pub fn get_working_blobs(repo: &Repository, paths: Vec<PathBuf>) -> Result<Vec<(PathBuf, String)>, DitError> {
+    hash_working_files(repo, &paths, false)
+}
+
+/// Hash the files `paths` of the working directory on a pool of threads and return their path
+/// and hash. With `write`, their blobs are written to the object store.
+///
+/// The large files, see `Config::is_large_file`, are replaced by their pointers. With `write`,
+/// their content is copied to the content store. An encrypted repository has no large files,
+/// since the content store is not encrypted.
+pub fn hash_working_files(repo: &Repository, paths: &[PathBuf], write: bool) -> Result<Vec<(PathBuf, String)>, DitError> {
+    if paths.is_empty() {
+        return Ok(vec![]);
+    }
+    let config = Config::load(repo)?;
+    let format = repo.get_object_format();
+
+    let progress = Progress::new(repo, "Hashing files", paths.len());
+    parallel_map(paths, |path| {
+        let full_path = repo.get_work_dir().join(path);
+        let size = fs::metadata(&full_path).map_err(DitError::IoError)?.len();
+        let is_large_file = config.is_large_file(path, size)?;
+        let content = if is_large_file && !repo.is_encrypted() {
+            clean(repo, path, write)?.encode().into_bytes()
+        } else {
+            if is_large_file && write {
+                let warning = format!("{} is stored in the objects, large files are not kept apart in an encrypted repository", path.display());
+                repo.report(Event::Warning(warning));
+            }
+            fs::read(&full_path).map_err(DitError::IoError)?
+        };
+
+        let hash = if write {
+            let (hash, written) = repo.get_store().write_blob(format, &content)?;
+            if written {
+                repo.report(Event::ObjectWritten(hash.clone()));
+            }
+            hash
+        } else {
+            Object::new(ObjectKind::Blob, content).get_id(format)
+        };
+        progress.add(1);
+        Ok((path.clone(), hash))
+    })
+}
+
+/// Return the path and hash of files of the working directory like `get_working_blobs`, only
+/// reading the files whose stat data changed since `index` recorded their hash, and record in
+/// `index` the stat data of the files found unchanged
+pub fn get_indexed_working_blobs(repo: &Repository, index: &mut Index, paths: Vec<PathBuf>) -> Result<Vec<(PathBuf, String)>, DitError> {
+    let mut blobs: Vec<(PathBuf, String)> = vec![];
+    let mut changed: Vec<PathBuf> = vec![];
+    for path in paths {
+        match index.get_cached_hash(repo, &path) {
+            Some(hash) => blobs.push((path, hash)),
+            None => changed.push(path),
+        }
+    }
+
+    for (path, hash) in get_working_blobs(repo, changed)? {
+        index.refresh(repo, &path, &hash)?;
+        blobs.push((path, hash));
+    }
+    Ok(blobs)
+}
+
+/// Return every file of the working directory outside of `.dit`, relative to the repository root
+pub fn get_working_files(repo: &Repository) -> Result<Vec<PathBuf>, DitError> {
+    let project_path = repo.get_work_dir();
+
+    let files = get_all_files_in_directory(project_path).map_err(DitError::IoError)?;
+    let files = files
+        .into_iter()
+        .filter(|file| !file.starts_with(repo.get_dit_path()))
+        .filter_map(|file| file.strip_prefix(project_path).ok().map(PathBuf::from))
+        .collect();
+    Ok(files)
+}
+
+/// Return the content of the blob `hash`
+pub fn read_blob(repo: &Repository, hash: &str) -> Result<Vec<u8>, DitError> {
+    repo.get_store().read_blob(hash)
+}
+
+/// Write the files of `root` under `dest`, replacing the files and symlinks at their paths
+pub fn write_tree_files(repo: &Repository, root: &Node, dest: &Path) -> Result<(), DitError> {
+    let mut blobs: Vec<(PathBuf, String)> = vec![];
+    collect_blobs(root, &mut blobs);
+    for (path, hash) in blobs {
+        let file_path = dest.join(&path);
+        if fs::symlink_metadata(&file_path).map(|metadata| metadata.file_type().is_symlink()).unwrap_or(false) {
+            fs::remove_file(&file_path).map_err(DitError::IoError)?;
+        }
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).map_err(DitError::IoError)?;
+        }
+        fs::write(&file_path, read_blob(repo, &hash)?).map_err(DitError::IoError)?;
+    }
+    Ok(())
+}
+
+/// Whether `content` holds a NUL byte in its first `BINARY_CHECK_SIZE` bytes, like the files
+/// which are not text
+pub fn is_binary_content(content: &[u8]) -> bool {
+    content.iter().take(BINARY_CHECK_SIZE).any(|byte| *byte == 0)
+}
+
+/// Whether `path` matches `pattern`, where `*` stands for any characters but `/` and `?` for
+/// one of them. A pattern without `/` is matched against the file name only.
+pub fn matches_pattern(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    let name = match pattern.contains('/') {
+        true => path.as_ref(),
+        false => path.rsplit('/').next().unwrap_or_default(),
+    };
+    matches_wildcard(pattern.as_bytes(), name.as_bytes())
+}
+
+fn matches_wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_wildcard(&pattern[1..], name)
+                || (!name.is_empty() && name[0] != b'/' && matches_wildcard(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(c)) if *c != b'/' => matches_wildcard(&pattern[1..], &name[1..]),
+        (Some(p), Some(c)) if p == c => matches_wildcard(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Directory of `.dit` only readable by its owner, for the decrypted content which must be
+/// written to files, during an upgrade or while the commit description is edited, removed
+/// when dropped
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub fn create(repo: &Repository) -> Result<ScratchDir, DitError> {
+        let path = repo.get_dit_path().join(format!("{}{}", SCRATCH_DIR_PREFIX, process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).map_err(DitError::IoError)?;
+        }
+        create_private_dir(&path)?;
+        Ok(ScratchDir { path })
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> Result<(), DitError> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().mode(0o700).create(path).map_err(DitError::IoError)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> Result<(), DitError> {
+    fs::create_dir(path).map_err(DitError::IoError)
+}